@@ -0,0 +1 @@
+ALTER TABLE package_status ADD COLUMN short_description TEXT;
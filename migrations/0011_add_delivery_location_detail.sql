@@ -0,0 +1 @@
+ALTER TABLE package_status ADD COLUMN delivery_location_detail TEXT;
@@ -0,0 +1 @@
+ALTER TABLE package_status ADD COLUMN courier_status_text TEXT;
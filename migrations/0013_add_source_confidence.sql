@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN source_confidence INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+CREATE TABLE order_placeholders (
+    id INTEGER PRIMARY KEY,
+    merchant TEXT NOT NULL,
+    order_number TEXT NOT NULL,
+    source_email_uid INTEGER NOT NULL,
+    source_email_subject TEXT,
+    created_at TEXT NOT NULL,
+    package_id INTEGER REFERENCES packages(id),
+    linked_at TEXT,
+    UNIQUE (merchant, order_number)
+);
+
+CREATE INDEX idx_order_placeholders_order_number ON order_placeholders(order_number);
@@ -0,0 +1 @@
+ALTER TABLE packages ADD COLUMN delivery_pending INTEGER NOT NULL DEFAULT 0;
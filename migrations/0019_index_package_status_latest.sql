@@ -0,0 +1,2 @@
+-- Speeds up finding each package's latest status row
+CREATE INDEX idx_package_status_package_id ON package_status(package_id, id);
@@ -0,0 +1,6 @@
+-- A courier event is its scan time plus description; the same description at
+-- a different time is a distinct event and must not be dropped
+DROP INDEX idx_package_status_dedup;
+CREATE UNIQUE INDEX idx_package_status_event
+  ON package_status(package_id, checked_at, description)
+  WHERE description IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Deliveries inferred by status.assume_delivered_after_days, which the next
+-- courier result replaces
+ALTER TABLE package_status ADD COLUMN assumed INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,29 @@
+-- Tracking numbers are looked up in canonical form (no whitespace or dashes,
+-- upper case). Rewrite rows stored as entered, merging rows that turn out to
+-- be the same package into the oldest one, preferring one not deleted.
+CREATE TEMP TABLE canonical_numbers AS
+SELECT id, deleted_at IS NOT NULL AS deleted,
+       UPPER(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(
+           tracking_number, ' ', ''), '-', ''), char(9), ''), char(10), ''), char(13), '')) AS number
+FROM packages;
+
+CREATE TEMP TABLE package_merges AS
+SELECT c.id AS old_id,
+       (SELECT k.id FROM canonical_numbers k
+        WHERE k.number = c.number
+        ORDER BY k.deleted, k.id
+        LIMIT 1) AS new_id
+FROM canonical_numbers c;
+DELETE FROM package_merges WHERE old_id = new_id;
+
+UPDATE OR IGNORE package_status
+SET package_id = (SELECT new_id FROM package_merges WHERE old_id = package_id)
+WHERE package_id IN (SELECT old_id FROM package_merges);
+DELETE FROM package_status WHERE package_id IN (SELECT old_id FROM package_merges);
+DELETE FROM packages WHERE id IN (SELECT old_id FROM package_merges);
+
+UPDATE packages
+SET tracking_number = (SELECT number FROM canonical_numbers c WHERE c.id = packages.id);
+
+DROP TABLE canonical_numbers;
+DROP TABLE package_merges;
@@ -0,0 +1,135 @@
+//! QR code scanning for emails that only carry the tracking number inside an
+//! embedded image. Decoding requires the `barcodes` feature; without it no
+//! images are scanned.
+
+use mailparse::{ParsedMail, parse_mail};
+use tracing::debug;
+
+/// Decode QR codes in every `image/*` part of a raw RFC 822 message and return
+/// their text payloads. Images that fail to decode or contain no QR code are
+/// skipped.
+pub fn scan_message(raw: &[u8]) -> Vec<String> {
+    let parsed = match parse_mail(raw) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            debug!(error = %err, "Failed to parse message for barcode scan");
+            return Vec::new();
+        }
+    };
+
+    let mut payloads = Vec::new();
+    collect_payloads(&parsed, &mut payloads);
+    payloads
+}
+
+fn collect_payloads(part: &ParsedMail, payloads: &mut Vec<String>) {
+    if part.ctype.mimetype.to_lowercase().starts_with("image/") {
+        match part.get_body_raw() {
+            Ok(bytes) => payloads.extend(decode_image(&bytes)),
+            Err(err) => debug!(error = %err, mimetype = %part.ctype.mimetype, "Failed to decode image part"),
+        }
+    }
+
+    for subpart in &part.subparts {
+        collect_payloads(subpart, payloads);
+    }
+}
+
+#[cfg(feature = "barcodes")]
+fn decode_image(bytes: &[u8]) -> Vec<String> {
+    let image = match image::load_from_memory(bytes) {
+        Ok(image) => image.to_luma8(),
+        Err(err) => {
+            debug!(error = %err, "Skipping undecodable image");
+            return Vec::new();
+        }
+    };
+
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+    prepared
+        .detect_grids()
+        .into_iter()
+        .filter_map(|grid| match grid.decode() {
+            Ok((_, content)) => Some(content),
+            Err(err) => {
+                debug!(error = %err, "Skipping unreadable QR code");
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "barcodes"))]
+fn decode_image(_bytes: &[u8]) -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(all(test, feature = "barcodes"))]
+mod tests {
+    use super::*;
+    use crate::extractors::extract_tracking_numbers;
+    use base64::Engine;
+    use std::io::Cursor;
+
+    /// PNG of a QR code encoding `data`, 8px per module with a 4-module quiet zone.
+    fn qr_png(data: &str) -> Vec<u8> {
+        let code = qrcode::QrCode::new(data).unwrap();
+        let width = code.width();
+        let colors = code.to_colors();
+        let (scale, quiet) = (8, 4);
+        let size = ((width + 2 * quiet) * scale) as u32;
+
+        let image = image::GrayImage::from_fn(size, size, |x, y| {
+            let (mx, my) = (x as usize / scale, y as usize / scale);
+            let dark = mx >= quiet
+                && my >= quiet
+                && mx < width + quiet
+                && my < width + quiet
+                && colors[(my - quiet) * width + (mx - quiet)] == qrcode::Color::Dark;
+            image::Luma([if dark { 0 } else { 255 }])
+        });
+
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        png
+    }
+
+    fn email_with_image(image: &[u8]) -> String {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(image);
+        let wrapped = encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect::<Vec<_>>()
+            .join("\r\n");
+
+        format!(
+            "Subject: Your order has shipped\r\n\
+             Content-Type: multipart/related; boundary=\"b1\"\r\n\r\n\
+             --b1\r\nContent-Type: text/plain\r\n\r\nScan the code below to track.\r\n\
+             --b1\r\nContent-Type: image/png\r\nContent-Transfer-Encoding: base64\r\n\r\n\
+             {wrapped}\r\n--b1--\r\n"
+        )
+    }
+
+    #[test]
+    fn extracts_tracking_number_from_qr_code() {
+        let email = email_with_image(&qr_png("1Z5R89390357567127"));
+
+        let payloads = scan_message(email.as_bytes());
+
+        assert_eq!(payloads, vec!["1Z5R89390357567127"]);
+        let results = extract_tracking_numbers(&payloads.join("\n"));
+        assert_eq!(results[0].tracking_number, "1Z5R89390357567127");
+    }
+
+    #[test]
+    fn skips_images_without_qr_codes() {
+        let blank = image::GrayImage::from_pixel(64, 64, image::Luma([255]));
+        let mut png = Vec::new();
+        blank.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+        assert!(scan_message(email_with_image(&png).as_bytes()).is_empty());
+        assert!(scan_message(email_with_image(b"not an image").as_bytes()).is_empty());
+    }
+}
@@ -1,10 +1,14 @@
 use figment::{
     Figment,
     providers::{Env, Format, Toml},
+    value::Value,
 };
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{AddrParseError, IpAddr, SocketAddr};
+use tracing::warn;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Config {
     pub email: EmailConfig,
 
@@ -19,9 +23,34 @@ pub struct Config {
 
     #[serde(default)]
     pub web: WebConfig,
+
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    pub webhook: Option<WebhookConfig>,
+
+    pub smtp: Option<SmtpConfig>,
+
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+
+    /// Permit check intervals below [`MIN_CHECK_INTERVAL_SECONDS`], for testing.
+    #[serde(default)]
+    pub allow_short_intervals: bool,
 }
 
-#[derive(Debug, Deserialize)]
+/// Shortest email or status check interval accepted without
+/// `allow_short_intervals`, to avoid getting banned by IMAP servers and couriers.
+pub const MIN_CHECK_INTERVAL_SECONDS: u64 = 60;
+
+/// Narrowest accepted `email.html_wrap_width`; narrower wrapping splits
+/// tracking numbers even in plain paragraphs.
+const MIN_HTML_WRAP_WIDTH: usize = 80;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct EmailConfig {
     #[serde(default = "default_check_interval")]
     pub check_interval_seconds: u64,
@@ -29,47 +58,335 @@ pub struct EmailConfig {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    /// How the IMAP connection is encrypted.
+    #[serde(default)]
+    pub security: ImapSecurity,
+
     #[serde(default = "default_folder")]
     pub folder: String,
 
     pub server: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
+
+    /// How to log in to the IMAP server.
+    #[serde(default)]
+    pub auth: ImapAuth,
+
+    /// OAuth2 credentials for `auth = "xoauth2"`.
+    pub oauth2: Option<OAuth2Config>,
+
+    #[serde(default)]
+    pub courier_overrides: Vec<CourierOverrideConfig>,
+
+    /// Regexes matched against each message's subject and body; a match
+    /// skips the message before extraction. Case-insensitive.
+    #[serde(default = "default_ignore_patterns")]
+    pub ignore_patterns: Vec<String>,
+
+    /// Like `ignore_patterns`, but matched against the subject only. Suits
+    /// marketing phrases that real shipment emails repeat in their footers.
+    #[serde(default = "default_ignore_subject_patterns")]
+    pub ignore_subject_patterns: Vec<String>,
+
+    /// Regexes matched against each message's subject and sender; a match
+    /// without a tracking number records the order as awaiting tracking.
+    /// Case-insensitive. Empty disables order tracking.
+    #[serde(default)]
+    pub order_confirmation_patterns: Vec<String>,
+
+    /// Upper bound on a single poll cycle; remaining messages are picked up next cycle.
+    pub max_poll_duration_seconds: Option<u64>,
+
+    /// On the first poll of a folder, also process mail received in the last
+    /// this many days. Unset starts from the next new message.
+    pub initial_lookback_days: Option<u32>,
+
+    /// Number of messages fetched per IMAP FETCH command.
+    #[serde(default = "default_fetch_batch_size")]
+    pub fetch_batch_size: usize,
+
+    /// Reconnect-and-retry attempts for a batch whose fetch fails mid-way.
+    #[serde(default = "default_fetch_retries")]
+    pub fetch_retries: u32,
+
+    /// Poll immediately at startup rather than waiting for the first interval.
+    #[serde(default = "default_true")]
+    pub poll_on_startup: bool,
+
+    /// Date messages the server returns without an INTERNALDATE by their
+    /// `Date:` header instead of skipping them.
+    #[serde(default = "default_true")]
+    pub date_header_fallback: bool,
+
+    /// Decode QR codes in image attachments; requires the `barcodes` feature.
+    #[serde(default)]
+    pub scan_barcodes: bool,
+
+    /// Read each shipment's service and description from its own row when an
+    /// email lists several shipments.
+    #[serde(default)]
+    pub parse_digests: bool,
+
+    /// Ignore quoted replies and forwarded messages, so numbers from an
+    /// earlier email in the thread aren't picked up again.
+    #[serde(default)]
+    pub strip_quoted: bool,
+
+    /// Line width HTML emails are wrapped to when converted to text. Wide
+    /// enough by default that table cells don't split tracking numbers.
+    #[serde(default = "default_html_wrap_width")]
+    pub html_wrap_width: usize,
+
+    /// What to do when a known tracking number is found with a different courier.
+    #[serde(default)]
+    pub courier_conflict: CourierConflictPolicy,
+
+    /// Store numbers the tracking-numbers crate can't classify, when found
+    /// near a shipping keyword, under the sending courier if it is the only
+    /// courier configured. The courier API then decides whether the number
+    /// is real.
+    #[serde(default)]
+    pub trust_courier_on_no_match: bool,
+
+    /// Wait for new mail with IMAP IDLE between polls instead of sleeping,
+    /// on servers that support it. Still polls at least every interval.
+    #[serde(default)]
+    pub use_idle: bool,
+}
+
+/// Encryption for the IMAP connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImapSecurity {
+    /// TLS from the start, usually on port 993.
+    #[default]
+    Tls,
+    /// A plain connection upgraded with STARTTLS, usually on port 143.
+    Starttls,
+    /// No encryption; the password is sent in the clear.
+    Plaintext,
+}
+
+/// IMAP login mechanism.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImapAuth {
+    /// LOGIN with `username` and `password`.
+    #[default]
+    Password,
+    /// SASL XOAUTH2 with an OAuth2 access token, as Gmail and Microsoft 365
+    /// require.
+    Xoauth2,
+}
+
+/// Either a fixed `access_token`, or a `refresh_token` plus client
+/// credentials to mint a fresh access token from `token_url` on each connect,
+/// but not both.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OAuth2Config {
+    pub access_token: Option<String>,
+    pub refresh_token: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub token_url: Option<String>,
+}
+
+/// How to resolve a tracking number detected as different couriers in different emails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CourierConflictPolicy {
+    /// Replace the stored courier when the new detection is more authoritative.
+    #[default]
+    PreferConfident,
+    /// Keep the first courier and log the conflict for manual resolution.
+    KeepFirst,
+}
+
+/// Forces the courier of any validated tracking number matching `pattern`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CourierOverrideConfig {
+    pub pattern: String,
+    pub courier: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct DatabaseConfig {
     #[serde(default = "default_db_path")]
     pub path: String,
+
+    /// SQLCipher key; requires the `sqlcipher` feature.
+    pub encryption_key: Option<String>,
+
+    /// Externally synced copy of the database used by read-only reporting endpoints.
+    pub replica_path: Option<String>,
 }
 
 impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
             path: default_db_path(),
+            encryption_key: None,
+            replica_path: None,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct StatusPollerConfig {
     #[serde(default = "default_status_check_interval")]
     pub check_interval_seconds: u64,
+
+    /// Only poll couriers during these local hours.
+    pub active_hours: Option<ActiveHoursConfig>,
+
+    /// Poll immediately at startup rather than waiting for the first interval.
+    #[serde(default = "default_true")]
+    pub poll_on_startup: bool,
+
+    /// Mark a package delivered when its last scan was out for delivery (or
+    /// similar) and the courier hasn't updated it for this many days.
+    pub assume_delivered_after_days: Option<u32>,
+
+    /// Only record a delivered scan (notifying and ending polling) once two
+    /// consecutive checks, by poll or web rescan, report it.
+    #[serde(default)]
+    pub confirm_delivered: bool,
+
+    /// Store a short phrase ("Arrived at facility") alongside each verbose
+    /// scan description, shown in the web UI's timeline.
+    #[serde(default)]
+    pub short_descriptions: bool,
+
+    /// Gentler polling while catching up on many never-checked packages.
+    pub backfill: Option<BackfillConfig>,
+
+    /// Check packages concurrently over async HTTP instead of one at a time.
+    pub async_requests: Option<AsyncRequestsConfig>,
+
+    /// Check packages less often while their tracking doesn't change.
+    pub adaptive: Option<AdaptivePollingConfig>,
 }
 
 impl Default for StatusPollerConfig {
     fn default() -> Self {
         Self {
             check_interval_seconds: default_status_check_interval(),
+            active_hours: None,
+            poll_on_startup: true,
+            assume_delivered_after_days: None,
+            confirm_delivered: false,
+            short_descriptions: false,
+            backfill: None,
+            async_requests: None,
+            adaptive: None,
+        }
+    }
+}
+
+/// Backfill mode, used while at least `unscanned_threshold` packages have
+/// never been checked: unchecked packages are checked first, then the rest
+/// soonest ETA first, at most `max_per_cycle` per poll, with `delay_ms`
+/// between courier requests.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BackfillConfig {
+    #[serde(default = "default_backfill_threshold")]
+    pub unscanned_threshold: usize,
+
+    #[serde(default = "default_backfill_max_per_cycle")]
+    pub max_per_cycle: usize,
+
+    #[serde(default = "default_backfill_delay_ms")]
+    pub delay_ms: u64,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        Self {
+            unscanned_threshold: default_backfill_threshold(),
+            max_per_cycle: default_backfill_max_per_cycle(),
+            delay_ms: default_backfill_delay_ms(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Async courier polling: each poll sends up to `max_concurrent` courier
+/// requests at once.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AsyncRequestsConfig {
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent: usize,
+}
+
+impl Default for AsyncRequestsConfig {
+    fn default() -> Self {
+        Self { max_concurrent: default_max_concurrent_requests() }
+    }
+}
+
+/// Adaptive polling: a package is re-checked after `base_interval_seconds`,
+/// doubling each time a check finds nothing new, up to `max_interval_seconds`.
+/// Any change resets it to the base interval.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AdaptivePollingConfig {
+    #[serde(default = "default_status_check_interval")]
+    pub base_interval_seconds: u64,
+
+    #[serde(default = "default_adaptive_max_interval")]
+    pub max_interval_seconds: u64,
+}
+
+impl Default for AdaptivePollingConfig {
+    fn default() -> Self {
+        Self {
+            base_interval_seconds: default_status_check_interval(),
+            max_interval_seconds: default_adaptive_max_interval(),
+        }
+    }
+}
+
+/// A daily local-time polling window from `start` (inclusive) to `end`
+/// (exclusive), in whole hours. Windows may wrap past midnight (e.g. 22 → 6).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ActiveHoursConfig {
+    pub start: u32,
+    pub end: u32,
+
+    /// Keep polling packages whose ETA is today even outside the window.
+    #[serde(default)]
+    pub exempt_arriving_today: bool,
+}
+
+impl ActiveHoursConfig {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct CourierConfig {
     pub fedex: Option<FedexConfig>,
     pub ups: Option<UpsConfig>,
     pub usps: Option<UspsConfig>,
+    pub canada_post: Option<CanadaPostConfig>,
+
+    /// Which client tracks UPS packages.
+    #[serde(default)]
+    pub ups_client: UpsClientKind,
+
+    /// Per courier code, the most requests to send it per minute. Requests
+    /// beyond that wait for their turn. Couriers not listed aren't limited.
+    #[serde(default)]
+    pub requests_per_minute: BTreeMap<String, u32>,
+
+    /// After a courier rate-limits a request, skip all requests to it for
+    /// this long, unless the response's `Retry-After` says otherwise.
+    #[serde(default = "default_rate_limit_cooldown_seconds")]
+    pub rate_limit_cooldown_seconds: u64,
 }
 
 impl Default for CourierConfig {
@@ -78,35 +395,131 @@ impl Default for CourierConfig {
             fedex: None,
             ups: None,
             usps: None,
+            canada_post: None,
+            ups_client: UpsClientKind::default(),
+            requests_per_minute: BTreeMap::new(),
+            rate_limit_cooldown_seconds: default_rate_limit_cooldown_seconds(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Which client tracks UPS packages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpsClientKind {
+    /// The API when `[courier.ups]` credentials are set, otherwise the web client.
+    #[default]
+    Auto,
+    /// The UPS Tracking API; requires `[courier.ups]`.
+    Api,
+    /// The credential-free web client, even when API credentials are set.
+    Web,
+}
+
+/// The FedEx API environment a project's credentials were issued for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FedexEnvironment {
+    #[default]
+    Production,
+    Sandbox,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct FedexConfig {
     pub client_id: String,
     pub client_secret: String,
+
+    /// Which FedEx API to call. Sandbox credentials only work against the
+    /// sandbox, which returns canned test data rather than real shipments.
+    #[serde(default)]
+    pub environment: FedexEnvironment,
+
+    #[serde(flatten)]
+    pub retry: RetryConfig,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct UpsConfig {
     pub client_id: String,
     pub client_secret: String,
+
+    #[serde(flatten)]
+    pub retry: RetryConfig,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct UspsConfig {
     pub client_id: String,
     pub client_secret: String,
+
+    /// Describe "Pre-Shipment" (label created) and "Accepted" scans
+    /// explicitly so the two stages read differently in the history.
+    #[serde(default)]
+    pub separate_pre_shipment: bool,
+
+    #[serde(flatten)]
+    pub retry: RetryConfig,
+}
+
+/// Canada Post API key, used as HTTP Basic credentials.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CanadaPostConfig {
+    pub username: String,
+    pub password: String,
+
+    #[serde(flatten)]
+    pub retry: RetryConfig,
 }
 
-#[derive(Debug, Deserialize)]
+/// Retries for courier API requests that fail transiently (timeouts, 429,
+/// 5xx), with exponential backoff.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct RetryConfig {
+    #[serde(default = "default_courier_max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first retry; each later retry waits twice as long.
+    #[serde(default = "default_courier_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: default_courier_max_retries(),
+            retry_base_delay_ms: default_courier_retry_base_delay_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct WebConfig {
     #[serde(default)]
     pub enabled: bool,
 
     #[serde(default = "default_web_port")]
     pub port: u16,
+
+    /// IP address to listen on. Defaults to loopback; use `0.0.0.0` to accept
+    /// connections from other machines.
+    #[serde(default = "default_web_bind_address")]
+    pub bind_address: String,
+
+    /// Maximum requests handled at once; extra requests get 503 Service Unavailable.
+    pub max_connections: Option<usize>,
+
+    /// Token required on every request except `/healthz`, as an
+    /// `Authorization: Bearer` header or a `token` query parameter.
+    pub auth_token: Option<String>,
+}
+
+impl WebConfig {
+    /// The address the web server listens on.
+    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        let ip: IpAddr = self.bind_address.trim().parse()?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
 }
 
 impl Default for WebConfig {
@@ -114,18 +527,183 @@ impl Default for WebConfig {
         Self {
             enabled: false,
             port: default_web_port(),
+            bind_address: default_web_bind_address(),
+            max_connections: None,
+            auth_token: None,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LoggingConfig {
+    /// Mask tracking numbers and personal details in logged courier payloads.
+    #[serde(default = "default_true")]
+    pub redact: bool,
+
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self { redact: true, format: LogFormat::default() }
+    }
+}
+
+/// How log lines are written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with event fields as top-level keys.
+    Json,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct NotifyConfig {
+    /// Send a one-time heads-up when a package's ETA is this many days away,
+    /// or sooner.
+    pub eta_lead_days: Option<u32>,
+    /// After downtime, send one consolidated notification instead of one per
+    /// package when a poll produces more than this many status changes.
+    pub batch_after_downtime: Option<usize>,
+}
+
+/// POST each package status change as JSON to `url`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+
+    /// Give up on a request after this long, so a dead endpoint can't hold
+    /// up polling.
+    #[serde(default = "default_webhook_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+/// Email a summary when a package is delivered. Port 465 uses implicit TLS,
+/// any other port STARTTLS. Credentials and addresses left unset default to
+/// the `[email]` login.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SmtpConfig {
+    pub server: String,
+
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Periodic database housekeeping. Each step is off unless configured, and
+/// row-deleting steps touch at most `max_rows_per_run` rows per run.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MaintenanceConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_maintenance_interval_hours")]
+    pub interval_hours: u64,
+
+    /// Archive (soft-delete) packages delivered more than this many days ago.
+    pub archive_delivered_after_days: Option<u32>,
+
+    /// Keep at most this many status history entries per package.
+    pub max_history_per_package: Option<usize>,
+
+    /// Checkpoint and truncate the SQLite write-ahead log.
+    #[serde(default)]
+    pub checkpoint_wal: bool,
+
+    /// Rebuild the database file to reclaim free space.
+    #[serde(default)]
+    pub vacuum: bool,
+
+    #[serde(default = "default_maintenance_max_rows")]
+    pub max_rows_per_run: usize,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: default_maintenance_interval_hours(),
+            archive_delivered_after_days: None,
+            max_history_per_package: None,
+            checkpoint_wal: false,
+            vacuum: false,
+            max_rows_per_run: default_maintenance_max_rows(),
+        }
+    }
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_webhook_timeout_seconds() -> u64 {
+    5
+}
+
+fn default_maintenance_interval_hours() -> u64 {
+    24
+}
+
+fn default_maintenance_max_rows() -> usize {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
 fn default_web_port() -> u16 {
     3000
 }
 
+fn default_web_bind_address() -> String {
+    "127.0.0.1".into()
+}
+
 fn default_status_check_interval() -> u64 {
     3600
 }
 
+fn default_backfill_threshold() -> usize {
+    10
+}
+
+fn default_backfill_max_per_cycle() -> usize {
+    20
+}
+
+fn default_backfill_delay_ms() -> u64 {
+    2000
+}
+
+fn default_adaptive_max_interval() -> u64 {
+    86400
+}
+
+fn default_max_concurrent_requests() -> usize {
+    8
+}
+
+fn default_rate_limit_cooldown_seconds() -> u64 {
+    300
+}
+
+fn default_courier_max_retries() -> u32 {
+    3
+}
+
+fn default_courier_retry_base_delay_ms() -> u64 {
+    500
+}
+
 fn default_db_path() -> String {
     "trackage.db".to_string()
 }
@@ -138,17 +716,100 @@ fn default_port() -> u16 {
     993
 }
 
+/// Courier marketing phrases that accompany example tracking numbers rather
+/// than real shipments.
+fn default_ignore_patterns() -> Vec<String> {
+    vec![r"\b(sample|example) tracking number\b".to_string()]
+}
+
+fn default_ignore_subject_patterns() -> Vec<String> {
+    [
+        r"\bset your delivery preferences\b",
+        r"\b(join|sign up for|try) (ups my choice|fedex delivery manager)\b",
+    ]
+    .iter()
+    .map(|p| p.to_string())
+    .collect()
+}
+
+fn default_fetch_batch_size() -> usize {
+    50
+}
+
+fn default_html_wrap_width() -> usize {
+    1000
+}
+
+fn default_fetch_retries() -> u32 {
+    2
+}
+
 fn default_folder() -> String {
     "INBOX".to_string()
 }
 
-/// Load configuration from config.toml and environment variables
-pub fn load() -> Result<Config, figment::Error> {
+fn figment() -> Figment {
     Figment::new()
         .merge(Toml::file("config.toml"))
         // Use double-underscore nesting for snake_case keys
         .merge(Env::prefixed("TRACKAGE_").split("__"))
-        .extract()
+}
+
+/// Load configuration from config.toml and environment variables
+pub fn load() -> Result<Config, Box<figment::Error>> {
+    figment().extract().map_err(Box::new)
+}
+
+/// List every explicitly set configuration key alongside the provider it came
+/// from (e.g. the TOML file or an environment variable). Keys not listed use
+/// their default value.
+pub fn sources() -> Vec<(String, String)> {
+    let figment = figment();
+    let mut keys = Vec::new();
+    if let Ok(dict) = figment.extract::<figment::value::Dict>() {
+        collect_keys("", &Value::from(dict), &mut keys);
+    }
+
+    keys.into_iter()
+        .map(|key| {
+            let source = figment
+                .find_metadata(&key)
+                .map(|md| md.name.to_string())
+                .unwrap_or_else(|| "unknown".into());
+            (key, source)
+        })
+        .collect()
+}
+
+fn collect_keys(prefix: &str, value: &Value, keys: &mut Vec<String>) {
+    match value {
+        Value::Dict(_, dict) => {
+            for (name, child) in dict {
+                let path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{prefix}.{name}")
+                };
+                collect_keys(&path, child, keys);
+            }
+        }
+        _ => keys.push(prefix.to_string()),
+    }
+}
+
+/// Render the effective configuration, with secrets masked, for the `config` subcommand.
+pub fn dump(config: &Config, sources: &[(String, String)]) -> String {
+    let mut out = format!("{:#?}\n", config.sanitized_for_log());
+
+    out.push_str("\nExplicitly set keys (all others use defaults):\n");
+    if sources.is_empty() {
+        out.push_str("  <none>\n");
+    }
+    for (key, source) in sources {
+        out.push_str(&format!("  {key} = {source}\n"));
+    }
+
+    out
 }
 
 /// Validate configuration and return a user-friendly error
@@ -163,17 +824,243 @@ pub fn validate(config: &Config) -> Result<(), String> {
         return Err("email.username is required".into());
     }
 
-    if email.password.is_none() {
-        return Err("email.password is required".into());
+    if email.security == ImapSecurity::Plaintext
+        && let Some(server) = &email.server
+        && !is_loopback(server)
+    {
+        warn!(server = %server, "email.security is plaintext; the IMAP password is sent unencrypted");
+    }
+
+    match email.auth {
+        ImapAuth::Password => {
+            if email.password.is_none() {
+                return Err("email.password is required".into());
+            }
+        }
+        ImapAuth::Xoauth2 => {
+            let Some(oauth2) = &email.oauth2 else {
+                return Err("email.oauth2 is required when email.auth is xoauth2".into());
+            };
+            if oauth2.access_token.is_some() && oauth2.refresh_token.is_some() {
+                return Err("email.oauth2 takes either an access_token or a refresh_token, not both".into());
+            }
+            let can_refresh = oauth2.refresh_token.is_some()
+                && oauth2.client_id.is_some()
+                && oauth2.client_secret.is_some()
+                && oauth2.token_url.is_some();
+            if oauth2.access_token.is_none() && !can_refresh {
+                return Err("email.oauth2 needs an access_token, or a refresh_token with client_id, \
+                            client_secret and token_url"
+                    .into());
+            }
+        }
     }
 
     if email.check_interval_seconds == 0 {
         return Err("email.check_interval_seconds must be greater than 0".into());
     }
 
+    if config.status.check_interval_seconds == 0 {
+        return Err("status.check_interval_seconds must be greater than 0".into());
+    }
+
+    if !config.allow_short_intervals {
+        for (key, interval) in [
+            ("email.check_interval_seconds", email.check_interval_seconds),
+            ("status.check_interval_seconds", config.status.check_interval_seconds),
+        ] {
+            if interval < MIN_CHECK_INTERVAL_SECONDS {
+                return Err(format!(
+                    "{key} is {interval}, below the minimum of {MIN_CHECK_INTERVAL_SECONDS} seconds; \
+                     polling this often risks being rate limited or banned \
+                     (set allow_short_intervals = true to override for testing)"
+                ));
+            }
+        }
+    }
+
+    if email.max_poll_duration_seconds == Some(0) {
+        return Err("email.max_poll_duration_seconds must be greater than 0".into());
+    }
+
+    let courier = &config.courier;
+    let mut credentials = Vec::new();
+    if let Some(fedex) = &courier.fedex {
+        credentials.extend([("courier.fedex.client_id", &fedex.client_id), ("courier.fedex.client_secret", &fedex.client_secret)]);
+    }
+    if let Some(ups) = &courier.ups {
+        credentials.extend([("courier.ups.client_id", &ups.client_id), ("courier.ups.client_secret", &ups.client_secret)]);
+    }
+    if let Some(usps) = &courier.usps {
+        credentials.extend([("courier.usps.client_id", &usps.client_id), ("courier.usps.client_secret", &usps.client_secret)]);
+    }
+    if let Some(canada_post) = &courier.canada_post {
+        credentials.extend([
+            ("courier.canada_post.username", &canada_post.username),
+            ("courier.canada_post.password", &canada_post.password),
+        ]);
+    }
+    if let Some((key, _)) = credentials.iter().find(|(_, value)| value.trim().is_empty()) {
+        return Err(format!("{key} is empty"));
+    }
+
+    for (courier, requests_per_minute) in &config.courier.requests_per_minute {
+        if courier.parse::<crate::courier::CourierCode>().is_err() {
+            return Err(format!("courier.requests_per_minute courier '{courier}' is not a known courier"));
+        }
+        if *requests_per_minute == 0 {
+            return Err(format!("courier.requests_per_minute.{courier} must be greater than 0"));
+        }
+    }
+
+    if config.courier.ups_client == UpsClientKind::Api && config.courier.ups.is_none() {
+        return Err("courier.ups is required when courier.ups_client is api".into());
+    }
+
+    if config.web.enabled && config.web.port == 0 {
+        return Err("web.port must be between 1 and 65535".into());
+    }
+
+    if config.web.socket_addr().is_err() {
+        return Err(format!("web.bind_address '{}' is not an IP address", config.web.bind_address));
+    }
+
+    if config.web.auth_token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+        return Err("web.auth_token is empty".into());
+    }
+
+    if config.web.max_connections == Some(0) {
+        return Err("web.max_connections must be greater than 0".into());
+    }
+
+    if let Some(smtp) = &config.smtp {
+        if smtp.password.is_none() && email.password.is_none() {
+            return Err("smtp.password is required when email.password isn't set".into());
+        }
+        crate::notify::smtp::addresses(smtp, email).map_err(|err| format!("{err:#}"))?;
+    }
+
+    if let Some(webhook) = &config.webhook {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            return Err("webhook.url must be an http:// or https:// URL".into());
+        }
+        if webhook.timeout_seconds == 0 {
+            return Err("webhook.timeout_seconds must be greater than 0".into());
+        }
+    }
+
+    if email.scan_barcodes && !cfg!(feature = "barcodes") {
+        return Err("email.scan_barcodes requires building with the `barcodes` feature".into());
+    }
+
+    if email.fetch_batch_size == 0 {
+        return Err("email.fetch_batch_size must be greater than 0".into());
+    }
+
+    if email.html_wrap_width < MIN_HTML_WRAP_WIDTH {
+        return Err(format!("email.html_wrap_width must be at least {MIN_HTML_WRAP_WIDTH}"));
+    }
+
+    if let Some(hours) = &config.status.active_hours {
+        if hours.start > 23 || hours.end > 23 {
+            return Err("status.active_hours start and end must be hours between 0 and 23".into());
+        }
+        if hours.start == hours.end {
+            return Err("status.active_hours start and end must differ".into());
+        }
+    }
+
+    if let Some(backfill) = &config.status.backfill {
+        if backfill.unscanned_threshold == 0 {
+            return Err("status.backfill.unscanned_threshold must be greater than 0".into());
+        }
+        if backfill.max_per_cycle == 0 {
+            return Err("status.backfill.max_per_cycle must be greater than 0".into());
+        }
+    }
+
+    if let Some(async_requests) = &config.status.async_requests
+        && async_requests.max_concurrent == 0
+    {
+        return Err("status.async_requests.max_concurrent must be greater than 0".into());
+    }
+
+    if let Some(adaptive) = &config.status.adaptive {
+        if adaptive.base_interval_seconds == 0 {
+            return Err("status.adaptive.base_interval_seconds must be greater than 0".into());
+        }
+        if adaptive.max_interval_seconds < adaptive.base_interval_seconds {
+            return Err("status.adaptive.max_interval_seconds must be at least base_interval_seconds".into());
+        }
+    }
+
+    let maintenance = &config.maintenance;
+    if maintenance.interval_hours == 0 {
+        return Err("maintenance.interval_hours must be greater than 0".into());
+    }
+    if maintenance.max_rows_per_run == 0 {
+        return Err("maintenance.max_rows_per_run must be greater than 0".into());
+    }
+    if maintenance.max_history_per_package == Some(0) {
+        return Err("maintenance.max_history_per_package must be greater than 0".into());
+    }
+
+    if let Some(key) = &config.database.encryption_key {
+        if !cfg!(feature = "sqlcipher") {
+            return Err("database.encryption_key requires trackage to be built with the `sqlcipher` feature".into());
+        }
+        if key.is_empty() {
+            return Err("database.encryption_key is empty".into());
+        }
+    }
+
+    if config.database.replica_path.is_some() && config.database.encryption_key.is_some() {
+        return Err("database.replica_path is not supported with database.encryption_key".into());
+    }
+
+    for (i, rule) in email.courier_overrides.iter().enumerate() {
+        if let Err(err) = regex::Regex::new(&rule.pattern) {
+            return Err(format!("email.courier_overrides[{i}].pattern is invalid: {err}"));
+        }
+        if rule.courier.parse::<crate::courier::CourierCode>().is_err() {
+            return Err(format!(
+                "email.courier_overrides[{i}].courier '{}' is not a known courier",
+                rule.courier
+            ));
+        }
+    }
+
+    for (i, pattern) in email.ignore_patterns.iter().enumerate() {
+        if let Err(err) = regex::Regex::new(pattern) {
+            return Err(format!("email.ignore_patterns[{i}] is invalid: {err}"));
+        }
+    }
+
+    for (i, pattern) in email.ignore_subject_patterns.iter().enumerate() {
+        if let Err(err) = regex::Regex::new(pattern) {
+            return Err(format!("email.ignore_subject_patterns[{i}] is invalid: {err}"));
+        }
+    }
+
+    for (i, pattern) in email.order_confirmation_patterns.iter().enumerate() {
+        if let Err(err) = regex::Regex::new(pattern) {
+            return Err(format!("email.order_confirmation_patterns[{i}] is invalid: {err}"));
+        }
+    }
+
     Ok(())
 }
 
+/// Whether `host` names this machine, where an unencrypted connection can't
+/// be snooped on the network.
+fn is_loopback(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_matches(['[', ']'])
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
 const MASKED: &str = "******";
 const NOT_SET: &str = "<not set>";
 
@@ -190,6 +1077,12 @@ pub struct SanitizedConfig {
     pub status: SanitizedStatusPollerConfig,
     pub courier: SanitizedCourierConfig,
     pub web: SanitizedWebConfig,
+    pub logging: SanitizedLoggingConfig,
+    pub notify: SanitizedNotifyConfig,
+    pub webhook: Option<SanitizedWebhookConfig>,
+    pub smtp: Option<SanitizedSmtpConfig>,
+    pub maintenance: SanitizedMaintenanceConfig,
+    pub allow_short_intervals: bool,
 }
 
 #[derive(Debug)]
@@ -197,22 +1090,62 @@ pub struct SanitizedConfig {
 pub struct SanitizedEmailConfig {
     pub server: String,
     pub port: u16,
+    pub security: ImapSecurity,
     pub username: String,
     pub password: &'static str,
+    pub auth: ImapAuth,
+    pub oauth2: Option<SanitizedOAuth2Config>,
     pub folder: String,
     pub check_interval_seconds: u64,
+    pub courier_overrides: Vec<CourierOverrideConfig>,
+    pub ignore_patterns: Vec<String>,
+    pub ignore_subject_patterns: Vec<String>,
+    pub order_confirmation_patterns: Vec<String>,
+    pub max_poll_duration_seconds: Option<u64>,
+    pub initial_lookback_days: Option<u32>,
+    pub fetch_batch_size: usize,
+    pub fetch_retries: u32,
+    pub poll_on_startup: bool,
+    pub date_header_fallback: bool,
+    pub scan_barcodes: bool,
+    pub parse_digests: bool,
+    pub strip_quoted: bool,
+    pub html_wrap_width: usize,
+    pub courier_conflict: CourierConflictPolicy,
+    pub trust_courier_on_no_match: bool,
+    pub use_idle: bool,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SanitizedOAuth2Config {
+    pub access_token: &'static str,
+    pub refresh_token: &'static str,
+    pub client_id: Option<String>,
+    pub client_secret: &'static str,
+    pub token_url: Option<String>,
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct SanitizedDatabaseConfig {
     pub path: String,
+    pub encryption_key: &'static str,
+    pub replica_path: Option<String>,
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct SanitizedStatusPollerConfig {
     pub check_interval_seconds: u64,
+    pub active_hours: Option<ActiveHoursConfig>,
+    pub poll_on_startup: bool,
+    pub assume_delivered_after_days: Option<u32>,
+    pub confirm_delivered: bool,
+    pub short_descriptions: bool,
+    pub backfill: Option<BackfillConfig>,
+    pub async_requests: Option<AsyncRequestsConfig>,
+    pub adaptive: Option<AdaptivePollingConfig>,
 }
 
 #[derive(Debug)]
@@ -221,6 +1154,10 @@ pub struct SanitizedCourierConfig {
     pub fedex: Option<SanitizedCourierCredentials>,
     pub ups: Option<SanitizedCourierCredentials>,
     pub usps: Option<SanitizedCourierCredentials>,
+    pub canada_post: Option<SanitizedCourierCredentials>,
+    pub ups_client: UpsClientKind,
+    pub requests_per_minute: BTreeMap<String, u32>,
+    pub rate_limit_cooldown_seconds: u64,
 }
 
 #[derive(Debug)]
@@ -228,6 +1165,7 @@ pub struct SanitizedCourierConfig {
 pub struct SanitizedCourierCredentials {
     pub client_id: String,
     pub client_secret: &'static str,
+    pub retry: RetryConfig,
 }
 
 #[derive(Debug)]
@@ -235,6 +1173,54 @@ pub struct SanitizedCourierCredentials {
 pub struct SanitizedWebConfig {
     pub enabled: bool,
     pub port: u16,
+    pub bind_address: String,
+    pub max_connections: Option<usize>,
+    pub auth_token: &'static str,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SanitizedLoggingConfig {
+    pub redact: bool,
+    pub format: LogFormat,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SanitizedMaintenanceConfig {
+    pub enabled: bool,
+    pub interval_hours: u64,
+    pub archive_delivered_after_days: Option<u32>,
+    pub max_history_per_package: Option<usize>,
+    pub checkpoint_wal: bool,
+    pub vacuum: bool,
+    pub max_rows_per_run: usize,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SanitizedNotifyConfig {
+    pub eta_lead_days: Option<u32>,
+    batch_after_downtime: Option<usize>,
+}
+
+/// The URL often embeds a secret webhook id, so it is masked.
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SanitizedWebhookConfig {
+    pub url: &'static str,
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct SanitizedSmtpConfig {
+    pub server: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: &'static str,
+    pub from: Option<String>,
+    pub to: Option<String>,
 }
 
 impl Config {
@@ -243,35 +1229,347 @@ impl Config {
             email: SanitizedEmailConfig {
                 server: self.email.server.clone().unwrap_or_else(|| NOT_SET.into()),
                 port: self.email.port,
+                security: self.email.security,
                 username: self.email.username.clone().unwrap_or_else(|| NOT_SET.into()),
                 password: mask_option(&self.email.password),
+                auth: self.email.auth,
+                oauth2: self.email.oauth2.as_ref().map(|o| SanitizedOAuth2Config {
+                    access_token: mask_option(&o.access_token),
+                    refresh_token: mask_option(&o.refresh_token),
+                    client_id: o.client_id.clone(),
+                    client_secret: mask_option(&o.client_secret),
+                    token_url: o.token_url.clone(),
+                }),
                 folder: self.email.folder.clone(),
                 check_interval_seconds: self.email.check_interval_seconds,
+                courier_overrides: self.email.courier_overrides.clone(),
+                ignore_patterns: self.email.ignore_patterns.clone(),
+                ignore_subject_patterns: self.email.ignore_subject_patterns.clone(),
+                order_confirmation_patterns: self.email.order_confirmation_patterns.clone(),
+                max_poll_duration_seconds: self.email.max_poll_duration_seconds,
+                initial_lookback_days: self.email.initial_lookback_days,
+                fetch_batch_size: self.email.fetch_batch_size,
+                fetch_retries: self.email.fetch_retries,
+                poll_on_startup: self.email.poll_on_startup,
+                date_header_fallback: self.email.date_header_fallback,
+                scan_barcodes: self.email.scan_barcodes,
+                parse_digests: self.email.parse_digests,
+                strip_quoted: self.email.strip_quoted,
+                html_wrap_width: self.email.html_wrap_width,
+                courier_conflict: self.email.courier_conflict,
+                trust_courier_on_no_match: self.email.trust_courier_on_no_match,
+                use_idle: self.email.use_idle,
             },
             database: SanitizedDatabaseConfig {
                 path: self.database.path.clone(),
+                encryption_key: mask_option(&self.database.encryption_key),
+                replica_path: self.database.replica_path.clone(),
             },
             status: SanitizedStatusPollerConfig {
                 check_interval_seconds: self.status.check_interval_seconds,
+                active_hours: self.status.active_hours.clone(),
+                poll_on_startup: self.status.poll_on_startup,
+                assume_delivered_after_days: self.status.assume_delivered_after_days,
+                confirm_delivered: self.status.confirm_delivered,
+                short_descriptions: self.status.short_descriptions,
+                backfill: self.status.backfill.clone(),
+                async_requests: self.status.async_requests.clone(),
+                adaptive: self.status.adaptive.clone(),
             },
             courier: SanitizedCourierConfig {
                 fedex: self.courier.fedex.as_ref().map(|c| SanitizedCourierCredentials {
                     client_id: c.client_id.clone(),
                     client_secret: MASKED,
+                    retry: c.retry,
                 }),
                 ups: self.courier.ups.as_ref().map(|c| SanitizedCourierCredentials {
                     client_id: c.client_id.clone(),
                     client_secret: MASKED,
+                    retry: c.retry,
                 }),
                 usps: self.courier.usps.as_ref().map(|c| SanitizedCourierCredentials {
                     client_id: c.client_id.clone(),
                     client_secret: MASKED,
+                    retry: c.retry,
+                }),
+                canada_post: self.courier.canada_post.as_ref().map(|c| SanitizedCourierCredentials {
+                    client_id: c.username.clone(),
+                    client_secret: MASKED,
+                    retry: c.retry,
                 }),
+                ups_client: self.courier.ups_client,
+                requests_per_minute: self.courier.requests_per_minute.clone(),
+                rate_limit_cooldown_seconds: self.courier.rate_limit_cooldown_seconds,
             },
             web: SanitizedWebConfig {
                 enabled: self.web.enabled,
                 port: self.web.port,
+                bind_address: self.web.bind_address.clone(),
+                max_connections: self.web.max_connections,
+                auth_token: mask_option(&self.web.auth_token),
             },
+            logging: SanitizedLoggingConfig {
+                redact: self.logging.redact,
+                format: self.logging.format,
+            },
+            notify: SanitizedNotifyConfig {
+                eta_lead_days: self.notify.eta_lead_days,
+                batch_after_downtime: self.notify.batch_after_downtime,
+            },
+            webhook: self.webhook.as_ref().map(|w| SanitizedWebhookConfig {
+                url: MASKED,
+                timeout_seconds: w.timeout_seconds,
+            }),
+            smtp: self.smtp.as_ref().map(|s| SanitizedSmtpConfig {
+                server: s.server.clone(),
+                port: s.port,
+                username: s.username.clone(),
+                password: mask_option(&s.password),
+                from: s.from.clone(),
+                to: s.to.clone(),
+            }),
+            maintenance: SanitizedMaintenanceConfig {
+                enabled: self.maintenance.enabled,
+                interval_hours: self.maintenance.interval_hours,
+                archive_delivered_after_days: self.maintenance.archive_delivered_after_days,
+                max_history_per_package: self.maintenance.max_history_per_package,
+                checkpoint_wal: self.maintenance.checkpoint_wal,
+                vacuum: self.maintenance.vacuum,
+                max_rows_per_run: self.maintenance.max_rows_per_run,
+            },
+            allow_short_intervals: self.allow_short_intervals,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Config {
+        Figment::from(Toml::string(toml)).extract().unwrap()
+    }
+
+    #[test]
+    fn dump_masks_secrets() {
+        let config = parse(
+            r#"
+            [email]
+            server = "imap.example.com"
+            username = "me@example.com"
+            password = "hunter2"
+
+            [database]
+            encryption_key = "db-secret"
+
+            [web]
+            auth_token = "web-secret"
+
+            [courier.fedex]
+            client_id = "fedex-id"
+            client_secret = "fedex-secret"
+            "#,
+        );
+
+        let out = dump(&config, &[("email.password".into(), "TOML file".into())]);
+
+        assert!(out.contains("imap.example.com"));
+        assert!(out.contains("fedex-id"));
+        assert!(out.contains(MASKED));
+        assert!(out.contains("email.password = TOML file"));
+        assert!(!out.contains("hunter2"));
+        assert!(!out.contains("fedex-secret"));
+        assert!(!out.contains("db-secret"));
+        assert!(!out.contains("web-secret"));
+    }
+
+    #[test]
+    fn active_hours_contains() {
+        let day = ActiveHoursConfig { start: 8, end: 20, exempt_arriving_today: false };
+        assert!(day.contains(8));
+        assert!(day.contains(19));
+        assert!(!day.contains(20));
+        assert!(!day.contains(3));
+
+        let overnight = ActiveHoursConfig { start: 22, end: 6, exempt_arriving_today: false };
+        assert!(overnight.contains(23));
+        assert!(overnight.contains(2));
+        assert!(!overnight.contains(6));
+        assert!(!overnight.contains(12));
+    }
+
+    const MINIMAL: &str = r#"
+        [email]
+        server = "imap.example.com"
+        username = "me@example.com"
+        password = "hunter2"
+    "#;
+
+    #[test]
+    fn rejects_intervals_below_minimum() {
+        let config = parse(&format!("{MINIMAL}\n[status]\ncheck_interval_seconds = 1"));
+        let err = validate(&config).unwrap_err();
+        assert!(err.contains("status.check_interval_seconds"), "{err}");
+
+        let config = parse(&format!("{MINIMAL}\ncheck_interval_seconds = 30"));
+        let err = validate(&config).unwrap_err();
+        assert!(err.contains("email.check_interval_seconds"), "{err}");
+
+        assert!(validate(&parse(MINIMAL)).is_ok());
+    }
+
+    #[test]
+    fn allow_short_intervals_overrides_minimum() {
+        let config = parse(&format!(
+            "allow_short_intervals = true\n{MINIMAL}\n[status]\ncheck_interval_seconds = 1"
+        ));
+        assert!(validate(&config).is_ok());
+
+        let config = parse(&format!(
+            "allow_short_intervals = true\n{MINIMAL}\n[status]\ncheck_interval_seconds = 0"
+        ));
+        assert!(validate(&config).is_err());
+    }
+
+    #[test]
+    fn courier_retries_default_and_override_per_courier() {
+        let config = parse(&format!(
+            "{MINIMAL}\n[courier.ups]\nclient_id = \"id\"\nclient_secret = \"secret\"\n\
+             [courier.usps]\nclient_id = \"id\"\nclient_secret = \"secret\"\nmax_retries = 5\nretry_base_delay_ms = 100"
+        ));
+
+        let ups = config.courier.ups.unwrap().retry;
+        assert_eq!((ups.max_retries, ups.retry_base_delay_ms), (3, 500));
+        let usps = config.courier.usps.unwrap().retry;
+        assert_eq!((usps.max_retries, usps.retry_base_delay_ms), (5, 100));
+    }
+
+    #[test]
+    fn rejects_blank_courier_credentials() {
+        let courier = |section: &str, id: &str, secret: &str| {
+            parse(&format!("{MINIMAL}\n[courier.{section}]\nclient_id = \"{id}\"\nclient_secret = \"{secret}\""))
+        };
+
+        assert!(validate(&courier("ups", "id", "secret")).is_ok());
+        assert_eq!(validate(&courier("ups", "id", "")).unwrap_err(), "courier.ups.client_secret is empty");
+        assert_eq!(validate(&courier("fedex", " ", "secret")).unwrap_err(), "courier.fedex.client_id is empty");
+        assert_eq!(validate(&courier("usps", "id", "")).unwrap_err(), "courier.usps.client_secret is empty");
+
+        let config = parse(&format!("{MINIMAL}\n[courier.canada_post]\nusername = \"key\"\npassword = \"\""));
+        assert_eq!(validate(&config).unwrap_err(), "courier.canada_post.password is empty");
+    }
+
+    #[test]
+    fn rejects_port_zero_only_when_web_is_enabled() {
+        let config = parse(&format!("{MINIMAL}\n[web]\nenabled = true\nport = 0"));
+        assert_eq!(validate(&config).unwrap_err(), "web.port must be between 1 and 65535");
+
+        let config = parse(&format!("{MINIMAL}\n[web]\nport = 0"));
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn binds_web_server_to_loopback_by_default() {
+        let config = parse(MINIMAL);
+        assert_eq!(config.web.socket_addr().unwrap(), "127.0.0.1:3000".parse().unwrap());
+
+        let config = parse(&format!("{MINIMAL}\n[web]\nbind_address = \"0.0.0.0\"\nport = 8080"));
+        assert!(validate(&config).is_ok());
+        assert_eq!(config.web.socket_addr().unwrap(), "0.0.0.0:8080".parse().unwrap());
+
+        let config = parse(&format!("{MINIMAL}\n[web]\nbind_address = \"::1\""));
+        assert_eq!(config.web.socket_addr().unwrap(), "[::1]:3000".parse().unwrap());
+
+        let config = parse(&format!("{MINIMAL}\n[web]\nbind_address = \"localhost\""));
+        assert_eq!(validate(&config).unwrap_err(), "web.bind_address 'localhost' is not an IP address");
+    }
+
+    #[test]
+    fn validates_html_wrap_width() {
+        assert_eq!(parse(MINIMAL).email.html_wrap_width, 1000);
+
+        let config = parse(&format!("{MINIMAL}\nhtml_wrap_width = 40"));
+        assert_eq!(validate(&config).unwrap_err(), "email.html_wrap_width must be at least 80");
+
+        let config = parse(&format!("{MINIMAL}\nhtml_wrap_width = 200"));
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn rejects_blank_web_auth_token() {
+        let config = parse(&format!("{MINIMAL}\n[web]\nauth_token = \" \""));
+        assert_eq!(validate(&config).unwrap_err(), "web.auth_token is empty");
+
+        let config = parse(&format!("{MINIMAL}\n[web]\nauth_token = \"s3cret\""));
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn parses_imap_security_modes() {
+        assert_eq!(parse(MINIMAL).email.security, ImapSecurity::Tls);
+
+        let config = parse(&format!("{MINIMAL}\nsecurity = \"starttls\"\nport = 143"));
+        assert_eq!(config.email.security, ImapSecurity::Starttls);
+        assert!(validate(&config).is_ok());
+
+        let config = parse(&format!("{MINIMAL}\nsecurity = \"plaintext\""));
+        assert_eq!(config.email.security, ImapSecurity::Plaintext);
+        assert!(validate(&config).is_ok());
+
+        assert!(is_loopback("localhost"));
+        assert!(is_loopback("127.0.0.1"));
+        assert!(is_loopback("[::1]"));
+        assert!(!is_loopback("imap.example.com"));
+    }
+
+    #[test]
+    fn parses_log_format() {
+        assert_eq!(parse(MINIMAL).logging.format, LogFormat::Text);
+
+        let config = parse(&format!("{MINIMAL}\n[logging]\nformat = \"json\""));
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert!(config.logging.redact);
+    }
+
+    #[test]
+    fn xoauth2_requires_a_token_or_refresh_credentials() {
+        let oauth_only = r#"
+            [email]
+            server = "imap.gmail.com"
+            username = "me@gmail.com"
+            auth = "xoauth2"
+        "#;
+        let err = validate(&parse(oauth_only)).unwrap_err();
+        assert!(err.contains("email.oauth2"), "{err}");
+
+        let config = parse(&format!("{oauth_only}\n[email.oauth2]\nrefresh_token = \"r\"\nclient_id = \"id\""));
+        let err = validate(&config).unwrap_err();
+        assert!(err.contains("refresh_token"), "{err}");
+
+        let config = parse(&format!("{oauth_only}\n[email.oauth2]\naccess_token = \"ya29.token\""));
+        assert_eq!(config.email.auth, ImapAuth::Xoauth2);
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn xoauth2_rejects_a_static_token_alongside_refresh_credentials() {
+        let config = parse(
+            r#"
+            [email]
+            server = "imap.gmail.com"
+            username = "me@gmail.com"
+            auth = "xoauth2"
+
+            [email.oauth2]
+            access_token = "ya29.token"
+            refresh_token = "r"
+            client_id = "id"
+            client_secret = "secret"
+            token_url = "https://oauth2.googleapis.com/token"
+        "#,
+        );
+
+        let err = validate(&config).unwrap_err();
+        assert!(err.contains("not both"), "{err}");
+    }
+}
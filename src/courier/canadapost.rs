@@ -0,0 +1,290 @@
+use super::http::{self, HttpRequest, HttpStatusError};
+use super::{CourierClient, CourierError, CourierFuture, CourierStatus};
+use crate::config::{CanadaPostConfig, RetryConfig};
+use crate::db::{Package, PackageStatus};
+use crate::redact;
+use anyhow::{Context, Result};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::{FixedOffset, NaiveDateTime, TimeZone};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Substrings matched case-insensitively in the event description. Pickup is
+/// checked before delivered: an item "delivered to the Post Office" still has
+/// to be collected.
+const DESCRIPTION_KEYWORDS_PICKUP: &[&str] = &["available for pickup", "ready for pickup", "for pickup at"];
+const DESCRIPTION_KEYWORD_DELIVERED: &str = "delivered";
+/// Descriptions mentioning delivery that say it didn't happen.
+const DESCRIPTION_KEYWORDS_NOT_DELIVERED: &[&str] =
+    &["not delivered", "not be delivered", "cannot be delivered", "can't be delivered", "undelivered"];
+const DESCRIPTION_KEYWORDS_WAITING: &[&str] = &["electronic information submitted", "shipping label created"];
+
+const API_URL: &str = "https://soa-gw.canadapost.ca";
+const TRACK_PATH: &str = "/vis/track/pin/";
+const ACCEPT: &str = "application/vnd.cpc.track-v2+xml";
+
+pub struct CanadaPostClient {
+    api_url: String,
+    authorization: String,
+    retry: RetryConfig,
+}
+
+impl CanadaPostClient {
+    pub fn new(config: &CanadaPostConfig) -> Self {
+        let credentials = BASE64.encode(format!("{}:{}", config.username, config.password));
+        Self {
+            api_url: API_URL.to_string(),
+            authorization: format!("Basic {credentials}"),
+            retry: config.retry,
+        }
+    }
+
+    fn track_request(&self, package: &Package) -> HttpRequest {
+        HttpRequest::get(format!("{}{TRACK_PATH}{}/summary", self.api_url, package.tracking_number))
+            .header("Authorization", self.authorization.clone())
+            .header("Accept", ACCEPT)
+            .header("Accept-Language", "en-CA")
+    }
+
+    /// The response body. Canada Post reports a number it has no history for
+    /// with a 404.
+    fn track_response(result: Result<String>, package: &Package) -> Result<String> {
+        match result {
+            Ok(body) => Ok(body),
+            Err(err) if err.downcast_ref::<HttpStatusError>().is_some_and(|e| e.status == 404) => {
+                debug!(
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
+                    "Canada Post tracking number not found"
+                );
+                Err(CourierError::NotFound.into())
+            }
+            Err(err) => Err(err).context("Canada Post track request failed"),
+        }
+    }
+
+    fn parse_track_response(package: &Package, body: &str) -> Result<Vec<CourierStatus>> {
+        // Check for error envelope
+        if let Some(code) = xml_text(body, "code") {
+            let message = xml_text(body, "description").unwrap_or_default();
+            warn!(
+                tracking_number = %redact::tracking_number(&package.tracking_number),
+                error_code = %code,
+                error_message = %message,
+                "Canada Post tracking error"
+            );
+            return Err(CourierError::from_canada_post_code(&code).into());
+        }
+
+        let Some(summary) = xml_element(body, "pin-summary") else {
+            debug!(
+                tracking_number = %redact::tracking_number(&package.tracking_number),
+                "No pin-summary in Canada Post response"
+            );
+            return Ok(vec![]);
+        };
+
+        let description = xml_text(summary, "event-description");
+        let event_type = xml_text(summary, "event-type");
+        let mapped = Self::map_event(event_type.as_deref(), description.as_deref().unwrap_or_default());
+
+        debug!(
+            tracking_number = %redact::tracking_number(&package.tracking_number),
+            canada_post_event = event_type.as_deref().unwrap_or_default(),
+            mapped_status = %mapped,
+            "Canada Post status retrieved"
+        );
+
+        Ok(vec![CourierStatus {
+            status: mapped.to_string(),
+            estimated_arrival_date: xml_text(summary, "expected-delivery-date"),
+            last_known_location: xml_text(summary, "event-location"),
+            checked_at: xml_text(summary, "event-date-time")
+                .as_deref()
+                .and_then(|value| parse_event_date_time(value, xml_text(summary, "event-time-zone").as_deref())),
+            courier_status_text: description.clone(),
+            description,
+            delivery_location_detail: None,
+            courier_status_code: event_type,
+        }])
+    }
+
+    fn map_event(event_type: Option<&str>, description: &str) -> PackageStatus {
+        let lower = description.to_lowercase();
+        if DESCRIPTION_KEYWORDS_PICKUP.iter().any(|keyword| lower.contains(keyword)) {
+            PackageStatus::ReadyForPickup
+        } else if event_type == Some("DELIVERED")
+            || (lower.contains(DESCRIPTION_KEYWORD_DELIVERED)
+                && !DESCRIPTION_KEYWORDS_NOT_DELIVERED.iter().any(|keyword| lower.contains(keyword)))
+        {
+            PackageStatus::Delivered
+        } else if DESCRIPTION_KEYWORDS_WAITING.iter().any(|keyword| lower.contains(keyword)) {
+            PackageStatus::Waiting
+        } else {
+            PackageStatus::InTransit
+        }
+    }
+}
+
+/// Parse Canada Post's `YYYYMMDD:HHMMSS` event time, local to the event's
+/// `time_zone` abbreviation, into RFC 3339 UTC. Without a recognized time
+/// zone the instant is unknown, so there is no time.
+fn parse_event_date_time(value: &str, time_zone: Option<&str>) -> Option<String> {
+    let local = NaiveDateTime::parse_from_str(value, "%Y%m%d:%H%M%S").ok()?;
+    let offset = time_zone_offset(time_zone?)?;
+    let utc = offset.from_local_datetime(&local).single()?.naive_utc();
+    Some(utc.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// The UTC offset of a Canadian time zone abbreviation as Canada Post reports it.
+fn time_zone_offset(abbreviation: &str) -> Option<FixedOffset> {
+    let minutes = match abbreviation.to_ascii_uppercase().as_str() {
+        "NST" => -210,
+        "NDT" => -150,
+        "AST" => -240,
+        "ADT" => -180,
+        "EST" => -300,
+        "EDT" => -240,
+        "CST" => -360,
+        "CDT" => -300,
+        "MST" => -420,
+        "MDT" => -360,
+        "PST" => -480,
+        "PDT" => -420,
+        "UTC" | "GMT" => 0,
+        _ => return None,
+    };
+    FixedOffset::east_opt(minutes * 60)
+}
+
+/// The contents of the first `<tag>` element in `xml`.
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(&xml[start..end])
+}
+
+/// The unescaped, trimmed text of the first `<tag>` element in `xml`, if it
+/// isn't empty.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let text = xml_element(xml, tag)?.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+impl CourierClient for CanadaPostClient {
+    fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
+        let request = self.track_request(package);
+        let result = http::retry_with_backoff(&self.retry, || http::send_text(&request));
+        let body = Self::track_response(result, package)?;
+        Self::parse_track_response(package, &body)
+    }
+
+    fn check_status_async(self: Arc<Self>, client: reqwest::Client, package: Package) -> CourierFuture {
+        Box::pin(async move {
+            let request = self.track_request(&package);
+            let result = http::retry_with_backoff_async(&self.retry, || http::send_text_async(&client, &request)).await;
+            let body = Self::track_response(result, &package)?;
+            Self::parse_track_response(&package, &body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELIVERED_FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tracking-summary xmlns="http://www.canadapost.ca/ws/track-v2">
+  <pin-summary>
+    <pin>0073938000549297</pin>
+    <origin-postal-id>K2B8J6</origin-postal-id>
+    <destination-postal-id>J8R1A2</destination-postal-id>
+    <destination-province>QC</destination-province>
+    <service-name>Priority</service-name>
+    <mailed-on-date>2026-03-02</mailed-on-date>
+    <expected-delivery-date>2026-03-04</expected-delivery-date>
+    <actual-delivery-date>2026-03-04</actual-delivery-date>
+    <delivery-option-completed-ind>0</delivery-option-completed-ind>
+    <event-date-time>20260304:101015</event-date-time>
+    <event-time-zone>EST</event-time-zone>
+    <event-description>Item successfully delivered</event-description>
+    <attempted-date></attempted-date>
+    <customer-ref-1></customer-ref-1>
+    <event-type>DELIVERED</event-type>
+    <event-location>GATINEAU</event-location>
+    <signatory-name>J DOE</signatory-name>
+  </pin-summary>
+</tracking-summary>"#;
+
+    const NOT_FOUND_FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<messages xmlns="http://www.canadapost.ca/ws/messages">
+  <message>
+    <code>004</code>
+    <description>No Pin History</description>
+  </message>
+</messages>"#;
+
+    fn package() -> Package {
+        Package {
+            id: 1,
+            tracking_number: "0073938000549297".into(),
+            courier: "canada_post".into(),
+            service: "Canada Post (16)".into(),
+            status: PackageStatus::InTransit,
+            destination_zip: None,
+        }
+    }
+
+    #[test]
+    fn parses_track_summary_fixture() {
+        let statuses = CanadaPostClient::parse_track_response(&package(), DELIVERED_FIXTURE).unwrap();
+
+        assert_eq!(statuses.len(), 1);
+        let status = &statuses[0];
+        assert_eq!(status.status, "delivered");
+        assert_eq!(status.estimated_arrival_date.as_deref(), Some("2026-03-04"));
+        assert_eq!(status.last_known_location.as_deref(), Some("GATINEAU"));
+        assert_eq!(status.checked_at.as_deref(), Some("2026-03-04T15:10:15Z"));
+        assert_eq!(status.description.as_deref(), Some("Item successfully delivered"));
+        assert_eq!(status.courier_status_code.as_deref(), Some("DELIVERED"));
+
+        let err = CanadaPostClient::parse_track_response(&package(), NOT_FOUND_FIXTURE).err().unwrap();
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::NotFound));
+    }
+
+    #[test]
+    fn maps_event_descriptions() {
+        let cases = [
+            ("Electronic information submitted by shipper", PackageStatus::Waiting),
+            ("Item processed", PackageStatus::InTransit),
+            ("Item available for pickup at Post Office", PackageStatus::ReadyForPickup),
+            ("Delivered to your community mailbox, parcel locker or apt./condo mailbox", PackageStatus::Delivered),
+            ("Item could not be delivered; returning to sender", PackageStatus::InTransit),
+            ("Item not delivered, notice card left", PackageStatus::InTransit),
+            ("Undelivered item being returned to sender", PackageStatus::InTransit),
+        ];
+
+        for (description, expected) in cases {
+            assert_eq!(CanadaPostClient::map_event(None, description), expected, "{description}");
+        }
+    }
+
+    #[test]
+    fn converts_event_time_from_its_time_zone() {
+        assert_eq!(parse_event_date_time("20260304:101015", Some("EST")).as_deref(), Some("2026-03-04T15:10:15Z"));
+        assert_eq!(parse_event_date_time("20260704:233000", Some("PDT")).as_deref(), Some("2026-07-05T06:30:00Z"));
+        assert_eq!(parse_event_date_time("20260304:101015", Some("NST")).as_deref(), Some("2026-03-04T13:40:15Z"));
+        assert_eq!(parse_event_date_time("20260304:101015", None), None);
+        assert_eq!(parse_event_date_time("20260304:1010", Some("EST")), None);
+    }
+}
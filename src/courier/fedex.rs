@@ -1,26 +1,40 @@
-use super::{CourierClient, CourierStatus};
-use crate::config::FedexConfig;
+use super::http::{self, HttpRequest};
+use super::{CourierClient, CourierError, CourierFuture, CourierStatus};
+use crate::config::{FedexConfig, FedexEnvironment, RetryConfig};
 use crate::db::{Package, PackageStatus};
+use crate::redact;
+use crate::util::format_rfc3339_utc;
 use anyhow::{Context, Result};
-use serde_json::json;
-use std::sync::Mutex;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tracing::{debug, warn};
 
-const TOKEN_URL: &str = "https://apis.fedex.com/oauth/token";
-const TRACK_URL: &str = "https://apis.fedex.com/track/v1/trackingnumbers";
+const PRODUCTION_API_URL: &str = "https://apis.fedex.com";
+const SANDBOX_API_URL: &str = "https://apis-sandbox.fedex.com";
+const TOKEN_PATH: &str = "/oauth/token";
+const TRACK_PATH: &str = "/track/v1/trackingnumbers";
 
 pub struct FedexClient {
+    api_url: String,
     client_id: String,
     client_secret: String,
+    retry: RetryConfig,
     token: Mutex<Option<(String, Instant)>>,
 }
 
 impl FedexClient {
     pub fn new(config: &FedexConfig) -> Self {
+        let api_url = match config.environment {
+            FedexEnvironment::Production => PRODUCTION_API_URL,
+            FedexEnvironment::Sandbox => SANDBOX_API_URL,
+        };
         Self {
+            api_url: api_url.to_string(),
             client_id: config.client_id.clone(),
             client_secret: config.client_secret.clone(),
+            retry: config.retry,
             token: Mutex::new(None),
         }
     }
@@ -28,10 +42,10 @@ impl FedexClient {
     fn get_token(&self) -> Result<String> {
         let mut guard = self.token.lock().unwrap();
 
-        if let Some((ref token, expiry)) = *guard {
-            if Instant::now() < expiry {
-                return Ok(token.clone());
-            }
+        if let Some((ref token, expiry)) = *guard
+            && Instant::now() < expiry
+        {
+            return Ok(token.clone());
         }
 
         let (token, ttl) = self.fetch_token()?;
@@ -40,24 +54,46 @@ impl FedexClient {
         Ok(token)
     }
 
-    fn fetch_token(&self) -> Result<(String, Duration)> {
+    /// The cached token, if it hasn't expired.
+    fn cached_token(&self) -> Option<String> {
+        match &*self.token.lock().unwrap() {
+            Some((token, expiry)) if Instant::now() < *expiry => Some(token.clone()),
+            _ => None,
+        }
+    }
+
+    async fn get_token_async(&self, client: &reqwest::Client) -> Result<String> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
         debug!("Fetching new FedEx OAuth token");
+        let request = self.token_request();
+        let body = http::retry_with_backoff_async(&self.retry, || http::send_async(client, &request))
+            .await
+            .context("FedEx OAuth token request failed")?;
+        let (token, ttl) = Self::parse_token(&body)?;
+        *self.token.lock().unwrap() = Some((token.clone(), Instant::now() + ttl));
+        Ok(token)
+    }
 
-        let form_body = format!(
-            "grant_type=client_credentials&client_id={}&client_secret={}",
-            self.client_id, self.client_secret
-        );
+    fn fetch_token(&self) -> Result<(String, Duration)> {
+        debug!("Fetching new FedEx OAuth token");
 
-        let response = ureq::post(TOKEN_URL)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .send(form_body.as_bytes())
+        let request = self.token_request();
+        let body = http::retry_with_backoff(&self.retry, || http::send(&request))
             .context("FedEx OAuth token request failed")?;
+        Self::parse_token(&body)
+    }
 
-        let body: serde_json::Value = response
-            .into_body()
-            .read_json()
-            .context("Failed to parse FedEx token response")?;
+    fn token_request(&self) -> HttpRequest {
+        HttpRequest::post(format!("{}{TOKEN_PATH}", self.api_url)).form(format!(
+            "grant_type=client_credentials&client_id={}&client_secret={}",
+            self.client_id, self.client_secret
+        ))
+    }
 
+    fn parse_token(body: &Value) -> Result<(String, Duration)> {
         let access_token = body["access_token"]
             .as_str()
             .context("Missing access_token in FedEx response")?
@@ -75,52 +111,106 @@ impl FedexClient {
         Ok((access_token, ttl))
     }
 
-    fn map_status_code(code: &str) -> PackageStatus {
-        match code {
-            "DL" => PackageStatus::Delivered,
-            "OC" => PackageStatus::Waiting,
-            _ => PackageStatus::InTransit,
+    fn track_request(&self, token: &str, request_body: Value) -> HttpRequest {
+        HttpRequest::post(format!("{}{TRACK_PATH}", self.api_url))
+            .header("Authorization", format!("Bearer {token}"))
+            .json(request_body)
+    }
+
+    /// Where FedEx left a delivered package, from `deliveryDetails`. Prefers the
+    /// free-text `locationDescription`, falling back to the `locationType` enum
+    /// (e.g. `FRONT_PORCH` → "Front Porch").
+    fn delivery_location_detail(track_result: &serde_json::Value) -> Option<String> {
+        let details = &track_result["deliveryDetails"];
+        details["locationDescription"]
+            .as_str()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim().to_string())
+            .or_else(|| {
+                details["locationType"]
+                    .as_str()
+                    .filter(|s| !s.is_empty())
+                    .map(title_case_enum)
+            })
+    }
+
+    /// The track result to report. A reused tracking number can return several
+    /// results; pick the one with the most recent event date rather than the first.
+    fn latest_track_result<'a>(body: &'a Value, tracking_number: &str) -> &'a Value {
+        static MISSING: Value = Value::Null;
+
+        let results: Vec<&Value> = body["output"]["completeTrackResults"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|complete| complete["trackResults"].as_array().into_iter().flatten())
+            .collect();
+
+        if results.len() > 1 {
+            warn!(
+                tracking_number = %redact::tracking_number(tracking_number),
+                count = results.len(),
+                "FedEx returned multiple shipments, using the most recent"
+            );
         }
+
+        // max_by_key keeps the last maximum; reverse so ties keep response order
+        results
+            .into_iter()
+            .rev()
+            .max_by_key(|result| {
+                result["dateAndTimes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|d| d["dateTime"].as_str())
+                    .filter_map(|d| DateTime::parse_from_rfc3339(d).ok())
+                    .max()
+            })
+            .unwrap_or(&MISSING)
     }
-}
 
-impl CourierClient for FedexClient {
-    fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
-        let token = self.get_token()?;
+    /// FedEx door tag numbers ("DT" plus 12 digits) can only be tracked
+    /// together with the destination ZIP code.
+    fn is_door_tag(tracking_number: &str) -> bool {
+        tracking_number
+            .strip_prefix("DT")
+            .is_some_and(|digits| digits.len() == 12 && digits.chars().all(|c| c.is_ascii_digit()))
+    }
 
-        let request_body = json!({
-            "trackingInfo": [{
-                "trackingNumberInfo": {
-                    "trackingNumber": package.tracking_number
-                }
-            }],
-            "includeDetailedScans": false
+    fn track_request_body(package: &Package) -> Result<Value, CourierError> {
+        let mut tracking_info = json!({
+            "trackingNumberInfo": {
+                "trackingNumber": package.tracking_number
+            }
         });
 
-        let response = ureq::post(TRACK_URL)
-            .header("Authorization", &format!("Bearer {token}"))
-            .header("Content-Type", "application/json")
-            .send_json(&request_body)
-            .context("FedEx track request failed")?;
+        if Self::is_door_tag(&package.tracking_number) {
+            let zip = package.destination_zip.as_deref().ok_or(CourierError::MissingDestinationZip)?;
+            tracking_info["destinationPostalCode"] = json!(zip);
+            tracking_info["destinationCountryCode"] = json!("US");
+        }
 
-        let body: serde_json::Value = response
-            .into_body()
-            .read_json()
-            .context("Failed to parse FedEx track response")?;
+        Ok(json!({
+            "trackingInfo": [tracking_info],
+            "includeDetailedScans": true
+        }))
+    }
 
+    fn parse_track_response(package: &Package, body: &Value) -> Result<Vec<CourierStatus>> {
         // Navigate the FedEx response structure:
-        // output.completeTrackResults[0].trackResults[0].latestStatusDetail.code
-        let track_result = &body["output"]["completeTrackResults"][0]["trackResults"][0];
+        // output.completeTrackResults[].trackResults[].latestStatusDetail.code
+        let track_result = Self::latest_track_result(body, &package.tracking_number);
 
         // Check for tracking-number-not-found errors
         if let Some(error) = track_result["error"].as_object() {
             let code = error.get("code").and_then(|c| c.as_str()).unwrap_or("");
             warn!(
-                tracking_number = %package.tracking_number,
+                tracking_number = %redact::tracking_number(&package.tracking_number),
                 error_code = code,
                 "FedEx tracking error"
             );
-            return Ok(vec![]);
+            return Err(CourierError::from_fedex_code(code).into());
         }
 
         let status_code = track_result["latestStatusDetail"]["code"]
@@ -142,35 +232,349 @@ impl CourierClient for FedexClient {
                     .map(|s| s.to_string());
 
                 // Extract last known location from latestStatusDetail.scanLocation
-                let scan_location = &track_result["latestStatusDetail"]["scanLocation"];
-                let last_known_location = scan_location["city"].as_str().map(|city| {
-                    match scan_location["stateOrProvinceCode"].as_str() {
-                        Some(state) => format!("{city}, {state}"),
-                        None => city.to_string(),
-                    }
-                });
+                let last_known_location = Self::scan_location(&track_result["latestStatusDetail"]["scanLocation"]);
 
                 debug!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     fedex_code = code,
                     mapped_status = %mapped,
                     "FedEx status retrieved"
                 );
-                Ok(vec![CourierStatus {
-                    status: mapped.to_string(),
-                    estimated_arrival_date,
-                    last_known_location,
-                    description: None,
-                    checked_at: None,
-                }])
+                let delivery_location_detail = if mapped == PackageStatus::Delivered {
+                    Self::delivery_location_detail(track_result)
+                } else {
+                    None
+                };
+                let courier_status_text = track_result["latestStatusDetail"]["description"]
+                    .as_str()
+                    .map(|s| s.to_string());
+
+                let events: &[Value] = track_result["scanEvents"].as_array().map_or(&[], |e| e.as_slice());
+                if events.is_empty() {
+                    return Ok(vec![CourierStatus {
+                        status: mapped.to_string(),
+                        estimated_arrival_date,
+                        last_known_location,
+                        description: None,
+                        checked_at: None,
+                        delivery_location_detail,
+                        courier_status_text,
+                        courier_status_code: Some(code.to_string()),
+                    }]);
+                }
+
+                // Scan events are newest first; reverse so the newest gets the
+                // highest DB id and sets the current status
+                let mut statuses = Vec::new();
+                for (i, event) in events.iter().rev().enumerate() {
+                    let is_latest = i == events.len() - 1;
+                    let event_code = event["derivedStatusCode"].as_str().or(event["eventType"].as_str());
+
+                    // Use the overall status for the most recent event and each
+                    // event's own code for the history
+                    let status = if is_latest {
+                        mapped
+                    } else {
+                        event_code.map_or(PackageStatus::InTransit, Self::map_status_code)
+                    };
+
+                    statuses.push(CourierStatus {
+                        status: status.to_string(),
+                        estimated_arrival_date: estimated_arrival_date.clone(),
+                        last_known_location: Self::scan_location(&event["scanLocation"])
+                            .or_else(|| if is_latest { last_known_location.clone() } else { None }),
+                        description: event["eventDescription"]
+                            .as_str()
+                            .map(str::trim)
+                            .filter(|s| !s.is_empty())
+                            .map(|s| s.to_string()),
+                        checked_at: event["date"].as_str().and_then(normalize_timestamp),
+                        delivery_location_detail: if is_latest { delivery_location_detail.clone() } else { None },
+                        courier_status_text: if is_latest { courier_status_text.clone() } else { None },
+                        courier_status_code: if is_latest { Some(code.to_string()) } else { event_code.map(|c| c.to_string()) },
+                    });
+                }
+                Ok(statuses)
             }
             None => {
                 debug!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     "No status code in FedEx response"
                 );
                 Ok(vec![])
             }
         }
     }
+
+    /// "City, ST" from a `scanLocation`, or just the city.
+    fn scan_location(location: &Value) -> Option<String> {
+        let city = location["city"].as_str().filter(|s| !s.is_empty())?;
+        Some(match location["stateOrProvinceCode"].as_str().filter(|s| !s.is_empty()) {
+            Some(state) => format!("{city}, {state}"),
+            None => city.to_string(),
+        })
+    }
+
+    fn map_status_code(code: &str) -> PackageStatus {
+        match code {
+            "DL" => PackageStatus::Delivered,
+            "OC" => PackageStatus::Waiting,
+            _ => PackageStatus::InTransit,
+        }
+    }
+}
+
+impl CourierClient for FedexClient {
+    fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
+        let request_body = Self::track_request_body(package)?;
+        let token = self.get_token()?;
+        let request = self.track_request(&token, request_body);
+        let body = http::retry_with_backoff(&self.retry, || http::send(&request)).context("FedEx track request failed")?;
+        Self::parse_track_response(package, &body)
+    }
+
+    fn check_status_async(self: Arc<Self>, client: reqwest::Client, package: Package) -> CourierFuture {
+        Box::pin(async move {
+            let request_body = Self::track_request_body(&package)?;
+            let token = self.get_token_async(&client).await?;
+            let request = self.track_request(&token, request_body);
+            let body = http::retry_with_backoff_async(&self.retry, || http::send_async(&client, &request))
+                .await
+                .context("FedEx track request failed")?;
+            Self::parse_track_response(&package, &body)
+        })
+    }
+}
+
+/// A FedEx scan time with a UTC offset, e.g. `2026-03-01T08:00:00-05:00`, as
+/// RFC 3339 UTC.
+fn normalize_timestamp(value: &str) -> Option<String> {
+    let utc = DateTime::parse_from_rfc3339(value).ok()?.with_timezone(&Utc);
+    Some(format_rfc3339_utc(
+        utc.year() as u32,
+        utc.month(),
+        utc.day(),
+        utc.hour(),
+        utc.minute(),
+        utc.second(),
+    ))
+}
+
+/// `FRONT_PORCH` → "Front Porch".
+fn title_case_enum(value: &str) -> String {
+    value
+        .split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let lower = word.to_lowercase();
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_delivery_location_from_delivered_result() {
+        let track_result = json!({
+            "latestStatusDetail": { "code": "DL", "description": "Delivered" },
+            "deliveryDetails": { "locationType": "FRONT_PORCH" }
+        });
+        assert_eq!(
+            FedexClient::delivery_location_detail(&track_result).as_deref(),
+            Some("Front Porch")
+        );
+
+        let track_result = json!({
+            "deliveryDetails": { "locationDescription": "Mailroom", "locationType": "SHIPPING_RECEIVING" }
+        });
+        assert_eq!(FedexClient::delivery_location_detail(&track_result).as_deref(), Some("Mailroom"));
+
+        assert_eq!(FedexClient::delivery_location_detail(&json!({})), None);
+    }
+
+    #[test]
+    fn picks_most_recent_of_multiple_track_results() {
+        let body = json!({
+            "output": { "completeTrackResults": [{ "trackResults": [
+                {
+                    "latestStatusDetail": { "code": "DL" },
+                    "dateAndTimes": [{ "type": "ACTUAL_DELIVERY", "dateTime": "2024-05-01T10:00:00-05:00" }]
+                },
+                {
+                    "latestStatusDetail": { "code": "IT" },
+                    "dateAndTimes": [{ "type": "SHIP", "dateTime": "2026-03-01T08:00:00-05:00" }]
+                }
+            ]}]}
+        });
+
+        let result = FedexClient::latest_track_result(&body, "986578788855");
+
+        assert_eq!(result["latestStatusDetail"]["code"], "IT");
+    }
+
+    #[test]
+    fn builds_history_from_detailed_scans() {
+        let body = json!({
+            "output": { "completeTrackResults": [{ "trackResults": [{
+                "latestStatusDetail": {
+                    "code": "DL",
+                    "description": "Delivered",
+                    "scanLocation": { "city": "AUSTIN", "stateOrProvinceCode": "TX" }
+                },
+                "deliveryDetails": { "locationType": "FRONT_PORCH" },
+                "scanEvents": [
+                    {
+                        "date": "2026-03-03T14:12:00-06:00",
+                        "eventType": "DL",
+                        "eventDescription": "Delivered",
+                        "derivedStatusCode": "DL",
+                        "scanLocation": { "city": "AUSTIN", "stateOrProvinceCode": "TX" }
+                    },
+                    {
+                        "date": "2026-03-02T03:40:00-06:00",
+                        "eventType": "AR",
+                        "eventDescription": "Arrived at FedEx location",
+                        "derivedStatusCode": "IT",
+                        "scanLocation": { "city": "MEMPHIS", "stateOrProvinceCode": "TN" }
+                    },
+                    {
+                        "date": "2026-03-01T18:05:00-05:00",
+                        "eventType": "OC",
+                        "eventDescription": "Shipment information sent to FedEx",
+                        "derivedStatusCode": "OC",
+                        "scanLocation": {}
+                    }
+                ]
+            }]}]}
+        });
+
+        let statuses = FedexClient::parse_track_response(&package("986578788855", None), &body).unwrap();
+
+        let summary: Vec<(&str, Option<&str>, Option<&str>)> = statuses
+            .iter()
+            .map(|s| (s.status.as_str(), s.checked_at.as_deref(), s.last_known_location.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("waiting", Some("2026-03-01T23:05:00Z"), None),
+                ("in_transit", Some("2026-03-02T09:40:00Z"), Some("MEMPHIS, TN")),
+                ("delivered", Some("2026-03-03T20:12:00Z"), Some("AUSTIN, TX")),
+            ]
+        );
+        assert_eq!(statuses[1].description.as_deref(), Some("Arrived at FedEx location"));
+        assert_eq!(statuses[2].delivery_location_detail.as_deref(), Some("Front Porch"));
+        assert_eq!(statuses[0].delivery_location_detail, None);
+    }
+
+    #[test]
+    fn reports_unknown_numbers_as_not_found() {
+        let body = json!({
+            "output": { "completeTrackResults": [{ "trackResults": [{
+                "trackingNumberInfo": { "trackingNumber": "986578788855" },
+                "error": {
+                    "code": "TRACKING.TRACKINGNUMBER.NOTFOUND",
+                    "message": "Tracking number cannot be found. Please correct the tracking number and try again."
+                }
+            }]}]}
+        });
+
+        let err = FedexClient::parse_track_response(&package("986578788855", None), &body).err().unwrap();
+
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::NotFound));
+    }
+
+    /// Log output captured from a `tracing` subscriber.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn logs_masked_tracking_number() {
+        let body = json!({
+            "output": { "completeTrackResults": [{ "trackResults": [{
+                "trackingNumberInfo": { "trackingNumber": "986578788855" },
+                "error": { "code": "TRACKING.TRACKINGNUMBER.NOTFOUND" }
+            }]}]}
+        });
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(FedexClient::parse_track_response(&package("986578788855", None), &body).is_err());
+        });
+
+        let logged = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logged.contains("tracking_number=********8855"), "{logged}");
+        assert!(!logged.contains("986578788855"), "{logged}");
+    }
+
+    fn package(tracking_number: &str, destination_zip: Option<&str>) -> Package {
+        Package {
+            id: 1,
+            tracking_number: tracking_number.into(),
+            courier: "fedex".into(),
+            service: "FedEx".into(),
+            status: PackageStatus::Waiting,
+            destination_zip: destination_zip.map(String::from),
+        }
+    }
+
+    #[test]
+    fn door_tag_request_includes_destination_zip() {
+        let body = FedexClient::track_request_body(&package("DT123456789012", Some("78701"))).unwrap();
+        assert_eq!(body["trackingInfo"][0]["destinationPostalCode"], "78701");
+
+        let body = FedexClient::track_request_body(&package("986578788855", Some("78701"))).unwrap();
+        assert!(body["trackingInfo"][0].get("destinationPostalCode").is_none());
+    }
+
+    #[test]
+    fn door_tag_without_zip_is_not_queried() {
+        let err = FedexClient::track_request_body(&package("DT123456789012", None)).unwrap_err();
+        assert_eq!(err, CourierError::MissingDestinationZip);
+    }
+
+    fn client(environment: FedexEnvironment) -> FedexClient {
+        FedexClient::new(&FedexConfig {
+            client_id: String::new(),
+            client_secret: String::new(),
+            environment,
+            retry: RetryConfig::default(),
+        })
+    }
+
+    #[test]
+    fn environment_selects_api_urls() {
+        let production = client(FedexEnvironment::default());
+        assert_eq!(production.token_request().url, "https://apis.fedex.com/oauth/token");
+        assert_eq!(production.track_request("t", json!({})).url, "https://apis.fedex.com/track/v1/trackingnumbers");
+
+        let sandbox = client(FedexEnvironment::Sandbox);
+        assert_eq!(sandbox.token_request().url, "https://apis-sandbox.fedex.com/oauth/token");
+        assert_eq!(sandbox.track_request("t", json!({})).url, "https://apis-sandbox.fedex.com/track/v1/trackingnumbers");
+    }
 }
@@ -0,0 +1,284 @@
+//! HTTP transport for the courier API clients. Clients describe each request
+//! as an [`HttpRequest`] and parse the JSON response themselves, so the same
+//! client can be driven by blocking ureq (the default) or async reqwest
+//! (`status.async_requests`). Transient failures are retried with
+//! [`retry_with_backoff`].
+
+use crate::config::RetryConfig;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+use tracing::warn;
+
+pub enum Method {
+    Get,
+    Post,
+}
+
+pub enum Body {
+    Empty,
+    Json(Value),
+    /// An `application/x-www-form-urlencoded` body.
+    Form(String),
+}
+
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Body,
+}
+
+impl HttpRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self { method: Method::Get, url: url.into(), headers: Vec::new(), body: Body::Empty }
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self { method: Method::Post, url: url.into(), headers: Vec::new(), body: Body::Empty }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn json(mut self, body: Value) -> Self {
+        self.body = Body::Json(body);
+        self
+    }
+
+    pub fn form(mut self, body: impl Into<String>) -> Self {
+        self.body = Body::Form(body.into());
+        self
+    }
+}
+
+/// A response with a non-success HTTP status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpStatusError {
+    pub status: u16,
+    /// The response's `Retry-After` delay, if it gave one in seconds.
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpStatusError {
+    fn new(status: u16, retry_after: Option<&str>) -> Self {
+        Self { status, retry_after: retry_after.and_then(|value| value.trim().parse().ok()).map(Duration::from_secs) }
+    }
+}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP status {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Longest `Retry-After` honored; a courier asking for more fails the request
+/// rather than stalling the poll.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Send a request, blocking the calling thread, and parse the JSON response.
+pub fn send(request: &HttpRequest) -> Result<Value> {
+    let body = send_text(request)?;
+    serde_json::from_str(&body).context("Failed to parse response JSON")
+}
+
+/// Send a request, blocking the calling thread, and return the response body
+/// unparsed, for couriers that don't answer in JSON.
+pub fn send_text(request: &HttpRequest) -> Result<String> {
+    let result = match request.method {
+        Method::Get => {
+            let mut builder = ureq::get(&request.url).config().http_status_as_error(false).build();
+            for (name, value) in &request.headers {
+                builder = builder.header(*name, value);
+            }
+            builder.call()
+        }
+        Method::Post => {
+            let mut builder = ureq::post(&request.url).config().http_status_as_error(false).build();
+            for (name, value) in &request.headers {
+                builder = builder.header(*name, value);
+            }
+            match &request.body {
+                Body::Empty => builder.send_empty(),
+                Body::Json(body) => builder.send_json(body),
+                Body::Form(body) => builder.header("Content-Type", FORM_CONTENT_TYPE).send(body.as_bytes()),
+            }
+        }
+    };
+
+    let response = result?;
+    if !response.status().is_success() {
+        let retry_after = response.headers().get("retry-after").and_then(|value| value.to_str().ok());
+        return Err(HttpStatusError::new(response.status().as_u16(), retry_after).into());
+    }
+
+    Ok(response.into_body().read_to_string()?)
+}
+
+/// Send a request without blocking and parse the JSON response.
+pub async fn send_async(client: &reqwest::Client, request: &HttpRequest) -> Result<Value> {
+    let body = send_text_async(client, request).await?;
+    serde_json::from_str(&body).context("Failed to parse response JSON")
+}
+
+/// [`send_text`] without blocking.
+pub async fn send_text_async(client: &reqwest::Client, request: &HttpRequest) -> Result<String> {
+    let mut builder = match request.method {
+        Method::Get => client.get(&request.url),
+        Method::Post => client.post(&request.url),
+    };
+    for (name, value) in &request.headers {
+        builder = builder.header(*name, value);
+    }
+    builder = match &request.body {
+        Body::Empty => builder,
+        Body::Json(body) => builder.json(body),
+        Body::Form(body) => builder.header("Content-Type", FORM_CONTENT_TYPE).body(body.clone()),
+    };
+
+    let response = builder.send().await?;
+    if !response.status().is_success() {
+        let retry_after = response.headers().get("retry-after").and_then(|value| value.to_str().ok());
+        return Err(HttpStatusError::new(response.status().as_u16(), retry_after).into());
+    }
+
+    Ok(response.text().await?)
+}
+
+/// Call `attempt` until it succeeds, fails permanently, or has been retried
+/// `config.max_retries` times, sleeping between attempts.
+pub fn retry_with_backoff<T>(config: &RetryConfig, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut retries = 0;
+    loop {
+        match attempt() {
+            Err(err) if retries < config.max_retries => match retry_delay(config, retries, &err) {
+                Some(delay) => {
+                    warn!(error = %err, retry_in_ms = delay.as_millis() as u64, "Courier request failed, retrying");
+                    std::thread::sleep(delay);
+                    retries += 1;
+                }
+                None => return Err(err),
+            },
+            result => return result,
+        }
+    }
+}
+
+/// [`retry_with_backoff`] for async requests.
+pub async fn retry_with_backoff_async<T, F>(config: &RetryConfig, mut attempt: impl FnMut() -> F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(err) if retries < config.max_retries => match retry_delay(config, retries, &err) {
+                Some(delay) => {
+                    warn!(error = %err, retry_in_ms = delay.as_millis() as u64, "Courier request failed, retrying");
+                    tokio::time::sleep(delay).await;
+                    retries += 1;
+                }
+                None => return Err(err),
+            },
+            result => return result,
+        }
+    }
+}
+
+/// How long to wait before retrying after `err`, or `None` if retrying
+/// won't help. Timeouts, connection failures, 429 and 5xx are transient; a
+/// 429's `Retry-After` replaces the backoff.
+fn retry_delay(config: &RetryConfig, retries: u32, err: &anyhow::Error) -> Option<Duration> {
+    if let Some(status) = err.downcast_ref::<HttpStatusError>() {
+        if status.status == 429
+            && let Some(retry_after) = status.retry_after
+        {
+            return (retry_after <= MAX_RETRY_AFTER).then_some(retry_after);
+        }
+        if status.status != 429 && status.status < 500 {
+            return None;
+        }
+    } else if err.downcast_ref::<ureq::Error>().is_none() && err.downcast_ref::<reqwest::Error>().is_none() {
+        return None;
+    }
+
+    let backoff = Duration::from_millis(config.retry_base_delay_ms).saturating_mul(1 << retries.min(16));
+    Some(with_jitter(backoff))
+}
+
+/// A random delay between half of `delay` and all of it, so clients retrying
+/// together don't all hit the API again at the same moment.
+fn with_jitter(delay: Duration) -> Duration {
+    let fraction = RandomState::new().hash_one(()) as f64 / u64::MAX as f64;
+    delay.mul_f64(0.5 + fraction / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Serve `GET /` on a background thread, failing with 503 for the first
+    /// `failures` requests. Returns the URL and the request count.
+    fn flaky_server(failures: u32) -> (String, Arc<AtomicU32>) {
+        let requests = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&requests);
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(move || async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                    Err(axum::http::StatusCode::SERVICE_UNAVAILABLE)
+                } else {
+                    Ok(axum::Json(serde_json::json!({ "ok": true })))
+                }
+            }),
+        );
+
+        (format!("{}/", serve(app)), requests)
+    }
+
+    fn retry_config(max_retries: u32) -> RetryConfig {
+        RetryConfig { max_retries, retry_base_delay_ms: 1 }
+    }
+
+    #[test]
+    fn retries_service_unavailable_until_success() {
+        let (url, requests) = flaky_server(2);
+
+        let body = retry_with_backoff(&retry_config(3), || send(&HttpRequest::get(&url))).unwrap();
+
+        assert_eq!(body["ok"], true);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let (url, requests) = flaky_server(5);
+
+        let err = retry_with_backoff(&retry_config(2), || send(&HttpRequest::get(&url))).unwrap_err();
+
+        assert_eq!(err.downcast_ref::<HttpStatusError>().map(|e| e.status), Some(503));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn honors_retry_after_and_skips_client_errors() {
+        let config = retry_config(3);
+        let rate_limited = anyhow::Error::from(HttpStatusError::new(429, Some("2")));
+        let not_found = anyhow::Error::from(HttpStatusError::new(404, None));
+
+        assert_eq!(retry_delay(&config, 0, &rate_limited), Some(Duration::from_secs(2)));
+        assert_eq!(retry_delay(&config, 0, &not_found), None);
+        assert_eq!(retry_delay(&config, 0, &anyhow::anyhow!("Missing access_token")), None);
+    }
+}
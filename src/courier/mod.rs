@@ -1,14 +1,153 @@
+pub mod canadapost;
 pub mod fedex;
+pub mod http;
 pub mod ups;
 pub mod ups_web;
 pub mod usps;
 
+use crate::config::{CourierConfig, UpsClientKind};
 use crate::db::Package;
+use crate::redact;
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use std::collections::HashMap;
 use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
 use std::str::FromStr;
-use tracing::warn;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Prefix → phrase pairs used to shorten verbose scan descriptions. Checked
+/// in order against the start of the lowercased description, up to a word
+/// boundary; the first match wins. A `None` phrase keeps the description as
+/// is, for wording a shorter phrase would misrepresent.
+const SHORT_DESCRIPTIONS: &[(&str, Option<&str>)] = &[
+    ("delivery attempted", Some("Delivery attempted")),
+    ("delivery attempt", Some("Delivery attempted")),
+    ("attempted delivery", Some("Delivery attempted")),
+    ("out for delivery", Some("Out for delivery")),
+    ("available for pickup", Some("Ready for pickup")),
+    ("ready for pickup", Some("Ready for pickup")),
+    // Left with someone else; the full description says who
+    ("delivered to agent", None),
+    ("delivered to neighbour", None),
+    ("delivered to neighbor", None),
+    ("delivered", Some("Delivered")),
+    ("delivery exception", Some("Delivery exception")),
+    ("exception", Some("Delivery exception")),
+    ("delayed", Some("Delayed")),
+    ("shipping label created", Some("Label created")),
+    ("label created", Some("Label created")),
+    ("pre-shipment", Some("Label created")),
+    ("awaiting item", Some("Label created")),
+    ("picked up", Some("Picked up")),
+    ("accepted", Some("Picked up")),
+    ("origin scan", Some("Picked up")),
+    ("arrived", Some("Arrived at facility")),
+    ("departed", Some("Departed facility")),
+    ("processed", Some("Processed at facility")),
+    ("in transit", Some("In transit")),
+];
+
+/// Short, human description for a verbose courier scan description, e.g.
+/// "Arrived at USPS Regional Origin Facility, ..." → "Arrived at facility".
+pub fn short_description(description: &str) -> Option<&'static str> {
+    let lower = description.trim_start().to_lowercase();
+    SHORT_DESCRIPTIONS
+        .iter()
+        .find(|(prefix, _)| {
+            lower
+                .strip_prefix(prefix)
+                .is_some_and(|rest| !rest.starts_with(char::is_alphanumeric))
+        })
+        .and_then(|(_, phrase)| *phrase)
+}
+
+/// A courier-reported failure, mapped from the error codes in FedEx and USPS
+/// error envelopes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CourierError {
+    /// The courier has no record of the tracking number.
+    NotFound,
+    /// Too many requests; retry later.
+    RateLimited,
+    /// Credentials were rejected.
+    Unauthorized,
+    /// The tracking number or request was malformed.
+    InvalidRequest,
+    /// The courier's service is down or timed out.
+    Unavailable,
+    /// The courier needs the destination ZIP code to look up this number.
+    MissingDestinationZip,
+    /// Not sent: the courier recently rate-limited us and is cooling down.
+    CoolingDown,
+    /// Not sent: trackage is shutting down while the request waited for its
+    /// rate limit slot.
+    ShuttingDown,
+    /// Any other code, kept verbatim.
+    Other(String),
+}
+
+impl CourierError {
+    /// Map a FedEx Track API `error.code`, e.g. `TRACKING.TRACKINGNUMBER.NOTFOUND`.
+    pub fn from_fedex_code(code: &str) -> Self {
+        match code {
+            "TRACKING.TRACKINGNUMBER.NOTFOUND" | "TRACKINGNUMBER.NOTFOUND" => Self::NotFound,
+            "TRACKING.TRACKINGNUMBER.INVALID" | "TRACKING.TRACKINGNUMBER.EMPTY" => Self::InvalidRequest,
+            "NOT.AUTHORIZED.ERROR" | "UNAUTHORIZED" | "FORBIDDEN.ERROR" => Self::Unauthorized,
+            "SERVICE.UNAVAILABLE.ERROR" | "INTERNAL.SERVER.ERROR" => Self::Unavailable,
+            // FedEx reports throttling with numeric 9xx codes
+            code if code.starts_with('9') && code.chars().all(|c| c.is_ascii_digit()) => Self::RateLimited,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Map a USPS Tracking API v3 `error.code`, which mirrors the HTTP status.
+    pub fn from_usps_code(code: &str) -> Self {
+        match code {
+            "404" => Self::NotFound,
+            "429" => Self::RateLimited,
+            "401" | "403" => Self::Unauthorized,
+            "400" => Self::InvalidRequest,
+            "500" | "502" | "503" | "504" => Self::Unavailable,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Map a Canada Post `message` code, e.g. `004` ("No Pin History").
+    pub fn from_canada_post_code(code: &str) -> Self {
+        match code {
+            "004" => Self::NotFound,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Human-readable explanation, suitable for showing alongside a package.
+    pub fn message(&self) -> String {
+        match self {
+            Self::NotFound => "Tracking number not found by courier".into(),
+            Self::RateLimited => "Courier rate limit reached, will retry later".into(),
+            Self::Unauthorized => "Courier rejected the API credentials".into(),
+            Self::InvalidRequest => "Courier rejected the tracking number as invalid".into(),
+            Self::Unavailable => "Courier service is temporarily unavailable".into(),
+            Self::MissingDestinationZip => "Courier requires a destination ZIP code for this number".into(),
+            Self::CoolingDown => "Courier is cooling down after a rate limit, will retry later".into(),
+            Self::ShuttingDown => "Shutting down, courier request not sent".into(),
+            Self::Other(code) => format!("Courier error {code}"),
+        }
+    }
+}
+
+impl fmt::Display for CourierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl std::error::Error for CourierError {}
 
 pub struct CourierStatus {
     pub status: String,
@@ -16,49 +155,296 @@ pub struct CourierStatus {
     pub last_known_location: Option<String>,
     pub description: Option<String>,
     pub checked_at: Option<String>,
+    /// Where a delivered package was left, e.g. "Front Porch".
+    pub delivery_location_detail: Option<String>,
+    /// The courier's own status wording, e.g. "In transit, arriving late".
+    pub courier_status_text: Option<String>,
+    /// The courier's raw status code behind `status`, e.g. FedEx "DL".
+    pub courier_status_code: Option<String>,
 }
 
-pub trait CourierClient: Send {
+/// The pending result of [`CourierClient::check_status_async`].
+pub type CourierFuture = Pin<Box<dyn Future<Output = Result<Vec<CourierStatus>>> + Send>>;
+
+pub trait CourierClient: Send + Sync + 'static {
     fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>>;
+
+    /// Check a package over async HTTP, for `status.async_requests`. Clients
+    /// without an async implementation make the blocking call on tokio's
+    /// blocking thread pool, where it can neither stall the runtime nor trip
+    /// over a blocking HTTP client's own runtime.
+    fn check_status_async(self: Arc<Self>, _http: reqwest::Client, package: Package) -> CourierFuture {
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || self.check_status(&package))
+                .await
+                .unwrap_or_else(|err| Err(anyhow::anyhow!("Courier status check panicked: {err}")))
+        })
+    }
 }
 
+/// Default for [`CourierRouter::with_rate_limit_cooldown`].
+const DEFAULT_RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Longest single sleep while a request waits for its rate limit slot, so a
+/// shutdown is noticed promptly.
+const SLOT_WAIT_STEP: Duration = Duration::from_millis(100);
+
 pub struct CourierRouter {
-    clients: HashMap<String, Box<dyn CourierClient>>,
+    clients: HashMap<String, Arc<dyn CourierClient>>,
+    rate_limit_cooldown: Duration,
+    /// Per courier, when requests may resume after a rate limit.
+    cooldowns: Mutex<HashMap<String, Instant>>,
+    /// Per courier, the minimum time between requests.
+    request_intervals: HashMap<String, Duration>,
+    /// Per courier, when the next request may be sent.
+    next_slots: Mutex<HashMap<String, Instant>>,
+    /// Cleared on shutdown; stops waiting for a rate limit slot.
+    running: Arc<AtomicBool>,
 }
 
 impl CourierRouter {
     pub fn new() -> Self {
         Self {
             clients: HashMap::new(),
+            rate_limit_cooldown: DEFAULT_RATE_LIMIT_COOLDOWN,
+            cooldowns: Mutex::new(HashMap::new()),
+            request_intervals: HashMap::new(),
+            next_slots: Mutex::new(HashMap::new()),
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Build a router with a client for every courier that has credentials
+    /// configured. UPS uses the API or the credential-free web client as
+    /// `ups_client` chooses.
+    pub fn from_config(config: &CourierConfig) -> Self {
+        let mut router = Self::new().with_rate_limit_cooldown(Duration::from_secs(config.rate_limit_cooldown_seconds));
+        for (courier, requests_per_minute) in &config.requests_per_minute {
+            match courier.parse::<CourierCode>() {
+                Ok(code) => router = router.with_requests_per_minute(&code, *requests_per_minute),
+                Err(err) => warn!(error = %err, "Ignoring courier.requests_per_minute entry"),
+            }
+        }
+        if let Some(ref fedex_config) = config.fedex {
+            info!("FedEx courier client enabled");
+            router.register(&CourierCode::FedEx, Box::new(fedex::FedexClient::new(fedex_config)));
+        }
+        match (&config.ups, config.ups_client) {
+            (Some(ups_config), UpsClientKind::Auto | UpsClientKind::Api) => {
+                info!("UPS courier client enabled (API)");
+                router.register(&CourierCode::UPS, Box::new(ups::UpsClient::new(ups_config)));
+            }
+            (None, UpsClientKind::Api) => warn!("courier.ups_client is api but courier.ups isn't configured"),
+            (_, UpsClientKind::Auto | UpsClientKind::Web) => {
+                info!("UPS courier client enabled (web)");
+                router.register(&CourierCode::UPS, Box::new(ups_web::UpsWebClient::new()));
+            }
+        }
+        if let Some(ref usps_config) = config.usps {
+            info!("USPS courier client enabled");
+            router.register(&CourierCode::USPS, Box::new(usps::UspsClient::new(usps_config)));
+        }
+        if let Some(ref canada_post_config) = config.canada_post {
+            info!("Canada Post courier client enabled");
+            router.register(&CourierCode::CanadaPost, Box::new(canadapost::CanadaPostClient::new(canada_post_config)));
         }
+
+        router
+    }
+
+    /// How long to stop sending requests to a courier after it rate-limits
+    /// one, when it doesn't say how long with `Retry-After`.
+    pub fn with_rate_limit_cooldown(mut self, cooldown: Duration) -> Self {
+        self.rate_limit_cooldown = cooldown;
+        self
+    }
+
+    /// Space requests to a courier at least `60 / requests_per_minute`
+    /// seconds apart.
+    pub fn with_requests_per_minute(mut self, courier_code: &CourierCode, requests_per_minute: u32) -> Self {
+        let interval = Duration::from_secs(60) / requests_per_minute.max(1);
+        self.request_intervals.insert(courier_code.to_string(), interval);
+        self
+    }
+
+    /// Stop waiting for rate limit slots once `running` is cleared.
+    pub fn with_shutdown(mut self, running: Arc<AtomicBool>) -> Self {
+        self.running = running;
+        self
     }
 
     pub fn register(&mut self, courier_code: &CourierCode, client: Box<dyn CourierClient>) {
-        self.clients.insert(courier_code.to_string(), client);
+        self.clients.insert(courier_code.to_string(), Arc::from(client));
+    }
+
+    /// Carry over `previous`'s rate limit cooldowns and request slots, so
+    /// swapping in a rebuilt router doesn't lift them.
+    fn inherit_rate_limits(&self, previous: &CourierRouter) {
+        let cooldowns = previous.cooldowns.lock().unwrap().clone();
+        self.cooldowns.lock().unwrap().extend(cooldowns);
+        let next_slots = previous.next_slots.lock().unwrap().clone();
+        self.next_slots.lock().unwrap().extend(next_slots);
+    }
+
+    /// Fail fast while `courier` is cooling down after a rate limit.
+    fn check_cooldown(&self, package: &Package) -> Result<()> {
+        let mut cooldowns = self.cooldowns.lock().unwrap();
+        match cooldowns.get(&package.courier) {
+            Some(until) if Instant::now() < *until => {
+                debug!(
+                    courier = %package.courier,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
+                    "Courier cooling down after a rate limit, skipping"
+                );
+                Err(CourierError::CoolingDown.into())
+            }
+            Some(_) => {
+                cooldowns.remove(&package.courier);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Claim the next request slot for the package's courier and return when
+    /// it starts, or `None` if the courier isn't rate limited.
+    fn reserve_slot(&self, package: &Package) -> Option<Instant> {
+        let interval = *self.request_intervals.get(&package.courier)?;
+        let mut slots = self.next_slots.lock().unwrap();
+        let now = Instant::now();
+        let start = slots.get(&package.courier).map_or(now, |next| (*next).max(now));
+        slots.insert(package.courier.clone(), start + interval);
+        Some(start)
+    }
+
+    /// How much longer to sleep before a slot starting at `start`, or an error
+    /// if trackage is shutting down.
+    fn slot_wait(&self, start: Instant) -> Result<Option<Duration>> {
+        let now = Instant::now();
+        if now >= start {
+            return Ok(None);
+        }
+        if !self.running.load(Ordering::SeqCst) {
+            return Err(CourierError::ShuttingDown.into());
+        }
+        Ok(Some((start - now).min(SLOT_WAIT_STEP)))
+    }
+
+    /// Block until the package's courier may be sent another request.
+    fn wait_for_slot(&self, package: &Package) -> Result<()> {
+        let Some(start) = self.reserve_slot(package) else { return Ok(()) };
+        while let Some(delay) = self.slot_wait(start)? {
+            std::thread::sleep(delay);
+        }
+        Ok(())
+    }
+
+    /// [`Self::wait_for_slot`] without blocking.
+    async fn wait_for_slot_async(&self, package: &Package) -> Result<()> {
+        let Some(start) = self.reserve_slot(package) else { return Ok(()) };
+        while let Some(delay) = self.slot_wait(start)? {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+
+    /// Start a cooldown for the package's courier if `result` was rate-limited.
+    fn note_rate_limit(&self, package: &Package, result: &Result<Vec<CourierStatus>>) {
+        let Err(err) = result else { return };
+        let cooldown = if let Some(status) = err.downcast_ref::<http::HttpStatusError>()
+            && status.status == 429
+        {
+            status.retry_after.unwrap_or(self.rate_limit_cooldown)
+        } else if err.downcast_ref::<CourierError>() == Some(&CourierError::RateLimited) {
+            self.rate_limit_cooldown
+        } else {
+            return;
+        };
+
+        warn!(
+            courier = %package.courier,
+            cooldown_secs = cooldown.as_secs(),
+            "Courier rate limit reached, pausing all requests to it"
+        );
+        self.cooldowns
+            .lock()
+            .unwrap()
+            .insert(package.courier.clone(), Instant::now() + cooldown);
     }
 }
 
 impl CourierClient for CourierRouter {
     fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
         match self.clients.get(&package.courier) {
-            Some(client) => client.check_status(package),
+            Some(client) => {
+                self.check_cooldown(package)?;
+                self.wait_for_slot(package)?;
+                let result = client.check_status(package);
+                self.note_rate_limit(package, &result);
+                result
+            }
             None => {
                 warn!(
                     courier = %package.courier,
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     "No client registered for this courier"
                 );
                 Ok(vec![])
             }
         }
     }
+
+    fn check_status_async(self: Arc<Self>, http: reqwest::Client, package: Package) -> CourierFuture {
+        match self.clients.get(&package.courier).cloned() {
+            Some(client) => Box::pin(async move {
+                self.check_cooldown(&package)?;
+                self.wait_for_slot_async(&package).await?;
+                let result = client.check_status_async(http, package.clone()).await;
+                self.note_rate_limit(&package, &result);
+                result
+            }),
+            None => Box::pin(async move { self.check_status(&package) }),
+        }
+    }
+}
+
+/// A [`CourierRouter`] that can be replaced at runtime (e.g. on config reload).
+/// Checks already in progress finish on the router they started with, and
+/// rate limit cooldowns carry over to the replacement.
+pub struct ReloadableCourier {
+    router: ArcSwap<CourierRouter>,
+}
+
+impl ReloadableCourier {
+    pub fn new(router: CourierRouter) -> Self {
+        Self {
+            router: ArcSwap::from_pointee(router),
+        }
+    }
+
+    pub fn replace(&self, router: CourierRouter) {
+        router.inherit_rate_limits(&self.router.load());
+        self.router.store(Arc::new(router));
+    }
+}
+
+impl CourierClient for ReloadableCourier {
+    fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
+        self.router.load_full().check_status(package)
+    }
+
+    fn check_status_async(self: Arc<Self>, http: reqwest::Client, package: Package) -> CourierFuture {
+        self.router.load_full().check_status_async(http, package)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum CourierCode {
     FedEx,
     UPS,
     USPS,
+    CanadaPost,
 }
 
 impl CourierCode {
@@ -68,6 +454,20 @@ impl CourierCode {
             CourierCode::FedEx => "FedEx",
             CourierCode::UPS   => "UPS",
             CourierCode::USPS  => "USPS",
+            CourierCode::CanadaPost => "Canada Post",
+        }
+    }
+
+    /// The courier's public tracking page for a number, for packages whose
+    /// tracking number match didn't come with a URL.
+    pub fn tracking_url(&self, tracking_number: &str) -> String {
+        match self {
+            CourierCode::FedEx => format!("https://www.fedex.com/fedextrack/?trknbr={tracking_number}"),
+            CourierCode::UPS   => format!("https://www.ups.com/track?tracknum={tracking_number}"),
+            CourierCode::USPS  => format!("https://tools.usps.com/go/TrackConfirmAction?tLabels={tracking_number}"),
+            CourierCode::CanadaPost => {
+                format!("https://www.canadapost-postescanada.ca/track-reperage/en#/search?searchFor={tracking_number}")
+            }
         }
     }
 }
@@ -78,6 +478,7 @@ impl fmt::Display for CourierCode {
             CourierCode::FedEx => write!(f, "fedex"),
             CourierCode::UPS   => write!(f, "ups"),
             CourierCode::USPS  => write!(f, "usps"),
+            CourierCode::CanadaPost => write!(f, "canada_post"),
         }
     }
 }
@@ -89,8 +490,218 @@ impl FromStr for CourierCode {
         match s {
             "fedex" | "FedEx" => Ok(CourierCode::FedEx),
             "ups"   | "UPS" => Ok(CourierCode::UPS),
-            "usps"  | "United States Postal Service" => Ok(CourierCode::USPS),
+            "usps"  | "USPS" | "United States Postal Service" => Ok(CourierCode::USPS),
+            "canada_post" | "Canada Post" => Ok(CourierCode::CanadaPost),
             other => Err(anyhow::anyhow!("Unknown courier code: {other}")),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_verbose_descriptions() {
+        let cases = [
+            ("Arrived at USPS Regional Origin Facility, OKLAHOMA CITY OK DISTRIBUTION CENTER", Some("Arrived at facility")),
+            ("Departed USPS Regional Facility, MEMPHIS TN NETWORK DISTRIBUTION CENTER", Some("Departed facility")),
+            ("Out for Delivery, AUSTIN, TX 78701", Some("Out for delivery")),
+            ("Delivered, Front Door/Porch, AUSTIN, TX 78701", Some("Delivered")),
+            ("Shipping Label Created, USPS Awaiting Item", Some("Label created")),
+            ("Delivery Attempted - No Access to Delivery Location", Some("Delivery attempted")),
+            ("Your package is moving", None),
+            ("Not delivered, recipient not available", None),
+            ("Item could not be delivered", None),
+            ("Delivered to agent for final delivery", None),
+            ("Delivered to neighbour at no. 12", None),
+            ("Deliveredx", None),
+        ];
+
+        for (raw, expected) in cases {
+            assert_eq!(short_description(raw), expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn maps_courier_error_codes() {
+        let fedex = [
+            ("TRACKING.TRACKINGNUMBER.NOTFOUND", CourierError::NotFound),
+            ("TRACKING.TRACKINGNUMBER.INVALID", CourierError::InvalidRequest),
+            ("NOT.AUTHORIZED.ERROR", CourierError::Unauthorized),
+            ("905", CourierError::RateLimited),
+            ("SOMETHING.ELSE", CourierError::Other("SOMETHING.ELSE".into())),
+        ];
+        for (code, expected) in fedex {
+            assert_eq!(CourierError::from_fedex_code(code), expected, "{code}");
+        }
+
+        let usps = [
+            ("404", CourierError::NotFound),
+            ("429", CourierError::RateLimited),
+            ("401", CourierError::Unauthorized),
+            ("503", CourierError::Unavailable),
+        ];
+        for (code, expected) in usps {
+            assert_eq!(CourierError::from_usps_code(code), expected, "{code}");
+        }
+
+        assert_eq!(CourierError::Other("X1".into()).message(), "Courier error X1");
+    }
+
+    struct FixedCourier(&'static str);
+
+    impl CourierClient for FixedCourier {
+        fn check_status(&self, _package: &Package) -> Result<Vec<CourierStatus>> {
+            Ok(vec![CourierStatus {
+                status: self.0.into(),
+                estimated_arrival_date: None,
+                last_known_location: None,
+                description: None,
+                checked_at: None,
+                delivery_location_detail: None,
+                courier_status_text: None,
+                courier_status_code: None,
+            }])
+        }
+    }
+
+    #[test]
+    fn reloaded_router_picks_up_new_courier() {
+        let package = Package {
+            id: 1,
+            tracking_number: "9400111206206406260787".into(),
+            courier: "usps".into(),
+            service: "USPS".into(),
+            status: crate::db::PackageStatus::Waiting,
+            destination_zip: None,
+        };
+        let courier = ReloadableCourier::new(CourierRouter::new());
+        assert!(courier.check_status(&package).unwrap().is_empty());
+
+        let mut router = CourierRouter::new();
+        router.register(&CourierCode::USPS, Box::new(FixedCourier("in_transit")));
+        courier.replace(router);
+
+        let statuses = courier.check_status(&package).unwrap();
+        assert_eq!(statuses[0].status, "in_transit");
+    }
+
+    /// Rate-limits every request with the given `Retry-After`, counting calls.
+    struct RateLimitedCourier {
+        retry_after: Option<Duration>,
+        calls: Arc<Mutex<u32>>,
+    }
+
+    impl CourierClient for RateLimitedCourier {
+        fn check_status(&self, _package: &Package) -> Result<Vec<CourierStatus>> {
+            *self.calls.lock().unwrap() += 1;
+            Err(http::HttpStatusError { status: 429, retry_after: self.retry_after }.into())
+        }
+    }
+
+    fn package(courier: &str) -> Package {
+        Package {
+            id: 1,
+            tracking_number: "1Z5R89390357567127".into(),
+            courier: courier.into(),
+            service: String::new(),
+            status: crate::db::PackageStatus::InTransit,
+            destination_zip: None,
+        }
+    }
+
+    #[test]
+    fn skips_rate_limited_courier_during_cooldown() {
+        let calls = Arc::new(Mutex::new(0));
+        let mut router = CourierRouter::new().with_rate_limit_cooldown(Duration::from_secs(3600));
+        router.register(&CourierCode::UPS, Box::new(RateLimitedCourier { retry_after: None, calls: Arc::clone(&calls) }));
+        router.register(&CourierCode::USPS, Box::new(FixedCourier("in_transit")));
+
+        assert!(router.check_status(&package("ups")).is_err());
+        let err = router.check_status(&package("ups")).err().unwrap();
+
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::CoolingDown));
+        assert_eq!(*calls.lock().unwrap(), 1);
+        assert_eq!(router.check_status(&package("usps")).unwrap()[0].status, "in_transit");
+    }
+
+    #[test]
+    fn cooldown_follows_retry_after() {
+        let calls = Arc::new(Mutex::new(0));
+        let mut router = CourierRouter::new().with_rate_limit_cooldown(Duration::from_secs(3600));
+        let courier = RateLimitedCourier { retry_after: Some(Duration::ZERO), calls: Arc::clone(&calls) };
+        router.register(&CourierCode::UPS, Box::new(courier));
+
+        assert!(router.check_status(&package("ups")).is_err());
+        assert!(router.check_status(&package("ups")).is_err());
+
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn replaced_router_keeps_rate_limit_cooldowns() {
+        let calls = Arc::new(Mutex::new(0));
+        let mut router = CourierRouter::new().with_rate_limit_cooldown(Duration::from_secs(3600));
+        router.register(&CourierCode::UPS, Box::new(RateLimitedCourier { retry_after: None, calls: Arc::clone(&calls) }));
+        let courier = ReloadableCourier::new(router);
+        assert!(courier.check_status(&package("ups")).is_err());
+
+        let mut router = CourierRouter::new();
+        router.register(&CourierCode::UPS, Box::new(FixedCourier("in_transit")));
+        courier.replace(router);
+
+        let err = courier.check_status(&package("ups")).err().unwrap();
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::CoolingDown));
+    }
+
+    #[test]
+    fn from_config_registers_only_configured_couriers() {
+        let config = CourierConfig {
+            fedex: Some(crate::config::FedexConfig {
+                client_id: "id".into(),
+                client_secret: "secret".into(),
+                environment: crate::config::FedexEnvironment::default(),
+                retry: crate::config::RetryConfig::default(),
+            }),
+            ..CourierConfig::default()
+        };
+
+        let router = CourierRouter::from_config(&config);
+
+        assert!(router.clients.contains_key("fedex"));
+        assert!(router.clients.contains_key("ups"), "UPS falls back to the web client");
+        assert!(!router.clients.contains_key("usps"));
+        assert!(router.check_status(&package("usps")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn spaces_requests_to_a_rate_limited_courier() {
+        let mut router = CourierRouter::new().with_requests_per_minute(&CourierCode::USPS, 600);
+        router.register(&CourierCode::USPS, Box::new(FixedCourier("in_transit")));
+        router.register(&CourierCode::UPS, Box::new(FixedCourier("in_transit")));
+
+        let started = Instant::now();
+        router.check_status(&package("usps")).unwrap();
+        router.check_status(&package("ups")).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        router.check_status(&package("usps")).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn stops_waiting_for_a_slot_on_shutdown() {
+        let running = Arc::new(AtomicBool::new(true));
+        let mut router = CourierRouter::new()
+            .with_requests_per_minute(&CourierCode::USPS, 1)
+            .with_shutdown(Arc::clone(&running));
+        router.register(&CourierCode::USPS, Box::new(FixedCourier("in_transit")));
+
+        router.check_status(&package("usps")).unwrap();
+        running.store(false, Ordering::SeqCst);
+        let err = router.check_status(&package("usps")).err().unwrap();
+
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::ShuttingDown));
+    }
+}
@@ -1,19 +1,26 @@
-use super::{CourierClient, CourierStatus};
-use crate::config::UpsConfig;
+use super::http::{self, HttpRequest, HttpStatusError};
+use super::{CourierClient, CourierError, CourierFuture, CourierStatus};
+use crate::config::{UpsConfig, RetryConfig};
 use crate::db::{Package, PackageStatus};
+use crate::redact;
 use crate::util::parse_date_yyyymmdd;
 use anyhow::{Context, Result};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use std::sync::Mutex;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
 const TOKEN_URL: &str = "https://onlinetools.ups.com/security/v1/oauth/token";
 const TRACK_URL: &str = "https://onlinetools.ups.com/api/track/v1/details/";
 
+/// Shipment warning UPS returns for a tracking number it has no record of.
+const WARNING_NOT_FOUND: &str = "TW0001";
+
 pub struct UpsClient {
     client_id: String,
     client_secret: String,
+    retry: RetryConfig,
     token: Mutex<Option<(String, Instant)>>,
 }
 
@@ -22,6 +29,7 @@ impl UpsClient {
         Self {
             client_id: config.client_id.clone(),
             client_secret: config.client_secret.clone(),
+            retry: config.retry,
             token: Mutex::new(None),
         }
     }
@@ -29,10 +37,10 @@ impl UpsClient {
     fn get_token(&self) -> Result<String> {
         let mut guard = self.token.lock().unwrap();
 
-        if let Some((ref token, expiry)) = *guard {
-            if Instant::now() < expiry {
-                return Ok(token.clone());
-            }
+        if let Some((ref token, expiry)) = *guard
+            && Instant::now() < expiry
+        {
+            return Ok(token.clone());
         }
 
         let (token, ttl) = self.fetch_token()?;
@@ -41,22 +49,47 @@ impl UpsClient {
         Ok(token)
     }
 
-    fn fetch_token(&self) -> Result<(String, Duration)> {
+    /// The cached token, if it hasn't expired.
+    fn cached_token(&self) -> Option<String> {
+        match &*self.token.lock().unwrap() {
+            Some((token, expiry)) if Instant::now() < *expiry => Some(token.clone()),
+            _ => None,
+        }
+    }
+
+    async fn get_token_async(&self, client: &reqwest::Client) -> Result<String> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
         debug!("Fetching new UPS OAuth token");
+        let request = self.token_request();
+        let body = http::retry_with_backoff_async(&self.retry, || http::send_async(client, &request))
+            .await
+            .context("UPS OAuth token request failed")?;
+        let (token, ttl) = Self::parse_token(&body)?;
+        *self.token.lock().unwrap() = Some((token.clone(), Instant::now() + ttl));
+        Ok(token)
+    }
 
-        let credentials = BASE64.encode(format!("{}:{}", self.client_id, self.client_secret));
+    fn fetch_token(&self) -> Result<(String, Duration)> {
+        debug!("Fetching new UPS OAuth token");
 
-        let response = ureq::post(TOKEN_URL)
-            .header("Authorization", &format!("Basic {credentials}"))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .send("grant_type=client_credentials".as_bytes())
+        let request = self.token_request();
+        let body = http::retry_with_backoff(&self.retry, || http::send(&request))
             .context("UPS OAuth token request failed")?;
+        Self::parse_token(&body)
+    }
+
+    fn token_request(&self) -> HttpRequest {
+        let credentials = BASE64.encode(format!("{}:{}", self.client_id, self.client_secret));
 
-        let body: serde_json::Value = response
-            .into_body()
-            .read_json()
-            .context("Failed to parse UPS token response")?;
+        HttpRequest::post(TOKEN_URL)
+            .header("Authorization", format!("Basic {credentials}"))
+            .form("grant_type=client_credentials")
+    }
 
+    fn parse_token(body: &Value) -> Result<(String, Duration)> {
         let access_token = body["access_token"]
             .as_str()
             .context("Missing access_token in UPS response")?
@@ -77,46 +110,186 @@ impl UpsClient {
         Ok((access_token, ttl))
     }
 
-    fn map_status_code(code: &str) -> PackageStatus {
-        match code {
-            "D" => PackageStatus::Delivered,
-            "M" | "P" => PackageStatus::Waiting,
-            _ => PackageStatus::InTransit,
-        }
-    }
-}
-
-impl CourierClient for UpsClient {
-    fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
-        let token = self.get_token()?;
-
-        let url = format!("{TRACK_URL}{}", package.tracking_number);
+    fn track_request(package: &Package, token: &str) -> HttpRequest {
         let trans_id = format!("trackage-{}", chrono::Utc::now().timestamp());
 
-        let result = ureq::get(&url)
-            .header("Authorization", &format!("Bearer {token}"))
-            .header("transId", &trans_id)
+        HttpRequest::get(format!("{TRACK_URL}{}", package.tracking_number))
+            .header("Authorization", format!("Bearer {token}"))
+            .header("transId", trans_id)
             .header("transactionSrc", "trackage")
-            .call();
+    }
 
-        let response = match result {
-            Ok(resp) => resp,
-            Err(ureq::Error::StatusCode(404)) => {
+    /// The parsed track response. UPS reports a number it doesn't know with a 404.
+    fn track_response(result: Result<Value>, package: &Package) -> Result<Value> {
+        match result {
+            Ok(body) => Ok(body),
+            Err(err) if err.downcast_ref::<HttpStatusError>().is_some_and(|e| e.status == 404) => {
                 debug!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     "UPS tracking number not found"
                 );
-                return Ok(vec![]);
+                Err(CourierError::NotFound.into())
             }
-            Err(e) => return Err(e).context("UPS track request failed"),
+            Err(err) => Err(err).context("UPS track request failed"),
+        }
+    }
+
+    /// The package to report. A reused tracking number can return several
+    /// shipments; pick the one whose latest activity is most recent rather than the first.
+    fn latest_package<'a>(body: &'a Value, tracking_number: &str) -> &'a Value {
+        static MISSING: Value = Value::Null;
+
+        let packages: Vec<&Value> = body["trackResponse"]["shipment"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|shipment| shipment["package"].as_array().into_iter().flatten())
+            .collect();
+
+        if packages.len() > 1 {
+            warn!(
+                tracking_number = %redact::tracking_number(tracking_number),
+                count = packages.len(),
+                "UPS returned multiple shipments, using the most recent"
+            );
+        }
+
+        // Activity dates are YYYYMMDD and times HHMMSS, so the strings sort
+        // chronologically. Reverse so ties keep response order.
+        packages
+            .into_iter()
+            .rev()
+            .max_by_key(|pkg| {
+                pkg["activity"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|act| {
+                        format!(
+                            "{}{}",
+                            act["date"].as_str().unwrap_or(""),
+                            act["time"].as_str().unwrap_or("")
+                        )
+                    })
+                    .max()
+            })
+            .unwrap_or(&MISSING)
+    }
+
+    /// Build a `CourierStatus` per activity. Activities are returned
+    /// newest-first; we reverse so oldest is first, meaning the last entry
+    /// (most recent) determines the package's current status. `code` is the
+    /// package's current status type.
+    fn statuses_from_package(pkg: &Value, code: &str) -> Vec<CourierStatus> {
+        let mapped = Self::map_status_code(code);
+
+        // Extract estimated delivery date (API returns YYYYMMDD → YYYY-MM-DD)
+        let estimated_arrival_date = pkg["deliveryDate"]
+            .as_array()
+            .and_then(|dates| dates.first())
+            .and_then(|d| d["date"].as_str())
+            .and_then(parse_date_yyyymmdd);
+
+        // Where the package was left, e.g. "Front Door"
+        let delivery_location_detail = if mapped == PackageStatus::Delivered {
+            pkg["deliveryInformation"]["location"]
+                .as_str()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        } else {
+            None
         };
 
-        let body: serde_json::Value = response
-            .into_body()
-            .read_json()
-            .context("Failed to parse UPS track response")?;
+        let courier_status_text = pkg["currentStatus"]["description"]
+            .as_str()
+            .map(|s| s.trim().to_string());
+
+        let activities: &[Value] = pkg["activity"].as_array().map_or(&[], |a| a.as_slice());
+        let mut statuses = Vec::new();
+
+        for (i, activity) in activities.iter().rev().enumerate() {
+            let is_latest = i == activities.len() - 1;
+
+            let description = activity["status"]["description"]
+                .as_str()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string());
+
+            let addr = &activity["location"]["address"];
+            let location = addr["city"].as_str().filter(|s| !s.is_empty()).map(|city| {
+                match addr["stateProvince"].as_str().filter(|s| !s.is_empty()) {
+                    Some(state) => format!("{city}, {state}"),
+                    None => city.to_string(),
+                }
+            });
+
+            // GMT fields are YYYYMMDD and HH:MM:SS
+            let checked_at = match (activity["gmtDate"].as_str(), activity["gmtTime"].as_str()) {
+                (Some(gd), Some(gt)) => parse_date_yyyymmdd(gd).map(|date| format!("{date}T{gt}Z")),
+                _ => None,
+            };
 
-        let pkg = &body["trackResponse"]["shipment"][0]["package"][0];
+            // Use the overall package status for the most recent activity and
+            // each activity's own status type for the history
+            let status = if is_latest {
+                mapped
+            } else {
+                activity["status"]["type"]
+                    .as_str()
+                    .map_or(PackageStatus::InTransit, Self::map_status_code)
+            };
+
+            statuses.push(CourierStatus {
+                status: status.to_string(),
+                estimated_arrival_date: estimated_arrival_date.clone(),
+                last_known_location: location,
+                description,
+                checked_at,
+                delivery_location_detail: if is_latest { delivery_location_detail.clone() } else { None },
+                courier_status_text: if is_latest { courier_status_text.clone() } else { None },
+                courier_status_code: Some(if is_latest {
+                    code.to_string()
+                } else {
+                    activity["status"]["type"].as_str().unwrap_or(code).to_string()
+                }),
+            });
+        }
+
+        // If no activities were found, still return the overall status
+        if statuses.is_empty() {
+            statuses.push(CourierStatus {
+                status: mapped.to_string(),
+                estimated_arrival_date,
+                last_known_location: None,
+                description: courier_status_text.clone(),
+                checked_at: None,
+                delivery_location_detail,
+                courier_status_text,
+                courier_status_code: Some(code.to_string()),
+            });
+        }
+
+        statuses
+    }
+
+    fn parse_track_response(package: &Package, body: &Value) -> Result<Vec<CourierStatus>> {
+        let not_found = body["trackResponse"]["shipment"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|shipment| shipment["warnings"].as_array().into_iter().flatten())
+            .any(|warning| warning["code"].as_str() == Some(WARNING_NOT_FOUND));
+        if not_found {
+            debug!(
+                tracking_number = %redact::tracking_number(&package.tracking_number),
+                "UPS tracking number not found"
+            );
+            return Err(CourierError::NotFound.into());
+        }
+
+        let pkg = Self::latest_package(body, &package.tracking_number);
 
         // Try currentStatus.type first, fall back to most recent activity
         let status_code = pkg["currentStatus"]["type"]
@@ -127,49 +300,154 @@ impl CourierClient for UpsClient {
             Some(code) => {
                 let mapped = Self::map_status_code(code);
 
-                // Extract estimated delivery date (API returns YYYYMMDD → YYYY-MM-DD)
-                let estimated_arrival_date = pkg["deliveryDate"]
-                    .as_array()
-                    .and_then(|dates| dates.first())
-                    .and_then(|d| d["date"].as_str())
-                    .and_then(parse_date_yyyymmdd);
-
-                // Extract last known location from latest activity
-                let last_known_location = pkg["activity"]
-                    .as_array()
-                    .and_then(|acts| acts.first())
-                    .and_then(|act| {
-                        let addr = &act["location"]["address"];
-                        addr["city"].as_str().map(|city| {
-                            match addr["stateProvince"].as_str() {
-                                Some(state) => format!("{city}, {state}"),
-                                None => city.to_string(),
-                            }
-                        })
-                    });
-
                 info!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     ups_code = code,
                     mapped_status = %mapped,
+                    activity_count = pkg["activity"].as_array().map_or(0, |a| a.len()),
                     "UPS status retrieved"
                 );
-                Ok(vec![CourierStatus {
-                    status: mapped.to_string(),
-                    estimated_arrival_date,
-                    last_known_location,
-                    description: None,
-                    checked_at: None,
-                }])
+
+                Ok(Self::statuses_from_package(pkg, code))
             }
             None => {
                 warn!(
-                    tracking_number = %package.tracking_number,
-                    response = %body,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
+                    response = %redact::body(&body.to_string(), &package.tracking_number),
                     "No status code in UPS response"
                 );
                 Ok(vec![])
             }
         }
     }
+
+    fn map_status_code(code: &str) -> PackageStatus {
+        match code {
+            "D" => PackageStatus::Delivered,
+            "M" | "P" => PackageStatus::Waiting,
+            _ => PackageStatus::InTransit,
+        }
+    }
+}
+
+impl CourierClient for UpsClient {
+    fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
+        let token = self.get_token()?;
+        let request = Self::track_request(package, &token);
+        let result = http::retry_with_backoff(&self.retry, || http::send(&request));
+        let body = Self::track_response(result, package)?;
+        Self::parse_track_response(package, &body)
+    }
+
+    fn check_status_async(self: Arc<Self>, client: reqwest::Client, package: Package) -> CourierFuture {
+        Box::pin(async move {
+            let token = self.get_token_async(&client).await?;
+            let request = Self::track_request(&package, &token);
+            let result = http::retry_with_backoff_async(&self.retry, || http::send_async(&client, &request)).await;
+            let body = Self::track_response(result, &package)?;
+            Self::parse_track_response(&package, &body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn picks_most_recent_of_multiple_shipments() {
+        let body = json!({
+            "trackResponse": { "shipment": [
+                { "package": [{
+                    "currentStatus": { "type": "D" },
+                    "activity": [{ "date": "20240501", "time": "101500" }]
+                }]},
+                { "package": [{
+                    "currentStatus": { "type": "I" },
+                    "activity": [{ "date": "20260301", "time": "080000" }]
+                }]}
+            ]}
+        });
+
+        let pkg = UpsClient::latest_package(&body, "1Z5R89390357567127");
+
+        assert_eq!(pkg["currentStatus"]["type"], "I");
+    }
+
+    #[test]
+    fn returns_one_status_per_activity_oldest_first() {
+        let pkg = json!({
+            "currentStatus": { "type": "D", "description": "Delivered" },
+            "deliveryInformation": { "location": "Front Door" },
+            "activity": [
+                {
+                    "status": { "type": "D", "description": "DELIVERED " },
+                    "location": { "address": { "city": "Austin", "stateProvince": "TX" } },
+                    "gmtDate": "20260302", "gmtTime": "18:05:00"
+                },
+                {
+                    "status": { "type": "I", "description": "Departed from Facility" },
+                    "location": { "address": { "city": "Memphis", "stateProvince": "TN" } },
+                    "gmtDate": "20260301", "gmtTime": "04:30:00"
+                },
+                {
+                    "status": { "type": "M", "description": "Shipper created a label" },
+                    "location": { "address": { "country": "US" } },
+                    "gmtDate": "20260228", "gmtTime": "22:10:00"
+                }
+            ]
+        });
+
+        let statuses = UpsClient::statuses_from_package(&pkg, "D");
+
+        let checked: Vec<_> = statuses.iter().map(|s| s.checked_at.as_deref().unwrap()).collect();
+        assert_eq!(
+            checked,
+            ["2026-02-28T22:10:00Z", "2026-03-01T04:30:00Z", "2026-03-02T18:05:00Z"]
+        );
+        let status: Vec<_> = statuses.iter().map(|s| s.status.as_str()).collect();
+        assert_eq!(status, ["waiting", "in_transit", "delivered"]);
+        assert_eq!(statuses[0].last_known_location, None);
+        assert_eq!(statuses[1].last_known_location.as_deref(), Some("Memphis, TN"));
+        assert_eq!(statuses[2].description.as_deref(), Some("DELIVERED"));
+        assert_eq!(statuses[2].delivery_location_detail.as_deref(), Some("Front Door"));
+        assert_eq!(statuses[1].delivery_location_detail, None);
+    }
+
+    #[test]
+    fn returns_current_status_without_activity() {
+        let pkg = json!({ "currentStatus": { "type": "I", "description": "On the Way" } });
+
+        let statuses = UpsClient::statuses_from_package(&pkg, "I");
+
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].status, "in_transit");
+        assert_eq!(statuses[0].courier_status_text.as_deref(), Some("On the Way"));
+    }
+
+    #[test]
+    fn reports_unknown_numbers_as_not_found() {
+        let package = Package {
+            id: 1,
+            tracking_number: "1Z5R89390357567127".into(),
+            courier: "ups".into(),
+            service: "UPS Ground".into(),
+            status: PackageStatus::InTransit,
+            destination_zip: None,
+        };
+        let warning = json!({
+            "trackResponse": { "shipment": [{
+                "inquiryNumber": "1Z5R89390357567127",
+                "warnings": [{ "code": "TW0001", "message": "Tracking Information Not Found" }]
+            }]}
+        });
+
+        let err = UpsClient::parse_track_response(&package, &warning).err().unwrap();
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::NotFound));
+
+        let missing = Err(HttpStatusError { status: 404, retry_after: None }.into());
+        let err = UpsClient::track_response(missing, &package).unwrap_err();
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::NotFound));
+    }
 }
@@ -1,5 +1,6 @@
 use super::{CourierClient, CourierStatus};
 use crate::db::{Package, PackageStatus};
+use crate::redact;
 use crate::util::parse_date_yyyymmdd;
 use anyhow::{Context, Result};
 use reqwest::blocking::Client;
@@ -46,8 +47,8 @@ impl UpsWebClient {
         );
 
         debug!(
-            tracking_number = tracking_number,
-            url = %url,
+            tracking_number = %redact::tracking_number(tracking_number),
+            url = %redact::body(&url, tracking_number),
             "UPS web: establishing session"
         );
 
@@ -65,7 +66,7 @@ impl UpsWebClient {
         let elapsed = start.elapsed();
 
         debug!(
-            tracking_number = tracking_number,
+            tracking_number = %redact::tracking_number(tracking_number),
             status = %resp.status(),
             elapsed_ms = elapsed.as_millis() as u64,
             "UPS web: session response received"
@@ -82,7 +83,7 @@ impl UpsWebClient {
         match xsrf_token {
             Some(token) => {
                 debug!(
-                    tracking_number = tracking_number,
+                    tracking_number = %redact::tracking_number(tracking_number),
                     "UPS web: XSRF token acquired"
                 );
                 Ok(token)
@@ -101,7 +102,7 @@ impl CourierClient for UpsWebClient {
             Ok(token) => token,
             Err(e) => {
                 warn!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     error = %e,
                     "UPS web: failed to establish session"
                 );
@@ -124,9 +125,9 @@ impl CourierClient for UpsWebClient {
         });
 
         debug!(
-            tracking_number = %package.tracking_number,
+            tracking_number = %redact::tracking_number(&package.tracking_number),
             url = TRACK_API_URL,
-            payload = %payload,
+            payload = %redact::body(&payload.to_string(), &package.tracking_number),
             "UPS web: tracking API request"
         );
 
@@ -147,7 +148,7 @@ impl CourierClient for UpsWebClient {
         let response = match result {
             Ok(resp) => {
                 debug!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     status = %resp.status(),
                     elapsed_ms = elapsed.as_millis() as u64,
                     "UPS web: tracking API response received"
@@ -156,7 +157,7 @@ impl CourierClient for UpsWebClient {
             }
             Err(e) => {
                 warn!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     error = %e,
                     elapsed_ms = elapsed.as_millis() as u64,
                     "UPS web: tracking API request failed"
@@ -169,7 +170,7 @@ impl CourierClient for UpsWebClient {
             Ok(text) => text,
             Err(e) => {
                 warn!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     error = %e,
                     "UPS web: failed to read tracking API response body"
                 );
@@ -178,8 +179,8 @@ impl CourierClient for UpsWebClient {
         };
 
         debug!(
-            tracking_number = %package.tracking_number,
-            body = %body_text,
+            tracking_number = %redact::tracking_number(&package.tracking_number),
+            body = %redact::body(&body_text, &package.tracking_number),
             "UPS web: tracking API response body"
         );
 
@@ -187,9 +188,9 @@ impl CourierClient for UpsWebClient {
             Ok(json) => json,
             Err(e) => {
                 warn!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     error = %e,
-                    body = %body_text,
+                    body = %redact::body(&body_text, &package.tracking_number),
                     "UPS web: failed to parse tracking API response as JSON"
                 );
                 return Ok(vec![]);
@@ -209,7 +210,7 @@ impl CourierClient for UpsWebClient {
                     .and_then(parse_date_yyyymmdd);
 
                 info!(
-                    tracking_number = %package.tracking_number,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
                     ups_code = code,
                     mapped_status = %mapped,
                     activity_count = details["shipmentProgressActivities"].as_array().map_or(0, |a| a.len()),
@@ -257,6 +258,12 @@ impl CourierClient for UpsWebClient {
                             last_known_location: location,
                             description,
                             checked_at,
+                            delivery_location_detail: None,
+                            courier_status_text: details["packageStatus"]
+                                .as_str()
+                                .filter(|s| is_latest && !s.is_empty())
+                                .map(|s| s.to_string()),
+                            courier_status_code: is_latest.then(|| code.to_string()),
                         });
                     }
                 }
@@ -272,6 +279,12 @@ impl CourierClient for UpsWebClient {
                             .filter(|s| !s.is_empty())
                             .map(|s| s.to_string()),
                         checked_at: None,
+                        delivery_location_detail: None,
+                        courier_status_text: details["packageStatus"]
+                            .as_str()
+                            .filter(|s| !s.is_empty())
+                            .map(|s| s.to_string()),
+                        courier_status_code: Some(code.to_string()),
                     });
                 }
 
@@ -279,8 +292,8 @@ impl CourierClient for UpsWebClient {
             }
             None => {
                 warn!(
-                    tracking_number = %package.tracking_number,
-                    response = %body,
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
+                    response = %redact::body(&body.to_string(), &package.tracking_number),
                     "UPS web: no status code in response"
                 );
                 Ok(vec![])
@@ -1,33 +1,56 @@
-use super::{CourierClient, CourierStatus};
-use crate::config::UspsConfig;
+use super::http::{self, HttpRequest, HttpStatusError};
+use super::{CourierClient, CourierError, CourierFuture, CourierStatus};
+use crate::config::{UspsConfig, RetryConfig};
 use crate::db::{Package, PackageStatus};
+use crate::redact;
 use crate::util::format_rfc3339_utc;
 use anyhow::{Context, Result};
+use chrono::NaiveDate;
 use regex::Regex;
-use serde_json::json;
-use std::sync::Mutex;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tracing::{debug, warn};
 
 /// Substrings matched case-insensitively in USPS eventSummary text to determine status.
+/// Checked before the delivered keyword: an item "delivered to an agent" or
+/// "available for pickup" still has to be collected.
+const SUMMARY_KEYWORDS_PICKUP: &[&str] = &[
+    "available for pickup",
+    "ready for pickup",
+    "delivered to agent",
+    "delivered to an agent",
+    "held at post office",
+];
 const SUMMARY_KEYWORD_DELIVERED: &str = "delivered";
 const SUMMARY_KEYWORD_LABEL_CREATED: &str = "shipping label created";
 const SUMMARY_KEYWORD_AWAITING_ITEM: &str = "awaiting item";
 
-const TOKEN_URL: &str = "https://apis.usps.com/oauth2/v3/token";
-const TRACK_URL: &str = "https://apis.usps.com/tracking/v3/tracking/";
+/// Descriptions for the two early stages when `separate_pre_shipment` is set.
+const PRE_SHIPMENT_DESCRIPTION: &str = "Label created, not yet accepted by USPS";
+const ACCEPTED_DESCRIPTION: &str = "Accepted by USPS";
+
+const API_URL: &str = "https://apis.usps.com";
+const TOKEN_PATH: &str = "/oauth2/v3/token";
+const TRACK_PATH: &str = "/tracking/v3/tracking/";
 
 pub struct UspsClient {
+    api_url: String,
     client_id: String,
     client_secret: String,
+    retry: RetryConfig,
+    separate_pre_shipment: bool,
     token: Mutex<Option<(String, Instant)>>,
 }
 
 impl UspsClient {
     pub fn new(config: &UspsConfig) -> Self {
         Self {
+            api_url: API_URL.to_string(),
             client_id: config.client_id.clone(),
             client_secret: config.client_secret.clone(),
+            retry: config.retry,
+            separate_pre_shipment: config.separate_pre_shipment,
             token: Mutex::new(None),
         }
     }
@@ -35,10 +58,10 @@ impl UspsClient {
     fn get_token(&self) -> Result<String> {
         let mut guard = self.token.lock().unwrap();
 
-        if let Some((ref token, expiry)) = *guard {
-            if Instant::now() < expiry {
-                return Ok(token.clone());
-            }
+        if let Some((ref token, expiry)) = *guard
+            && Instant::now() < expiry
+        {
+            return Ok(token.clone());
         }
 
         let (token, ttl) = self.fetch_token()?;
@@ -47,25 +70,47 @@ impl UspsClient {
         Ok(token)
     }
 
+    /// The cached token, if it hasn't expired.
+    fn cached_token(&self) -> Option<String> {
+        match &*self.token.lock().unwrap() {
+            Some((token, expiry)) if Instant::now() < *expiry => Some(token.clone()),
+            _ => None,
+        }
+    }
+
+    async fn get_token_async(&self, client: &reqwest::Client) -> Result<String> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
+        debug!("Fetching new USPS OAuth token");
+        let request = self.token_request();
+        let body = http::retry_with_backoff_async(&self.retry, || http::send_async(client, &request))
+            .await
+            .context("USPS OAuth token request failed")?;
+        let (token, ttl) = Self::parse_token(&body)?;
+        *self.token.lock().unwrap() = Some((token.clone(), Instant::now() + ttl));
+        Ok(token)
+    }
+
     fn fetch_token(&self) -> Result<(String, Duration)> {
         debug!("Fetching new USPS OAuth token");
 
-        let request_body = json!({
+        let request = self.token_request();
+        let body = http::retry_with_backoff(&self.retry, || http::send(&request))
+            .context("USPS OAuth token request failed")?;
+        Self::parse_token(&body)
+    }
+
+    fn token_request(&self) -> HttpRequest {
+        HttpRequest::post(format!("{}{TOKEN_PATH}", self.api_url)).json(json!({
             "client_id": self.client_id,
             "client_secret": self.client_secret,
             "grant_type": "client_credentials"
-        });
-
-        let response = ureq::post(TOKEN_URL)
-            .header("Content-Type", "application/json")
-            .send_json(&request_body)
-            .context("USPS OAuth token request failed")?;
-
-        let body: serde_json::Value = response
-            .into_body()
-            .read_json()
-            .context("Failed to parse USPS token response")?;
+        }))
+    }
 
+    fn parse_token(body: &Value) -> Result<(String, Duration)> {
         let access_token = body["access_token"]
             .as_str()
             .context("Missing access_token in USPS response")?
@@ -83,17 +128,146 @@ impl UspsClient {
         Ok((access_token, ttl))
     }
 
+    fn track_request(&self, package: &Package, token: &str) -> HttpRequest {
+        HttpRequest::get(format!("{}{TRACK_PATH}{}", self.api_url, package.tracking_number))
+            .header("Authorization", format!("Bearer {token}"))
+    }
+
+    /// The parsed track response. USPS answers a number it doesn't know with a
+    /// 404, carrying the same error envelope as [`Self::parse_track_response`] checks.
+    fn track_response(result: Result<Value>, package: &Package) -> Result<Value> {
+        match result {
+            Ok(body) => Ok(body),
+            Err(err) if err.downcast_ref::<HttpStatusError>().is_some_and(|e| e.status == 404) => {
+                debug!(
+                    tracking_number = %redact::tracking_number(&package.tracking_number),
+                    "USPS tracking number not found"
+                );
+                Err(CourierError::NotFound.into())
+            }
+            Err(err) => Err(err).context("USPS track request failed"),
+        }
+    }
+
+    fn parse_track_response(&self, package: &Package, body: &Value) -> Result<Vec<CourierStatus>> {
+        // Check for error envelope
+        if let Some(error) = body["error"].as_object() {
+            let code = error.get("code").and_then(|c| c.as_str()).unwrap_or("");
+            let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("");
+            warn!(
+                tracking_number = %redact::tracking_number(&package.tracking_number),
+                error_code = code,
+                error_message = message,
+                "USPS tracking error"
+            );
+            return Err(CourierError::from_usps_code(code).into());
+        }
+
+        let status_category = body["statusCategory"].as_str();
+
+        // Structured path: statusCategory is present
+        if let Some(category) = status_category {
+            let mapped = Self::map_status_category(category);
+
+            let estimated_arrival_date = body["expectedDeliveryDate"].as_str().and_then(|date| {
+                let normalized = Self::normalize_date(date);
+                if normalized.is_none() {
+                    warn!(
+                        tracking_number = %redact::tracking_number(&package.tracking_number),
+                        expected_delivery_date = date,
+                        "Unrecognized USPS expectedDeliveryDate, ignoring"
+                    );
+                }
+                normalized
+            });
+
+            let last_known_location = body["trackingEvents"]
+                .as_array()
+                .and_then(|events| events.first())
+                .and_then(|event| {
+                    event["eventCity"].as_str().map(|city| {
+                        match event["eventState"].as_str() {
+                            Some(state) => format!("{city}, {state}"),
+                            None => city.to_string(),
+                        }
+                    })
+                });
+
+            debug!(
+                tracking_number = %redact::tracking_number(&package.tracking_number),
+                usps_category = category,
+                mapped_status = %mapped,
+                "USPS status retrieved"
+            );
+            return Ok(vec![CourierStatus {
+                status: mapped.to_string(),
+                estimated_arrival_date,
+                last_known_location,
+                description: self.stage_description(category),
+                checked_at: None,
+                delivery_location_detail: None,
+                courier_status_text: body["statusSummary"]
+                    .as_str()
+                    .or(body["status"].as_str())
+                    .map(|s| s.to_string()),
+                courier_status_code: Some(category.to_string()),
+            }]);
+        }
+
+        // Fallback path: parse eventSummaries
+        if let Some(summaries) = body["eventSummaries"].as_array() {
+            debug!(
+                tracking_number = %redact::tracking_number(&package.tracking_number),
+                count = summaries.len(),
+                "Parsing USPS eventSummaries fallback"
+            );
+
+            let statuses: Vec<CourierStatus> = summaries
+                .iter()
+                .rev() // reverse: oldest first so newest gets highest DB id
+                .filter_map(|s| s.as_str())
+                .map(Self::parse_event_summary)
+                .collect();
+
+            if !statuses.is_empty() {
+                return Ok(statuses);
+            }
+        }
+
+        debug!(
+            tracking_number = %redact::tracking_number(&package.tracking_number),
+            "No statusCategory or eventSummaries in USPS response"
+        );
+        Ok(vec![])
+    }
+
     fn map_status_category(category: &str) -> PackageStatus {
         match category {
             "Delivered" => PackageStatus::Delivered,
+            "Available for Pickup" | "Delivered to Agent" => PackageStatus::ReadyForPickup,
             "Pre-Shipment" => PackageStatus::Waiting,
             _ => PackageStatus::InTransit,
         }
     }
 
+    /// Description distinguishing a label-only shipment from one USPS has
+    /// accepted, for `statusCategory` values that have no scan text of their own.
+    fn stage_description(&self, category: &str) -> Option<String> {
+        if !self.separate_pre_shipment {
+            return None;
+        }
+        match category {
+            "Pre-Shipment" => Some(PRE_SHIPMENT_DESCRIPTION.to_string()),
+            "Accepted" => Some(ACCEPTED_DESCRIPTION.to_string()),
+            _ => None,
+        }
+    }
+
     fn map_summary_status(text: &str) -> PackageStatus {
         let lower = text.to_lowercase();
-        if lower.contains(SUMMARY_KEYWORD_DELIVERED) {
+        if SUMMARY_KEYWORDS_PICKUP.iter().any(|keyword| lower.contains(keyword)) {
+            PackageStatus::ReadyForPickup
+        } else if lower.contains(SUMMARY_KEYWORD_DELIVERED) {
             PackageStatus::Delivered
         } else if lower.contains(SUMMARY_KEYWORD_LABEL_CREATED)
             || lower.contains(SUMMARY_KEYWORD_AWAITING_ITEM)
@@ -164,6 +338,21 @@ impl UspsClient {
         None
     }
 
+    /// A date-only value as `YYYY-MM-DD`. Accepts ISO dates (optionally with a
+    /// time), `MM/DD/YYYY`, and the long and slash forms [`Self::extract_date`]
+    /// handles; `None` if the date can't be parsed.
+    fn normalize_date(text: &str) -> Option<String> {
+        let text = text.trim();
+        let date = NaiveDate::parse_from_str(text.get(..10).unwrap_or(text), "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(text, "%m/%d/%Y"))
+            .ok()
+            .or_else(|| {
+                let timestamp = Self::extract_date(text)?;
+                NaiveDate::parse_from_str(&timestamp[..10], "%Y-%m-%d").ok()
+            })?;
+        Some(date.format("%Y-%m-%d").to_string())
+    }
+
     fn extract_location(text: &str) -> Option<String> {
         // Pattern 1: "City, ST" with comma separator
         let re = Regex::new(r"([A-Z][A-Za-z]+(?:\s+[A-Z][A-Za-z]+)*),\s+([A-Z]{2})\b").unwrap();
@@ -186,6 +375,9 @@ impl UspsClient {
             last_known_location: Self::extract_location(summary),
             description: Some(summary.to_string()),
             estimated_arrival_date: None,
+            delivery_location_detail: None,
+            courier_status_text: None,
+            courier_status_code: None,
         }
     }
 }
@@ -193,93 +385,157 @@ impl UspsClient {
 impl CourierClient for UspsClient {
     fn check_status(&self, package: &Package) -> Result<Vec<CourierStatus>> {
         let token = self.get_token()?;
+        let request = self.track_request(package, &token);
+        let result = http::retry_with_backoff(&self.retry, || http::send(&request));
+        let body = Self::track_response(result, package)?;
+        self.parse_track_response(package, &body)
+    }
 
-        let url = format!("{TRACK_URL}{}", package.tracking_number);
+    fn check_status_async(self: Arc<Self>, client: reqwest::Client, package: Package) -> CourierFuture {
+        Box::pin(async move {
+            let token = self.get_token_async(&client).await?;
+            let request = self.track_request(&package, &token);
+            let result = http::retry_with_backoff_async(&self.retry, || http::send_async(&client, &request)).await;
+            let body = Self::track_response(result, &package)?;
+            self.parse_track_response(&package, &body)
+        })
+    }
+}
 
-        let response = ureq::get(&url)
-            .header("Authorization", &format!("Bearer {token}"))
-            .call()
-            .context("USPS track request failed")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
 
-        let body: serde_json::Value = response
-            .into_body()
-            .read_json()
-            .context("Failed to parse USPS track response")?;
+    #[test]
+    fn maps_pickup_and_agent_categories_to_ready_for_pickup() {
+        assert_eq!(UspsClient::map_status_category("Available for Pickup"), PackageStatus::ReadyForPickup);
+        assert_eq!(UspsClient::map_status_category("Delivered to Agent"), PackageStatus::ReadyForPickup);
+        assert_eq!(UspsClient::map_status_category("Delivered"), PackageStatus::Delivered);
+    }
 
-        // Check for error envelope
-        if let Some(error) = body["error"].as_object() {
-            let code = error.get("code").and_then(|c| c.as_str()).unwrap_or("");
-            let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("");
-            warn!(
-                tracking_number = %package.tracking_number,
-                error_code = code,
-                error_message = message,
-                "USPS tracking error"
-            );
-            return Ok(vec![]);
-        }
+    #[test]
+    fn maps_pickup_and_agent_summaries_to_ready_for_pickup() {
+        assert_eq!(
+            UspsClient::map_summary_status(
+                "Your item arrived at the Post Office at 10:02 am on March 2, 2026 in AUSTIN, TX 78701 and is ready for pickup."
+            ),
+            PackageStatus::ReadyForPickup
+        );
+        assert_eq!(
+            UspsClient::map_summary_status(
+                "Your item has been delivered to an agent for final delivery in AUSTIN, TX 78701 on March 2, 2026 at 9:15 am."
+            ),
+            PackageStatus::ReadyForPickup
+        );
+        assert_eq!(
+            UspsClient::map_summary_status("Your item was delivered in or at the mailbox at 1:20 pm on March 2, 2026."),
+            PackageStatus::Delivered
+        );
+    }
 
-        let status_category = body["statusCategory"].as_str();
+    #[test]
+    fn normalizes_expected_delivery_dates() {
+        let cases = [
+            ("2026-03-02", Some("2026-03-02")),
+            ("2026-03-02T00:00:00Z", Some("2026-03-02")),
+            ("March 2, 2026", Some("2026-03-02")),
+            ("3/2/2026", Some("2026-03-02")),
+            ("03/02/2026, 9:15 am", Some("2026-03-02")),
+            ("February 30, 2026", None),
+            ("Pending", None),
+        ];
 
-        // Structured path: statusCategory is present
-        if let Some(category) = status_category {
-            let mapped = Self::map_status_category(category);
+        for (raw, expected) in cases {
+            assert_eq!(UspsClient::normalize_date(raw).as_deref(), expected, "{raw}");
+        }
+    }
 
-            let estimated_arrival_date = body["expectedDeliveryDate"]
-                .as_str()
-                .map(|s| s.to_string());
+    fn client(separate_pre_shipment: bool) -> UspsClient {
+        UspsClient::new(&UspsConfig {
+            client_id: String::new(),
+            client_secret: String::new(),
+            separate_pre_shipment,
+            retry: RetryConfig::default(),
+        })
+    }
 
-            let last_known_location = body["trackingEvents"]
-                .as_array()
-                .and_then(|events| events.first())
-                .and_then(|event| {
-                    event["eventCity"].as_str().map(|city| {
-                        match event["eventState"].as_str() {
-                            Some(state) => format!("{city}, {state}"),
-                            None => city.to_string(),
-                        }
-                    })
-                });
+    #[test]
+    fn describes_pre_shipment_and_accepted_when_separated() {
+        let usps = client(true);
 
-            debug!(
-                tracking_number = %package.tracking_number,
-                usps_category = category,
-                mapped_status = %mapped,
-                "USPS status retrieved"
-            );
-            return Ok(vec![CourierStatus {
-                status: mapped.to_string(),
-                estimated_arrival_date,
-                last_known_location,
-                description: None,
-                checked_at: None,
-            }]);
-        }
+        assert_eq!(UspsClient::map_status_category("Pre-Shipment"), PackageStatus::Waiting);
+        assert_eq!(usps.stage_description("Pre-Shipment").as_deref(), Some(PRE_SHIPMENT_DESCRIPTION));
+        assert_eq!(UspsClient::map_status_category("Accepted"), PackageStatus::InTransit);
+        assert_eq!(usps.stage_description("Accepted").as_deref(), Some(ACCEPTED_DESCRIPTION));
+        assert_eq!(usps.stage_description("In Transit"), None);
+    }
 
-        // Fallback path: parse eventSummaries
-        if let Some(summaries) = body["eventSummaries"].as_array() {
-            debug!(
-                tracking_number = %package.tracking_number,
-                count = summaries.len(),
-                "Parsing USPS eventSummaries fallback"
-            );
+    #[test]
+    fn leaves_stages_undescribed_by_default() {
+        let usps = client(false);
 
-            let statuses: Vec<CourierStatus> = summaries
-                .iter()
-                .rev() // reverse: oldest first so newest gets highest DB id
-                .filter_map(|s| s.as_str())
-                .map(Self::parse_event_summary)
-                .collect();
+        assert_eq!(usps.stage_description("Pre-Shipment"), None);
+        assert_eq!(usps.stage_description("Accepted"), None);
+    }
 
-            if !statuses.is_empty() {
-                return Ok(statuses);
-            }
-        }
+    #[test]
+    fn reports_unknown_numbers_as_not_found() {
+        let package = Package {
+            id: 1,
+            tracking_number: "9400111206206406260787".into(),
+            courier: "usps".into(),
+            service: "USPS".into(),
+            status: PackageStatus::InTransit,
+            destination_zip: None,
+        };
+        let envelope = json!({ "error": { "code": "404", "message": "Tracking number not found" } });
+
+        let err = client(false).parse_track_response(&package, &envelope).err().unwrap();
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::NotFound));
+
+        let missing = Err(HttpStatusError { status: 404, retry_after: None }.into());
+        let err = UspsClient::track_response(missing, &package).unwrap_err();
+        assert_eq!(err.downcast_ref::<CourierError>(), Some(&CourierError::NotFound));
+    }
 
-        debug!(
-            tracking_number = %package.tracking_number,
-            "No statusCategory or eventSummaries in USPS response"
-        );
-        Ok(vec![])
+    #[test]
+    fn checks_status_over_async_http() {
+        let mock = axum::Router::new()
+            .route(
+                TOKEN_PATH,
+                axum::routing::post(|| async { axum::Json(json!({ "access_token": "t0k3n", "expires_in": 3600 })) }),
+            )
+            .route(
+                &format!("{TRACK_PATH}{{number}}"),
+                axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                    assert_eq!(headers["authorization"], "Bearer t0k3n");
+                    axum::Json(json!({
+                        "statusCategory": "Delivered",
+                        "statusSummary": "Delivered, In/At Mailbox",
+                        "trackingEvents": [{ "eventCity": "AUSTIN", "eventState": "TX" }]
+                    }))
+                }),
+            );
+        let api_url = serve(mock);
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        let statuses = runtime.block_on(async {
+            let usps = UspsClient { api_url, ..client(false) };
+            let package = Package {
+                id: 1,
+                tracking_number: "9400111206206406260787".into(),
+                courier: "usps".into(),
+                service: "USPS".into(),
+                status: PackageStatus::InTransit,
+                destination_zip: None,
+            };
+            Arc::new(usps).check_status_async(reqwest::Client::new(), package).await.unwrap()
+        });
+
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].status, "delivered");
+        assert_eq!(statuses[0].last_known_location.as_deref(), Some("AUSTIN, TX"));
+        assert_eq!(statuses[0].courier_status_text.as_deref(), Some("Delivered, In/At Mailbox"));
     }
 }
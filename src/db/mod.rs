@@ -12,6 +12,8 @@ use std::str::FromStr;
 pub enum PackageStatus {
     Waiting,
     InTransit,
+    /// Held at a post office or agent location, waiting to be picked up.
+    ReadyForPickup,
     Delivered,
     NotFound,
 }
@@ -21,6 +23,7 @@ impl fmt::Display for PackageStatus {
         match self {
             PackageStatus::Waiting => write!(f, "waiting"),
             PackageStatus::InTransit => write!(f, "in_transit"),
+            PackageStatus::ReadyForPickup => write!(f, "ready_for_pickup"),
             PackageStatus::Delivered => write!(f, "delivered"),
             PackageStatus::NotFound => write!(f, "not_found"),
         }
@@ -34,6 +37,7 @@ impl FromStr for PackageStatus {
         match s {
             "waiting" => Ok(PackageStatus::Waiting),
             "in_transit" => Ok(PackageStatus::InTransit),
+            "ready_for_pickup" => Ok(PackageStatus::ReadyForPickup),
             "delivered" => Ok(PackageStatus::Delivered),
             "not_found" => Ok(PackageStatus::NotFound),
             other => Err(anyhow::anyhow!("Unknown package status: {other}")),
@@ -41,6 +45,7 @@ impl FromStr for PackageStatus {
     }
 }
 
+#[derive(Clone)]
 #[allow(dead_code)]
 pub struct Package {
     pub id: i64,
@@ -48,6 +53,8 @@ pub struct Package {
     pub courier: String,
     pub service: String,
     pub status: PackageStatus,
+    /// Destination ZIP code, for couriers that need it to look up some numbers.
+    pub destination_zip: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -56,19 +63,155 @@ pub struct PackageWithStatus {
     pub tracking_number: String,
     pub courier: String,
     pub service: String,
+    /// What the shipment contains, when the source email said.
+    pub description: Option<String>,
     pub status: String,
+    /// The courier's own wording for the current status, e.g. "In transit, arriving late".
+    pub courier_status_text: Option<String>,
     pub last_known_location: Option<String>,
+    pub estimated_arrival_date: Option<String>,
+    /// Estimated from historical transit times when the courier gives no ETA.
+    pub predicted_arrival_date: Option<String>,
     pub tracking_url: Option<String>,
     pub source_email_from: Option<String>,
+    pub source_email_subject: Option<String>,
+    /// When the source email was received; `None` for packages added by hand.
+    pub source_email_date: Option<String>,
     pub created_at: String,
+    /// Human-readable message from the most recent failed courier check.
+    pub last_error: Option<String>,
+    /// When the package was deleted. Only set in listings that include deleted packages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    /// Compact one-line status, e.g. "UPS · In transit · Memphis TN · ETA Mar 2".
+    /// Only set, by [`PackageWithStatus::fill_summary`], for list responses
+    /// that ask for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl PackageWithStatus {
+    /// Set `summary` from the courier, status, location and ETA (or
+    /// predicted arrival).
+    pub fn fill_summary(&mut self) {
+        self.summary = Some(compact_summary(
+            &self.courier,
+            &self.status,
+            self.last_known_location.as_deref(),
+            self.estimated_arrival_date.as_deref().or(self.predicted_arrival_date.as_deref()),
+        ));
+    }
+}
+
+/// One-line package summary, e.g. "UPS · In transit · Memphis TN · ETA Mar 2".
+/// Missing location or ETA parts are left out.
+fn compact_summary(courier: &str, status: &str, location: Option<&str>, eta: Option<&str>) -> String {
+    let mut status = status.replace('_', " ");
+    if let Some(first) = status.get(..1) {
+        status.replace_range(..1, &first.to_uppercase());
+    }
+
+    let mut parts = vec![courier.to_string(), status];
+    if let Some(location) = location.map(|l| l.replace(',', "")).filter(|l| !l.trim().is_empty()) {
+        parts.push(location);
+    }
+    if let Some(date) = eta
+        .and_then(|eta| eta.get(..10))
+        .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+    {
+        parts.push(format!("ETA {}", date.format("%b %-d")));
+    }
+    parts.join(" · ")
 }
 
 #[derive(Debug, Serialize)]
 pub struct StatusHistoryEntry {
     pub status: String,
     pub description: Option<String>,
+    pub short_description: Option<String>,
     pub last_known_location: Option<String>,
     pub checked_at: String,
+    pub delivery_location_detail: Option<String>,
+    pub courier_status_text: Option<String>,
+    /// The courier's raw status code, e.g. FedEx "DL" or UPS "D".
+    pub courier_status_code: Option<String>,
+}
+
+/// Everything known about one package, composed in a single database call.
+#[derive(Debug, Serialize)]
+pub struct Shipment {
+    pub package: PackageWithStatus,
+    pub status: ShipmentStatus,
+    /// The most recent ETA any scan reported.
+    pub estimated_arrival_date: Option<String>,
+    /// Distinct locations passed through, oldest first.
+    pub route: Vec<String>,
+    /// The email the package was found in; `None` for manually added packages.
+    pub source: Option<ShipmentSource>,
+    /// Full status history, newest first.
+    pub history: Vec<StatusHistoryEntry>,
+}
+
+/// A package's current status, both normalized and in the courier's words.
+#[derive(Debug, Serialize)]
+pub struct ShipmentStatus {
+    pub normalized: String,
+    pub courier_text: Option<String>,
+    pub courier_code: Option<String>,
+}
+
+/// One package in a full export: its current status and where it came from.
+#[derive(Debug, Serialize)]
+pub struct ExportRow {
+    pub id: i64,
+    pub tracking_number: String,
+    /// Courier code, e.g. "ups".
+    pub courier: String,
+    pub service: String,
+    pub description: Option<String>,
+    pub status: String,
+    pub courier_status_text: Option<String>,
+    pub last_known_location: Option<String>,
+    pub estimated_arrival_date: Option<String>,
+    /// When the current status was recorded.
+    pub status_checked_at: Option<String>,
+    pub tracking_url: Option<String>,
+    pub created_at: String,
+    pub source_email_subject: Option<String>,
+    pub source_email_from: Option<String>,
+    /// When the source email was received; `None` for packages added by hand.
+    pub source_email_date: Option<String>,
+    pub deleted_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShipmentSource {
+    pub email_subject: Option<String>,
+    pub email_from: Option<String>,
+    pub email_date: String,
+}
+
+/// Package counts for one courier, for dashboard charts.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct CourierCount {
+    /// Courier display name, e.g. "FedEx".
+    pub courier: String,
+    /// Packages not yet delivered or marked not found.
+    pub active: usize,
+    pub total: usize,
+}
+
+/// How the courier of a package was determined, least to most authoritative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SourceConfidence {
+    /// Inferred from the tracking number format alone.
+    Guessed = 0,
+    /// The email came from the courier's own domain.
+    Sender = 1,
+    /// Forced by an `email.courier_overrides` rule.
+    Override = 2,
+    /// Entered by the user.
+    Manual = 3,
 }
 
 pub struct NewPackage {
@@ -80,6 +223,44 @@ pub struct NewPackage {
     pub source_email_subject: Option<String>,
     pub source_email_from: Option<String>,
     pub source_email_date: DateTime<Utc>,
+    pub source_confidence: SourceConfidence,
+    pub destination_zip: Option<String>,
+    pub description: Option<String>,
+}
+
+/// An order confirmation seen before any tracking number for it, waiting to
+/// be linked to the package from the shipping email.
+#[derive(Debug, Serialize)]
+pub struct OrderPlaceholder {
+    pub id: i64,
+    pub merchant: String,
+    pub order_number: String,
+    pub source_email_subject: Option<String>,
+    pub created_at: String,
+}
+
+pub struct NewOrderPlaceholder {
+    pub merchant: String,
+    pub order_number: String,
+    pub source_email_uid: u32,
+    pub source_email_subject: Option<String>,
+}
+
+/// Optional details recorded alongside a status check. `checked_at` defaults
+/// to the insertion time when unset.
+#[derive(Debug, Default)]
+pub struct StatusDetails<'a> {
+    pub estimated_arrival_date: Option<&'a str>,
+    pub last_known_location: Option<&'a str>,
+    pub description: Option<&'a str>,
+    /// Short form of `description`, with `status.short_descriptions`.
+    pub short_description: Option<&'a str>,
+    pub checked_at: Option<&'a str>,
+    /// Where a delivered package was left, e.g. "Front Porch".
+    pub delivery_location_detail: Option<&'a str>,
+    /// The courier's own status wording, stored verbatim.
+    pub courier_status_text: Option<&'a str>,
+    pub courier_status_code: Option<&'a str>,
 }
 
 pub trait Database: Send {
@@ -89,33 +270,165 @@ pub trait Database: Send {
     /// Update the highest IMAP UID we have processed for the given folder.
     fn set_last_seen_uid(&mut self, folder: &str, uid: u32) -> Result<()>;
 
-    /// Insert a package if the tracking number doesn't already exist.
-    /// Returns `true` if a new row was inserted.
+    /// When the status poller last completed a poll, if it ever has.
+    fn get_last_status_poll(&self) -> Result<Option<DateTime<Utc>>>;
+
+    /// Record when the status poller completed a poll.
+    fn set_last_status_poll(&mut self, at: DateTime<Utc>) -> Result<()>;
+
+    /// Insert a package if the tracking number doesn't already exist. A
+    /// soft-deleted package with the same tracking number is restored instead.
+    /// Returns `true` if a row was inserted or restored.
     fn insert_package(&mut self, package: &NewPackage) -> Result<bool>;
 
+    /// If `package` is already stored under a different courier with lower
+    /// source confidence, switch it to the new courier, service and tracking
+    /// URL. Returns true if the stored package was updated.
+    fn replace_courier_if_more_confident(&mut self, package: &NewPackage) -> Result<bool>;
+
     /// Get all packages that have not yet been delivered.
     fn get_active_packages(&self) -> Result<Vec<Package>>;
 
+    /// Record an order awaiting its tracking number. Returns `true` if it is
+    /// new, `false` if the merchant's order was already recorded.
+    fn insert_order_placeholder(&mut self, placeholder: &NewOrderPlaceholder) -> Result<bool>;
+
+    /// Link every unlinked placeholder for `order_number` to a package.
+    /// Returns how many were linked.
+    fn link_order_placeholders(&mut self, order_number: &str, package_id: i64) -> Result<usize>;
+
+    /// Orders still waiting for a tracking number, oldest first.
+    fn get_awaiting_orders(&self) -> Result<Vec<OrderPlaceholder>>;
+
+    /// Get active packages whose most recent ETA is today (local time).
+    fn get_packages_arriving_today(&self) -> Result<Vec<Package>>;
+
+    /// Get all packages that have not yet been delivered, soonest ETA first;
+    /// packages without an ETA come last, oldest first.
+    fn get_active_packages_by_eta(&self) -> Result<Vec<Package>>;
+
+    /// Get packages that have never had a status check recorded, oldest first.
+    fn get_unscanned_packages(&self) -> Result<Vec<Package>>;
+
     /// Get all packages with their latest status details.
     fn get_all_packages_with_status(&self) -> Result<Vec<PackageWithStatus>>;
 
+    /// Get all packages, including soft-deleted ones, with their latest status details.
+    fn get_all_packages_with_status_including_deleted(&self) -> Result<Vec<PackageWithStatus>>;
+
+    /// Get every package for export, oldest first, optionally including
+    /// soft-deleted ones.
+    fn export_packages(&self, include_deleted: bool) -> Result<Vec<ExportRow>>;
+
+    /// Get a single non-deleted package with its latest status details.
+    fn get_package_with_status(&self, package_id: i64) -> Result<Option<PackageWithStatus>>;
+
+    /// Get a single non-deleted package with its latest status details by tracking number.
+    fn get_by_tracking_number(&self, number: &str) -> Result<Option<PackageWithStatus>>;
+
+    /// Get a non-deleted package with its status, ETA, route, source email and history.
+    fn get_shipment(&self, package_id: i64) -> Result<Option<Shipment>>;
+
+    /// Get the email a non-deleted package was found in. `None` if there's no
+    /// such package or it was added by hand.
+    fn get_package_source(&self, package_id: i64) -> Result<Option<ShipmentSource>>;
+
+    /// Get a single non-deleted package by id.
+    fn get_package(&self, package_id: i64) -> Result<Option<Package>>;
+
+    /// Count non-deleted packages per courier (by display name), ordered by name.
+    fn courier_distribution(&self) -> Result<Vec<CourierCount>>;
+
     /// Get the full status history for a package, newest first.
     fn get_package_status_history(&self, package_id: i64) -> Result<Vec<StatusHistoryEntry>>;
 
-    /// Insert a status check record into package_status history.
+    /// Get the distinct locations a package passed through, oldest first,
+    /// collapsing consecutive repeats.
+    fn location_path(&self, package_id: i64) -> Result<Vec<String>>;
+
+    /// Insert a status check record into package_status history. An event
+    /// already recorded with the same `checked_at` and description is
+    /// ignored; without a courier `checked_at`, a repeated description reuses
+    /// the earlier row's time so re-polls don't duplicate it.
     fn insert_package_status(
         &mut self,
         package_id: i64,
         status: &PackageStatus,
-        estimated_arrival_date: Option<&str>,
-        last_known_location: Option<&str>,
-        description: Option<&str>,
-        checked_at: Option<&str>,
+        details: &StatusDetails,
     ) -> Result<()>;
 
     /// Delete all status history for a package, resetting it to "waiting".
     fn delete_all_package_status(&mut self, package_id: i64) -> Result<()>;
 
+    /// Record a delivery inferred by `status.assume_delivered_after_days`,
+    /// marked so that the next courier result can replace it.
+    fn insert_assumed_delivery(&mut self, package_id: i64, description: &str) -> Result<()>;
+
+    /// Delete a package's inferred deliveries, leaving its courier scans.
+    fn delete_assumed_deliveries(&mut self, package_id: i64) -> Result<()>;
+
+    /// Record (or clear, with `None`) the message from the last failed courier check.
+    fn set_last_error(&mut self, package_id: i64, message: Option<&str>) -> Result<()>;
+
+    /// Get the ETA value an arriving-soon notification was last sent for.
+    fn get_eta_notified(&self, package_id: i64) -> Result<Option<String>>;
+
+    /// Remember that an arriving-soon notification was sent for this ETA value.
+    fn set_eta_notified(&mut self, package_id: i64, eta: &str) -> Result<()>;
+
+    /// Whether a delivered scan is waiting for a second poll to confirm it
+    /// (`status.confirm_delivered`).
+    fn is_delivery_pending(&self, package_id: i64) -> Result<bool>;
+
+    fn set_delivery_pending(&mut self, package_id: i64, pending: bool) -> Result<()>;
+
+    /// The schema version (`PRAGMA user_version`), i.e. the number of migrations applied.
+    fn schema_version(&self) -> Result<u32>;
+
+    /// Run `PRAGMA integrity_check`, returning its rows (`["ok"]` when healthy).
+    fn integrity_check(&self) -> Result<Vec<String>>;
+
+    /// Rebuild the database file to reclaim free space.
+    fn vacuum(&mut self) -> Result<()>;
+
+    /// Soft-delete up to `limit` packages whose latest status is delivered and
+    /// was recorded before `cutoff`. Returns the number archived.
+    fn archive_delivered_before(&mut self, cutoff: DateTime<Utc>, limit: usize) -> Result<usize>;
+
+    /// Delete up to `limit` status history entries beyond the newest `keep`
+    /// per delivered package. Returns the number deleted. Packages still being
+    /// polled are left alone: couriers return the full history on every
+    /// check, so pruned events would come back as the newest entries.
+    fn prune_status_history(&mut self, keep: usize, limit: usize) -> Result<usize>;
+
+    /// Checkpoint the write-ahead log into the database and truncate it.
+    fn checkpoint_wal(&mut self) -> Result<()>;
+
+    /// Run a trivial query to confirm the connection works.
+    fn ping(&self) -> Result<()>;
+
     /// Soft-delete a package by setting deleted_at. Returns true if a row was updated.
     fn delete_package(&mut self, package_id: i64) -> Result<bool>;
+
+    /// Undo a soft delete by clearing deleted_at. Returns true if a row was updated.
+    fn restore_package(&mut self, package_id: i64) -> Result<bool>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_compact_summary() {
+        assert_eq!(
+            compact_summary("UPS", "in_transit", Some("Memphis, TN"), Some("2026-03-02")),
+            "UPS · In transit · Memphis TN · ETA Mar 2"
+        );
+        assert_eq!(
+            compact_summary("FedEx", "delivered", Some("Austin, TX"), Some("2026-03-02T14:00:00-06:00")),
+            "FedEx · Delivered · Austin TX · ETA Mar 2"
+        );
+        assert_eq!(compact_summary("USPS", "ready_for_pickup", None, None), "USPS · Ready for pickup");
+        assert_eq!(compact_summary("UPS", "waiting", Some(""), Some("soon")), "UPS · Waiting");
+    }
 }
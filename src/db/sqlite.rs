@@ -1,39 +1,143 @@
-use super::{Database, NewPackage, Package, PackageStatus, PackageWithStatus, StatusHistoryEntry};
+use super::{
+    CourierCount, Database, ExportRow, NewOrderPlaceholder, NewPackage, OrderPlaceholder, Package, PackageStatus,
+    PackageWithStatus, Shipment, ShipmentSource, ShipmentStatus, StatusDetails, StatusHistoryEntry,
+};
+use crate::config::DatabaseConfig;
 use crate::courier::CourierCode;
 use anyhow::{Context, Result};
-use rusqlite::Connection;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OpenFlags};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
-use tracing::info;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Minimum number of delivered packages with the same courier/service
+/// required before a transit-time prediction is made.
+const MIN_PREDICTION_SAMPLES: usize = 3;
+
+/// How long computed median transit times are reused. They shift slowly, and
+/// recomputing them scans the whole delivery history.
+const TRANSIT_TIMES_TTL: std::time::Duration = std::time::Duration::from_secs(600);
+
+type TransitTimes = HashMap<(String, String), Duration>;
+
+/// Schema migrations in order; the schema version (`PRAGMA user_version`) is
+/// the number applied.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0001_create_packages_and_metadata.sql"),
+    include_str!("../../migrations/0002_create_package_status.sql"),
+    include_str!("../../migrations/0003_add_eta_and_location.sql"),
+    include_str!("../../migrations/0004_add_status_description.sql"),
+    include_str!("../../migrations/0005_add_tracking_url.sql"),
+    include_str!("../../migrations/0006_add_deleted_at.sql"),
+    include_str!("../../migrations/0007_normalize_dates_rfc3339.sql"),
+    include_str!("../../migrations/0008_add_short_description.sql"),
+    include_str!("../../migrations/0009_add_last_error.sql"),
+    include_str!("../../migrations/0010_add_eta_notified.sql"),
+    include_str!("../../migrations/0011_add_delivery_location_detail.sql"),
+    include_str!("../../migrations/0012_add_courier_status_text.sql"),
+    include_str!("../../migrations/0013_add_source_confidence.sql"),
+    include_str!("../../migrations/0014_add_destination_zip.sql"),
+    include_str!("../../migrations/0015_add_courier_status_code.sql"),
+    include_str!("../../migrations/0016_create_order_placeholders.sql"),
+    include_str!("../../migrations/0017_add_delivery_pending.sql"),
+    include_str!("../../migrations/0018_add_package_description.sql"),
+    include_str!("../../migrations/0019_index_package_status_latest.sql"),
+    include_str!("../../migrations/0020_dedup_package_status_by_event.sql"),
+    include_str!("../../migrations/0021_add_status_assumed.sql"),
+    include_str!("../../migrations/0022_canonicalize_tracking_numbers.sql"),
+];
 
 pub struct SqliteDatabase {
     conn: Connection,
+    /// Median transit times and when they were computed, shared by every
+    /// query that predicts arrivals. Cleared when a delivery is recorded.
+    transit_times: RefCell<Option<(Instant, Arc<TransitTimes>)>>,
 }
 
 impl SqliteDatabase {
+    /// Open the database described by `config`, decrypting it with
+    /// `encryption_key` when one is set.
+    pub fn from_config(config: &DatabaseConfig) -> Result<Self> {
+        match &config.encryption_key {
+            #[cfg(feature = "sqlcipher")]
+            Some(key) => Self::open_encrypted(&config.path, key),
+            #[cfg(not(feature = "sqlcipher"))]
+            Some(_) => anyhow::bail!("database.encryption_key requires the `sqlcipher` feature"),
+            None => Self::open(&config.path),
+        }
+    }
+
     pub fn open(path: &str) -> Result<Self> {
         let conn = Connection::open(path)
             .with_context(|| format!("Failed to open database at {path}"))?;
 
+        // An encrypted file is indistinguishable from garbage until keyed
+        conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+            .with_context(|| format!(
+                "Failed to read database at {path}; if it is encrypted, set database.encryption_key"
+            ))?;
+
+        Self::init(conn)
+    }
+
+    /// Open a SQLCipher-encrypted database, creating it if it doesn't exist.
+    #[cfg(feature = "sqlcipher")]
+    pub fn open_encrypted(path: &str, key: &str) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database at {path}"))?;
+
+        conn.pragma_update(None, "key", key)
+            .context("Failed to set database encryption key")?;
+
+        conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+            .with_context(|| format!(
+                "Failed to decrypt database at {path}; the key is wrong or the database is not encrypted"
+            ))?;
+
+        Self::init(conn)
+    }
+
+    /// Open an existing database for reading only. Every write method on the
+    /// returned handle fails; migrations are not run.
+    pub fn open_read_only(path: &str) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open database at {path} read-only"))?;
+
+        // Belt and braces: reject writes even if the file itself is writable
+        conn.pragma_update(None, "query_only", true)
+            .context("Failed to enable query_only mode")?;
+
+        // Migrations can't be run here, so an outdated copy would only fail
+        // later with confusing SQL errors
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .with_context(|| format!("Failed to read schema version of database at {path}"))?;
+        if version < MIGRATIONS.len() {
+            anyhow::bail!(
+                "Database at {path} has schema version {version}, but this version of trackage needs {}; \
+                 let it catch up with the primary database first",
+                MIGRATIONS.len()
+            );
+        }
+
+        Ok(Self { conn, transit_times: RefCell::default() })
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
         conn.pragma_update(None, "journal_mode", "WAL")
             .context("Failed to enable WAL mode")?;
 
-        let mut db = Self { conn };
+        let mut db = Self { conn, transit_times: RefCell::default() };
         db.migrate()?;
 
         Ok(db)
     }
 
     fn migrate(&mut self) -> Result<()> {
-        const MIGRATIONS: &[&str] = &[
-            include_str!("../../migrations/0001_create_packages_and_metadata.sql"),
-            include_str!("../../migrations/0002_create_package_status.sql"),
-            include_str!("../../migrations/0003_add_eta_and_location.sql"),
-            include_str!("../../migrations/0004_add_status_description.sql"),
-            include_str!("../../migrations/0005_add_tracking_url.sql"),
-            include_str!("../../migrations/0006_add_deleted_at.sql"),
-            include_str!("../../migrations/0007_normalize_dates_rfc3339.sql"),
-        ];
-
         let version: u32 = self
             .conn
             .pragma_query_value(None, "user_version", |row| row.get(0))
@@ -54,72 +158,129 @@ impl SqliteDatabase {
 
         Ok(())
     }
-}
-
-impl Database for SqliteDatabase {
-    fn get_last_seen_uid(&self, folder: &str) -> Result<u32> {
-        let scoped_key = format!("last_seen_uid:{folder}");
 
-        let result: Option<String> = self
+    /// Rewrite courier values stored as display names or full names ("UPS",
+    /// "United States Postal Service") to canonical codes, so older rows
+    /// route to the right courier client. Unrecognized values are logged and
+    /// left alone. Returns how many packages changed; safe to run repeatedly.
+    pub fn normalize_couriers(&mut self) -> Result<usize> {
+        let mut stmt = self
             .conn
-            .query_row(
-                "SELECT value FROM metadata WHERE key = ?1",
-                [&scoped_key],
-                |row| row.get(0),
-            )
-            .optional()
-            .context("Failed to query last_seen_uid")?;
+            .prepare("SELECT DISTINCT courier FROM packages")
+            .context("Failed to prepare courier query")?;
+        let couriers = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .context("Failed to query couriers")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read courier rows")?;
+        drop(stmt);
 
-        match result {
-            Some(val) => val
-                .parse::<u32>()
-                .context("Invalid last_seen_uid value in metadata"),
-            None => Ok(0),
+        let mut normalized = 0;
+        for raw in couriers {
+            match raw.parse::<CourierCode>() {
+                Ok(code) if code.to_string() == raw => {}
+                Ok(code) => {
+                    normalized += self
+                        .conn
+                        .execute(
+                            "UPDATE packages SET courier = ?1 WHERE courier = ?2",
+                            rusqlite::params![code.to_string(), raw],
+                        )
+                        .context("Failed to normalize package courier")?;
+                }
+                Err(_) => warn!(courier = %raw, "Unrecognized courier stored in database; leaving it unchanged"),
+            }
         }
-    }
 
-    fn set_last_seen_uid(&mut self, folder: &str, uid: u32) -> Result<()> {
-        let scoped_key = format!("last_seen_uid:{folder}");
+        if normalized > 0 {
+            info!(packages = normalized, "Normalized stored courier names to courier codes");
+        }
+        Ok(normalized)
+    }
 
-        self.conn
-            .execute(
-                "INSERT INTO metadata (key, value) VALUES (?1, ?2)
-                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
-                rusqlite::params![scoped_key, uid.to_string()],
-            )
-            .context("Failed to update last_seen_uid")?;
+    /// [`Self::median_transit_times`], reusing the last result for up to
+    /// [`TRANSIT_TIMES_TTL`].
+    fn cached_transit_times(&self) -> Result<Arc<TransitTimes>> {
+        if let Some((computed, transit_times)) = &*self.transit_times.borrow()
+            && computed.elapsed() < TRANSIT_TIMES_TTL
+        {
+            return Ok(Arc::clone(transit_times));
+        }
 
-        Ok(())
+        let transit_times = Arc::new(self.median_transit_times()?);
+        *self.transit_times.borrow_mut() = Some((Instant::now(), Arc::clone(&transit_times)));
+        Ok(transit_times)
     }
 
-    fn insert_package(&mut self, package: &NewPackage) -> Result<bool> {
-        let changes = self
+    /// Median transit time, keyed by (courier code, service), of delivered
+    /// packages. Transit time runs from `created_at` to the first delivered scan.
+    fn median_transit_times(&self) -> Result<TransitTimes> {
+        let mut stmt = self
             .conn
-            .execute(
-                "INSERT OR IGNORE INTO packages
-                    (tracking_number, courier, service, tracking_url, source_email_uid,
-                     source_email_subject, source_email_from, source_email_date, created_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))",
-                rusqlite::params![
-                    package.tracking_number,
-                    package.courier,
-                    package.service,
-                    package.tracking_url,
-                    package.source_email_uid,
-                    package.source_email_subject,
-                    package.source_email_from,
-                    package.source_email_date.to_rfc3339(),
-                ],
+            .prepare(
+                "SELECT p.courier, p.service, p.created_at, MIN(ps.checked_at)
+                 FROM packages p
+                 JOIN package_status ps ON ps.package_id = p.id
+                 WHERE ps.status = 'delivered'
+                 GROUP BY p.id",
             )
-            .context("Failed to insert package")?;
+            .context("Failed to prepare transit history query")?;
 
-        Ok(changes > 0)
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .context("Failed to query transit history")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read transit history rows")?;
+
+        let mut samples: HashMap<(String, String), Vec<Duration>> = HashMap::new();
+        for (courier, service, created_at, delivered_at) in rows {
+            let (Ok(created), Ok(delivered)) = (
+                DateTime::parse_from_rfc3339(&created_at),
+                DateTime::parse_from_rfc3339(&delivered_at),
+            ) else {
+                continue;
+            };
+            let transit = delivered.signed_duration_since(created);
+            if transit > Duration::zero() {
+                samples
+                    .entry((normalize_courier(&courier), service))
+                    .or_default()
+                    .push(transit);
+            }
+        }
+
+        Ok(samples
+            .into_iter()
+            .filter(|(_, durations)| durations.len() >= MIN_PREDICTION_SAMPLES)
+            .map(|(key, mut durations)| {
+                durations.sort();
+                let mid = durations.len() / 2;
+                let median = if durations.len() % 2 == 0 {
+                    (durations[mid - 1] + durations[mid]) / 2
+                } else {
+                    durations[mid]
+                };
+                (key, median)
+            })
+            .collect())
     }
 
-    fn get_active_packages(&self) -> Result<Vec<Package>> {
+    /// Query non-deleted packages with their current status. `filter` is
+    /// appended to a SELECT over the `current_status` CTE, whose columns are
+    /// `id`, `tracking_number`, `courier`, `service`, `status`, `created_at`,
+    /// `eta` (the most recent non-null ETA), and `scanned` (whether any status
+    /// history exists).
+    fn query_packages<P: rusqlite::Params>(&self, filter: &str, params: P) -> Result<Vec<Package>> {
         let mut stmt = self
             .conn
-            .prepare(
+            .prepare(&format!(
                 "WITH current_status AS (
                     SELECT p.id, p.tracking_number, p.courier, p.service,
                            COALESCE(
@@ -127,16 +288,22 @@ impl Database for SqliteDatabase {
                                 WHERE ps.package_id = p.id
                                 ORDER BY ps.id DESC LIMIT 1),
                                'waiting'
-                           ) AS status
+                           ) AS status,
+                           p.created_at,
+                           (SELECT ps.estimated_arrival_date FROM package_status ps
+                            WHERE ps.package_id = p.id AND ps.estimated_arrival_date IS NOT NULL
+                            ORDER BY ps.id DESC LIMIT 1) AS eta,
+                           EXISTS (SELECT 1 FROM package_status ps WHERE ps.package_id = p.id) AS scanned,
+                           p.destination_zip
                     FROM packages p
                     WHERE p.deleted_at IS NULL
                 )
-                SELECT * FROM current_status WHERE status NOT IN ('delivered', 'not_found')",
-            )
-            .context("Failed to prepare get_active_packages query")?;
+                SELECT id, tracking_number, courier, service, status, destination_zip FROM current_status {filter}"
+            ))
+            .context("Failed to prepare packages query")?;
 
         let packages = stmt
-            .query_map([], |row| {
+            .query_map(params, |row| {
                 let status_str: String = row.get(4)?;
                 Ok((
                     row.get::<_, i64>(0)?,
@@ -144,56 +311,87 @@ impl Database for SqliteDatabase {
                     row.get::<_, String>(2)?,
                     row.get::<_, String>(3)?,
                     status_str,
+                    row.get::<_, Option<String>>(5)?,
                 ))
             })
-            .context("Failed to query active packages")?
+            .context("Failed to query packages")?
             .collect::<std::result::Result<Vec<_>, _>>()
-            .context("Failed to read active packages rows")?;
+            .context("Failed to read packages rows")?;
 
         packages
             .into_iter()
-            .map(|(id, tracking_number, courier, service, status_str)| {
+            .map(|(id, tracking_number, courier, service, status_str, destination_zip)| {
                 let status = PackageStatus::from_str(&status_str)
                     .with_context(|| format!("Invalid status '{status_str}' for package {id}"))?;
-                let courier = courier
-                    .parse::<CourierCode>()
-                    .map(|c| c.to_string())
-                    .unwrap_or(courier);
                 Ok(Package {
                     id,
                     tracking_number,
-                    courier,
+                    courier: normalize_courier(&courier),
                     service,
                     status,
+                    destination_zip,
                 })
             })
             .collect()
     }
 
-    fn get_all_packages_with_status(&self) -> Result<Vec<PackageWithStatus>> {
+    /// Query packages with their latest status details. `filter` is the WHERE
+    /// condition (e.g. `"p.deleted_at IS NULL AND p.id = ?1"`), bound with `params`.
+    fn query_packages_with_status<P: rusqlite::Params>(
+        &self,
+        filter: &str,
+        params: P,
+    ) -> Result<Vec<PackageWithStatus>> {
         let mut stmt = self
             .conn
-            .prepare(
+            .prepare(&format!(
                 "SELECT p.id, p.tracking_number, p.courier, p.service,
                         COALESCE(ps.status, 'waiting') AS status,
                         ps.last_known_location,
+                        (SELECT ps3.estimated_arrival_date FROM package_status ps3
+                         WHERE ps3.package_id = p.id AND ps3.estimated_arrival_date IS NOT NULL
+                         ORDER BY ps3.id DESC LIMIT 1) AS estimated_arrival_date,
                         p.tracking_url,
                         p.source_email_from,
-                        p.created_at
+                        p.created_at,
+                        p.last_error,
+                        ps.courier_status_text,
+                        p.description,
+                        p.source_email_subject,
+                        CASE WHEN p.source_email_uid != 0 THEN p.source_email_date END,
+                        p.deleted_at
                  FROM packages p
                  LEFT JOIN package_status ps ON ps.id = (
                      SELECT ps2.id FROM package_status ps2
                      WHERE ps2.package_id = p.id
                      ORDER BY ps2.id DESC LIMIT 1
                  )
-                 WHERE p.deleted_at IS NULL
-                 ORDER BY p.created_at DESC",
-            )
-            .context("Failed to prepare get_all_packages_with_status query")?;
+                 WHERE {filter}
+                 ORDER BY p.created_at DESC"
+            ))
+            .context("Failed to prepare packages with status query")?;
+
+        let transit_times = self.cached_transit_times()?;
 
         let packages = stmt
-            .query_map([], |row| {
+            .query_map(params, |row| {
                 let courier_raw: String = row.get(2)?;
+                let service: String = row.get(3)?;
+                let status: String = row.get(4)?;
+                let estimated_arrival_date: Option<String> = row.get(6)?;
+                let created_at: String = row.get(9)?;
+
+                let predicted_arrival_date = if status == "in_transit" && estimated_arrival_date.is_none() {
+                    transit_times
+                        .get(&(normalize_courier(&courier_raw), service.clone()))
+                        .zip(DateTime::parse_from_rfc3339(&created_at).ok())
+                        .map(|(transit, created)| {
+                            (created.with_timezone(&Utc) + *transit).format("%Y-%m-%d").to_string()
+                        })
+                } else {
+                    None
+                };
+
                 let courier = courier_raw
                     .parse::<CourierCode>()
                     .map(|c| c.display_name().to_string())
@@ -202,12 +400,21 @@ impl Database for SqliteDatabase {
                     id: row.get(0)?,
                     tracking_number: row.get(1)?,
                     courier,
-                    service: row.get(3)?,
-                    status: row.get(4)?,
+                    service,
+                    description: row.get(12)?,
+                    status,
+                    courier_status_text: row.get(11)?,
                     last_known_location: row.get(5)?,
-                    tracking_url: row.get(6)?,
-                    source_email_from: row.get(7)?,
-                    created_at: row.get(8)?,
+                    estimated_arrival_date,
+                    predicted_arrival_date,
+                    tracking_url: row.get(7)?,
+                    source_email_from: row.get(8)?,
+                    source_email_subject: row.get(13)?,
+                    source_email_date: row.get(14)?,
+                    created_at,
+                    last_error: row.get(10)?,
+                    deleted_at: row.get(15)?,
+                    summary: None,
                 })
             })
             .context("Failed to query packages with status")?
@@ -216,85 +423,1422 @@ impl Database for SqliteDatabase {
 
         Ok(packages)
     }
+}
 
-    fn get_package_status_history(&self, package_id: i64) -> Result<Vec<StatusHistoryEntry>> {
-        let mut stmt = self
+/// Canonical courier code for a stored courier value, or the value itself if unrecognized.
+fn normalize_courier(courier: &str) -> String {
+    courier
+        .parse::<CourierCode>()
+        .map(|c| c.to_string())
+        .unwrap_or_else(|_| courier.to_string())
+}
+
+/// The form tracking numbers are stored in, so one package found in emails
+/// that format its number differently ("1z5r 8939-0357") maps to one row,
+/// whichever courier each email was detected as.
+fn canonical_tracking_number(number: &str) -> String {
+    number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+impl Database for SqliteDatabase {
+    fn get_last_seen_uid(&self, folder: &str) -> Result<u32> {
+        let scoped_key = format!("last_seen_uid:{folder}");
+
+        let result: Option<String> = self
             .conn
-            .prepare(
-                "SELECT status, description, last_known_location, checked_at
-                 FROM package_status
-                 WHERE package_id = ?1
-                 ORDER BY id DESC",
+            .query_row(
+                "SELECT value FROM metadata WHERE key = ?1",
+                [&scoped_key],
+                |row| row.get(0),
             )
-            .context("Failed to prepare get_package_status_history query")?;
-
-        let entries = stmt
-            .query_map([package_id], |row| {
-                Ok(StatusHistoryEntry {
-                    status: row.get(0)?,
-                    description: row.get(1)?,
-                    last_known_location: row.get(2)?,
-                    checked_at: row.get(3)?,
-                })
-            })
-            .context("Failed to query package status history")?
-            .collect::<std::result::Result<Vec<_>, _>>()
-            .context("Failed to read package status history rows")?;
+            .optional()
+            .context("Failed to query last_seen_uid")?;
 
-        Ok(entries)
+        match result {
+            Some(val) => val
+                .parse::<u32>()
+                .context("Invalid last_seen_uid value in metadata"),
+            None => Ok(0),
+        }
     }
 
-    fn insert_package_status(
-        &mut self,
-        package_id: i64,
-        status: &PackageStatus,
-        estimated_arrival_date: Option<&str>,
-        last_known_location: Option<&str>,
-        description: Option<&str>,
-        checked_at: Option<&str>,
-    ) -> Result<()> {
+    fn set_last_seen_uid(&mut self, folder: &str, uid: u32) -> Result<()> {
+        let scoped_key = format!("last_seen_uid:{folder}");
+
         self.conn
             .execute(
-                "INSERT OR IGNORE INTO package_status
-                    (package_id, status, estimated_arrival_date, last_known_location, description, checked_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, COALESCE(?6, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')))",
-                rusqlite::params![
-                    package_id,
-                    status.to_string(),
-                    estimated_arrival_date,
-                    last_known_location,
-                    description,
-                    checked_at,
-                ],
+                "INSERT INTO metadata (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                rusqlite::params![scoped_key, uid.to_string()],
             )
-            .context("Failed to insert package status")?;
+            .context("Failed to update last_seen_uid")?;
 
         Ok(())
     }
 
-    fn delete_all_package_status(&mut self, package_id: i64) -> Result<()> {
+    fn get_last_status_poll(&self) -> Result<Option<DateTime<Utc>>> {
+        let result: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM metadata WHERE key = 'last_status_poll'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to query last_status_poll")?;
+
+        result
+            .map(|val| {
+                DateTime::parse_from_rfc3339(&val)
+                    .map(|at| at.with_timezone(&Utc))
+                    .context("Invalid last_status_poll value in metadata")
+            })
+            .transpose()
+    }
+
+    fn set_last_status_poll(&mut self, at: DateTime<Utc>) -> Result<()> {
         self.conn
             .execute(
-                "DELETE FROM package_status WHERE package_id = ?1",
-                [package_id],
+                "INSERT INTO metadata (key, value) VALUES ('last_status_poll', ?1)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                [at.to_rfc3339()],
             )
-            .context("Failed to delete all package status")?;
+            .context("Failed to update last_status_poll")?;
 
         Ok(())
     }
 
-    fn delete_package(&mut self, package_id: i64) -> Result<bool> {
+    fn insert_package(&mut self, package: &NewPackage) -> Result<bool> {
+        let tracking_number = canonical_tracking_number(&package.tracking_number);
         let changes = self
             .conn
             .execute(
-                "UPDATE packages SET deleted_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
-                 WHERE id = ?1 AND deleted_at IS NULL",
-                [package_id],
+                "INSERT OR IGNORE INTO packages
+                    (tracking_number, courier, service, tracking_url, source_email_uid,
+                     source_email_subject, source_email_from, source_email_date, source_confidence,
+                     destination_zip, description, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))",
+                rusqlite::params![
+                    tracking_number,
+                    package.courier,
+                    package.service,
+                    package.tracking_url,
+                    package.source_email_uid,
+                    package.source_email_subject,
+                    package.source_email_from,
+                    package.source_email_date.to_rfc3339(),
+                    package.source_confidence as i64,
+                    package.destination_zip,
+                    package.description,
+                ],
             )
-            .context("Failed to soft-delete package")?;
+            .context("Failed to insert package")?;
+
+        if changes > 0 {
+            return Ok(true);
+        }
+
+        // The insert was ignored; if the existing row was soft-deleted, bring it back
+        let restored = self
+            .conn
+            .execute(
+                "UPDATE packages SET deleted_at = NULL
+                 WHERE tracking_number = ?1 AND deleted_at IS NOT NULL",
+                [&tracking_number],
+            )
+            .context("Failed to restore soft-deleted package")?;
+
+        Ok(restored > 0)
+    }
+
+    fn replace_courier_if_more_confident(&mut self, package: &NewPackage) -> Result<bool> {
+        let changes = self
+            .conn
+            .execute(
+                "UPDATE packages
+                 SET courier = ?2, service = ?3, tracking_url = ?4, source_confidence = ?5
+                 WHERE tracking_number = ?1 AND courier != ?2 AND source_confidence < ?5",
+                rusqlite::params![
+                    canonical_tracking_number(&package.tracking_number),
+                    package.courier,
+                    package.service,
+                    package.tracking_url,
+                    package.source_confidence as i64,
+                ],
+            )
+            .context("Failed to update package courier")?;
 
         Ok(changes > 0)
     }
-}
 
-use rusqlite::OptionalExtension;
+    fn get_active_packages(&self) -> Result<Vec<Package>> {
+        self.query_packages("WHERE status NOT IN ('delivered', 'not_found')", [])
+    }
+
+    fn insert_order_placeholder(&mut self, placeholder: &NewOrderPlaceholder) -> Result<bool> {
+        let changes = self
+            .conn
+            .execute(
+                "INSERT OR IGNORE INTO order_placeholders
+                    (merchant, order_number, source_email_uid, source_email_subject, created_at)
+                 VALUES (?1, ?2, ?3, ?4, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))",
+                rusqlite::params![
+                    placeholder.merchant,
+                    placeholder.order_number,
+                    placeholder.source_email_uid,
+                    placeholder.source_email_subject,
+                ],
+            )
+            .context("Failed to insert order placeholder")?;
+
+        Ok(changes > 0)
+    }
+
+    fn link_order_placeholders(&mut self, order_number: &str, package_id: i64) -> Result<usize> {
+        self.conn
+            .execute(
+                "UPDATE order_placeholders
+                 SET package_id = ?1, linked_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+                 WHERE order_number = ?2 AND package_id IS NULL",
+                rusqlite::params![package_id, order_number],
+            )
+            .context("Failed to link order placeholders")
+    }
+
+    fn get_awaiting_orders(&self) -> Result<Vec<OrderPlaceholder>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, merchant, order_number, source_email_subject, created_at
+                 FROM order_placeholders
+                 WHERE package_id IS NULL
+                 ORDER BY created_at ASC, id ASC",
+            )
+            .context("Failed to prepare awaiting orders query")?;
+
+        let orders = stmt
+            .query_map([], |row| {
+                Ok(OrderPlaceholder {
+                    id: row.get(0)?,
+                    merchant: row.get(1)?,
+                    order_number: row.get(2)?,
+                    source_email_subject: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })
+            .context("Failed to query awaiting orders")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read awaiting order rows")?;
+
+        Ok(orders)
+    }
+
+    fn get_packages_arriving_today(&self) -> Result<Vec<Package>> {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        self.query_packages(
+            "WHERE status NOT IN ('delivered', 'not_found') AND substr(eta, 1, 10) = ?1",
+            [today],
+        )
+    }
+
+    fn get_active_packages_by_eta(&self) -> Result<Vec<Package>> {
+        self.query_packages(
+            "WHERE status NOT IN ('delivered', 'not_found')
+             ORDER BY eta IS NULL, substr(eta, 1, 10) ASC, created_at ASC",
+            [],
+        )
+    }
+
+    fn get_unscanned_packages(&self) -> Result<Vec<Package>> {
+        self.query_packages("WHERE NOT scanned ORDER BY created_at ASC", [])
+    }
+
+    fn get_all_packages_with_status(&self) -> Result<Vec<PackageWithStatus>> {
+        self.query_packages_with_status("p.deleted_at IS NULL", [])
+    }
+
+    fn get_all_packages_with_status_including_deleted(&self) -> Result<Vec<PackageWithStatus>> {
+        self.query_packages_with_status("1", [])
+    }
+
+    fn export_packages(&self, include_deleted: bool) -> Result<Vec<ExportRow>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT p.id, p.tracking_number, p.courier, p.service, p.description,
+                        COALESCE(ps.status, 'waiting'),
+                        ps.courier_status_text,
+                        ps.last_known_location,
+                        (SELECT ps3.estimated_arrival_date FROM package_status ps3
+                         WHERE ps3.package_id = p.id AND ps3.estimated_arrival_date IS NOT NULL
+                         ORDER BY ps3.id DESC LIMIT 1),
+                        ps.checked_at,
+                        p.tracking_url,
+                        p.created_at,
+                        p.source_email_subject,
+                        p.source_email_from,
+                        CASE WHEN p.source_email_uid != 0 THEN p.source_email_date END,
+                        p.deleted_at
+                 FROM packages p
+                 LEFT JOIN package_status ps ON ps.id = (
+                     SELECT ps2.id FROM package_status ps2
+                     WHERE ps2.package_id = p.id
+                     ORDER BY ps2.id DESC LIMIT 1
+                 )
+                 WHERE ?1 OR p.deleted_at IS NULL
+                 ORDER BY p.created_at, p.id",
+            )
+            .context("Failed to prepare export query")?;
+
+        let rows = stmt
+            .query_map([include_deleted], |row| {
+                Ok(ExportRow {
+                    id: row.get(0)?,
+                    tracking_number: row.get(1)?,
+                    courier: row.get(2)?,
+                    service: row.get(3)?,
+                    description: row.get(4)?,
+                    status: row.get(5)?,
+                    courier_status_text: row.get(6)?,
+                    last_known_location: row.get(7)?,
+                    estimated_arrival_date: row.get(8)?,
+                    status_checked_at: row.get(9)?,
+                    tracking_url: row.get(10)?,
+                    created_at: row.get(11)?,
+                    source_email_subject: row.get(12)?,
+                    source_email_from: row.get(13)?,
+                    source_email_date: row.get(14)?,
+                    deleted_at: row.get(15)?,
+                })
+            })
+            .context("Failed to query export")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read export rows")?;
+
+        Ok(rows)
+    }
+
+    fn get_package_with_status(&self, package_id: i64) -> Result<Option<PackageWithStatus>> {
+        Ok(self
+            .query_packages_with_status("p.deleted_at IS NULL AND p.id = ?1", [package_id])?
+            .into_iter()
+            .next())
+    }
+
+    fn get_by_tracking_number(&self, number: &str) -> Result<Option<PackageWithStatus>> {
+        Ok(self
+            .query_packages_with_status("p.deleted_at IS NULL AND p.tracking_number = ?1", [canonical_tracking_number(number)])?
+            .into_iter()
+            .next())
+    }
+
+    fn get_shipment(&self, package_id: i64) -> Result<Option<Shipment>> {
+        let Some(package) = self.get_package_with_status(package_id)? else {
+            return Ok(None);
+        };
+
+        let source = self.get_package_source(package_id)?;
+        let history = self.get_package_status_history(package_id)?;
+        let status = ShipmentStatus {
+            normalized: package.status.clone(),
+            courier_text: package.courier_status_text.clone(),
+            courier_code: history.first().and_then(|entry| entry.courier_status_code.clone()),
+        };
+
+        Ok(Some(Shipment {
+            estimated_arrival_date: package.estimated_arrival_date.clone(),
+            route: self.location_path(package_id)?,
+            status,
+            source,
+            history,
+            package,
+        }))
+    }
+
+    fn get_package_source(&self, package_id: i64) -> Result<Option<ShipmentSource>> {
+        self.conn
+            .query_row(
+                "SELECT source_email_subject, source_email_from, source_email_date
+                 FROM packages WHERE id = ?1 AND source_email_uid != 0 AND deleted_at IS NULL",
+                [package_id],
+                |row| {
+                    Ok(ShipmentSource {
+                        email_subject: row.get(0)?,
+                        email_from: row.get(1)?,
+                        email_date: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+            .context("Failed to query package source email")
+    }
+
+    fn get_package(&self, package_id: i64) -> Result<Option<Package>> {
+        Ok(self.query_packages("WHERE id = ?1", [package_id])?.into_iter().next())
+    }
+
+    fn courier_distribution(&self) -> Result<Vec<CourierCount>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT courier,
+                        SUM(status NOT IN ('delivered', 'not_found')) AS active,
+                        COUNT(*) AS total
+                 FROM (
+                     SELECT p.courier,
+                            COALESCE(
+                                (SELECT ps.status FROM package_status ps
+                                 WHERE ps.package_id = p.id
+                                 ORDER BY ps.id DESC LIMIT 1),
+                                'waiting'
+                            ) AS status
+                     FROM packages p
+                     WHERE p.deleted_at IS NULL
+                 )
+                 GROUP BY courier",
+            )
+            .context("Failed to prepare courier_distribution query")?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?, row.get::<_, usize>(2)?))
+            })
+            .context("Failed to query courier distribution")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read courier distribution rows")?;
+
+        // Stored courier values may differ in spelling ("usps" vs the crate's
+        // long name), so merge them under their display name.
+        let mut counts: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        for (courier, active, total) in rows {
+            let name = courier
+                .parse::<CourierCode>()
+                .map(|c| c.display_name().to_string())
+                .unwrap_or(courier);
+            let entry = counts.entry(name).or_default();
+            entry.0 += active;
+            entry.1 += total;
+        }
+
+        Ok(counts
+            .into_iter()
+            .map(|(courier, (active, total))| CourierCount { courier, active, total })
+            .collect())
+    }
+
+    fn get_package_status_history(&self, package_id: i64) -> Result<Vec<StatusHistoryEntry>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT status, description, short_description, last_known_location, checked_at,
+                        delivery_location_detail, courier_status_text, courier_status_code
+                 FROM package_status
+                 WHERE package_id = ?1
+                 ORDER BY id DESC",
+            )
+            .context("Failed to prepare get_package_status_history query")?;
+
+        let entries = stmt
+            .query_map([package_id], |row| {
+                Ok(StatusHistoryEntry {
+                    status: row.get(0)?,
+                    description: row.get(1)?,
+                    short_description: row.get(2)?,
+                    last_known_location: row.get(3)?,
+                    checked_at: row.get(4)?,
+                    delivery_location_detail: row.get(5)?,
+                    courier_status_text: row.get(6)?,
+                    courier_status_code: row.get(7)?,
+                })
+            })
+            .context("Failed to query package status history")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read package status history rows")?;
+
+        Ok(entries)
+    }
+
+    fn location_path(&self, package_id: i64) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT last_known_location
+                 FROM package_status
+                 WHERE package_id = ?1 AND last_known_location IS NOT NULL
+                 ORDER BY id ASC",
+            )
+            .context("Failed to prepare location_path query")?;
+
+        let mut locations = stmt
+            .query_map([package_id], |row| row.get::<_, String>(0))
+            .context("Failed to query package locations")?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .context("Failed to read package location rows")?;
+
+        locations.dedup();
+        Ok(locations)
+    }
+
+    fn insert_package_status(
+        &mut self,
+        package_id: i64,
+        status: &PackageStatus,
+        details: &StatusDetails,
+    ) -> Result<()> {
+        if *status == PackageStatus::Delivered {
+            self.transit_times.take();
+        }
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO package_status
+                    (package_id, status, estimated_arrival_date, last_known_location, description,
+                     short_description, delivery_location_detail, courier_status_text,
+                     courier_status_code, checked_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, COALESCE(
+                     ?10,
+                     (SELECT checked_at FROM package_status
+                      WHERE package_id = ?1 AND description = ?5
+                      ORDER BY id DESC LIMIT 1),
+                     strftime('%Y-%m-%dT%H:%M:%SZ', 'now')))",
+                rusqlite::params![
+                    package_id,
+                    status.to_string(),
+                    details.estimated_arrival_date,
+                    details.last_known_location,
+                    details.description,
+                    details.short_description,
+                    details.delivery_location_detail,
+                    details.courier_status_text,
+                    details.courier_status_code,
+                    details.checked_at,
+                ],
+            )
+            .context("Failed to insert package status")?;
+
+        Ok(())
+    }
+
+    fn insert_assumed_delivery(&mut self, package_id: i64, description: &str) -> Result<()> {
+        self.transit_times.take();
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO package_status (package_id, status, description, assumed, checked_at)
+                 VALUES (?1, ?2, ?3, 1, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))",
+                rusqlite::params![package_id, PackageStatus::Delivered.to_string(), description],
+            )
+            .context("Failed to insert assumed delivery")?;
+
+        Ok(())
+    }
+
+    fn delete_assumed_deliveries(&mut self, package_id: i64) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM package_status WHERE package_id = ?1 AND assumed",
+                [package_id],
+            )
+            .context("Failed to delete assumed deliveries")?;
+
+        Ok(())
+    }
+
+    fn delete_all_package_status(&mut self, package_id: i64) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM package_status WHERE package_id = ?1",
+                [package_id],
+            )
+            .context("Failed to delete all package status")?;
+
+        Ok(())
+    }
+
+    fn set_last_error(&mut self, package_id: i64, message: Option<&str>) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE packages SET last_error = ?1 WHERE id = ?2",
+                rusqlite::params![message, package_id],
+            )
+            .context("Failed to update package last_error")?;
+
+        Ok(())
+    }
+
+    fn get_eta_notified(&self, package_id: i64) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT eta_notified FROM packages WHERE id = ?1",
+                [package_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to query package eta_notified")
+            .map(Option::flatten)
+    }
+
+    fn set_eta_notified(&mut self, package_id: i64, eta: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE packages SET eta_notified = ?1 WHERE id = ?2",
+                rusqlite::params![eta, package_id],
+            )
+            .context("Failed to update package eta_notified")?;
+
+        Ok(())
+    }
+
+    fn is_delivery_pending(&self, package_id: i64) -> Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT delivery_pending FROM packages WHERE id = ?1",
+                [package_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to query package delivery_pending")
+            .map(|pending| pending.unwrap_or(false))
+    }
+
+    fn set_delivery_pending(&mut self, package_id: i64, pending: bool) -> Result<()> {
+        self.conn
+            .execute(
+                "UPDATE packages SET delivery_pending = ?1 WHERE id = ?2",
+                rusqlite::params![pending, package_id],
+            )
+            .context("Failed to update package delivery_pending")?;
+
+        Ok(())
+    }
+
+    fn schema_version(&self) -> Result<u32> {
+        self.conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .context("Failed to read user_version")
+    }
+
+    fn integrity_check(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("PRAGMA integrity_check")
+            .context("Failed to prepare integrity check")?;
+
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to run integrity check")?
+            .collect::<std::result::Result<Vec<String>, _>>()
+            .context("Failed to read integrity check rows")?;
+
+        Ok(rows)
+    }
+
+    fn vacuum(&mut self) -> Result<()> {
+        self.conn.execute_batch("VACUUM").context("Failed to vacuum database")
+    }
+
+    fn archive_delivered_before(&mut self, cutoff: DateTime<Utc>, limit: usize) -> Result<usize> {
+        self.conn
+            .execute(
+                "UPDATE packages SET deleted_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+                 WHERE id IN (
+                     SELECT p.id FROM packages p
+                     JOIN package_status ps ON ps.id = (
+                         SELECT MAX(ps2.id) FROM package_status ps2 WHERE ps2.package_id = p.id
+                     )
+                     WHERE p.deleted_at IS NULL AND ps.status = 'delivered' AND ps.checked_at < ?1
+                     LIMIT ?2
+                 )",
+                rusqlite::params![cutoff.format("%Y-%m-%dT%H:%M:%SZ").to_string(), limit as i64],
+            )
+            .context("Failed to archive delivered packages")
+    }
+
+    fn prune_status_history(&mut self, keep: usize, limit: usize) -> Result<usize> {
+        self.conn
+            .execute(
+                "DELETE FROM package_status WHERE id IN (
+                     SELECT id FROM (
+                         SELECT id,
+                                ROW_NUMBER() OVER latest_first AS rn,
+                                FIRST_VALUE(status) OVER latest_first AS latest_status
+                         FROM package_status
+                         WINDOW latest_first AS (PARTITION BY package_id ORDER BY id DESC)
+                     )
+                     WHERE rn > ?1 AND latest_status = 'delivered'
+                     LIMIT ?2
+                 )",
+                rusqlite::params![keep as i64, limit as i64],
+            )
+            .context("Failed to prune package status history")
+    }
+
+    fn checkpoint_wal(&mut self) -> Result<()> {
+        let busy: i64 = self
+            .conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))
+            .context("Failed to checkpoint WAL")?;
+        if busy != 0 {
+            anyhow::bail!("WAL checkpoint could not complete because the database is busy");
+        }
+
+        Ok(())
+    }
+
+    fn ping(&self) -> Result<()> {
+        self.conn
+            .query_row("SELECT 1", [], |_| Ok(()))
+            .context("Database did not answer SELECT 1")
+    }
+
+    fn delete_package(&mut self, package_id: i64) -> Result<bool> {
+        let changes = self
+            .conn
+            .execute(
+                "UPDATE packages SET deleted_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+                 WHERE id = ?1 AND deleted_at IS NULL",
+                [package_id],
+            )
+            .context("Failed to soft-delete package")?;
+
+        Ok(changes > 0)
+    }
+
+    fn restore_package(&mut self, package_id: i64) -> Result<bool> {
+        let changes = self
+            .conn
+            .execute(
+                "UPDATE packages SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+                [package_id],
+            )
+            .context("Failed to restore package")?;
+
+        Ok(changes > 0)
+    }
+}
+
+use rusqlite::OptionalExtension;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SourceConfidence;
+    use crate::test_support::new_package;
+    use chrono::TimeZone;
+
+    fn package_id(db: &SqliteDatabase, tracking_number: &str) -> i64 {
+        db.conn
+            .query_row(
+                "SELECT id FROM packages WHERE tracking_number = ?1",
+                [tracking_number],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    fn set_created_at(db: &SqliteDatabase, id: i64, created_at: &str) {
+        db.conn
+            .execute("UPDATE packages SET created_at = ?1 WHERE id = ?2", rusqlite::params![created_at, id])
+            .unwrap();
+    }
+
+    #[test]
+    fn migration_canonicalizes_and_merges_stored_tracking_numbers() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        for number in ["1z5r 8939-0357", "1Z5R89390357", "9400 1112"] {
+            db.conn
+                .execute(
+                    "INSERT INTO packages (tracking_number, courier, service, source_email_uid, source_email_date)
+                     VALUES (?1, 'ups', 'UPS Ground', 0, '2026-03-01T12:00:00Z')",
+                    [number],
+                )
+                .unwrap();
+            let id = package_id(&db, number);
+            let details = StatusDetails { description: Some(number), ..Default::default() };
+            db.insert_package_status(id, &PackageStatus::InTransit, &details).unwrap();
+        }
+        let oldest = package_id(&db, "1z5r 8939-0357");
+
+        db.conn.pragma_update(None, "user_version", 21).unwrap();
+        db.migrate().unwrap();
+
+        let package = db.get_by_tracking_number("1z5r 8939 0357").unwrap().unwrap();
+        assert_eq!(package.id, oldest);
+        assert_eq!(package.tracking_number, "1Z5R89390357");
+        assert_eq!(db.get_package_status_history(oldest).unwrap().len(), 2);
+        assert!(db.get_by_tracking_number("94001112").unwrap().is_some());
+        assert!(!db.insert_package(&new_package("1Z5R 8939 0357")).unwrap());
+    }
+
+    #[test]
+    fn predicts_arrival_from_median_transit_time() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        for (number, days) in [("1ZHIST1", "03"), ("1ZHIST2", "04"), ("1ZHIST3", "06")] {
+            db.insert_package(&new_package(number)).unwrap();
+            let id = package_id(&db, number);
+            set_created_at(&db, id, "2026-03-01T12:00:00Z");
+            let delivered_at = format!("2026-03-{days}T12:00:00Z");
+            db.insert_package_status(
+                id,
+                &PackageStatus::Delivered,
+                &StatusDetails { checked_at: Some(&delivered_at), ..Default::default() },
+            )
+            .unwrap();
+        }
+
+        db.insert_package(&new_package("1ZACTIVE")).unwrap();
+        let id = package_id(&db, "1ZACTIVE");
+        set_created_at(&db, id, "2026-04-10T08:00:00Z");
+        db.insert_package_status(id, &PackageStatus::InTransit, &StatusDetails::default())
+            .unwrap();
+
+        let packages = db.get_all_packages_with_status().unwrap();
+        let active = packages.iter().find(|p| p.tracking_number == "1ZACTIVE").unwrap();
+
+        assert_eq!(active.predicted_arrival_date.as_deref(), Some("2026-04-13"));
+    }
+
+    #[test]
+    fn reuses_transit_times_until_a_delivery_is_recorded() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        let deliver = |db: &mut SqliteDatabase, number: &str, day: &str| {
+            db.insert_package(&new_package(number)).unwrap();
+            let id = package_id(db, number);
+            set_created_at(db, id, "2026-03-01T12:00:00Z");
+            let delivered_at = format!("2026-03-{day}T12:00:00Z");
+            let details = StatusDetails { checked_at: Some(&delivered_at), ..Default::default() };
+            db.insert_package_status(id, &PackageStatus::Delivered, &details).unwrap();
+        };
+        for (number, day) in [("1ZHIST1", "03"), ("1ZHIST2", "04"), ("1ZHIST3", "06")] {
+            deliver(&mut db, number, day);
+        }
+        let key = ("ups".to_string(), "UPS Ground".to_string());
+        assert_eq!(db.cached_transit_times().unwrap()[&key], Duration::days(3));
+
+        // Changed behind the cache's back: the cached medians are reused
+        db.conn.execute("UPDATE package_status SET checked_at = '2026-03-11T12:00:00Z'", []).unwrap();
+        assert_eq!(db.cached_transit_times().unwrap()[&key], Duration::days(3));
+
+        deliver(&mut db, "1ZHIST4", "12");
+        assert_eq!(db.cached_transit_times().unwrap()[&key], Duration::days(10));
+    }
+
+    #[test]
+    fn averages_middle_transit_times_for_even_sample_counts() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        for (number, days) in [("1ZHIST1", "03"), ("1ZHIST2", "04"), ("1ZHIST3", "06"), ("1ZHIST4", "10")] {
+            db.insert_package(&new_package(number)).unwrap();
+            let id = package_id(&db, number);
+            set_created_at(&db, id, "2026-03-01T12:00:00Z");
+            let delivered_at = format!("2026-03-{days}T12:00:00Z");
+            db.insert_package_status(
+                id,
+                &PackageStatus::Delivered,
+                &StatusDetails { checked_at: Some(&delivered_at), ..Default::default() },
+            )
+            .unwrap();
+        }
+
+        // Transit times of 2, 3, 5 and 9 days: the median is 4 days
+        let transit_times = db.median_transit_times().unwrap();
+        assert_eq!(transit_times[&("ups".to_string(), "UPS Ground".to_string())], Duration::days(4));
+    }
+
+    #[test]
+    fn no_prediction_without_enough_history() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZHIST1")).unwrap();
+        let id = package_id(&db, "1ZHIST1");
+        set_created_at(&db, id, "2026-03-01T12:00:00Z");
+        db.insert_package_status(
+            id,
+            &PackageStatus::Delivered,
+            &StatusDetails {
+                checked_at: Some("2026-03-03T12:00:00Z"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        db.insert_package(&new_package("1ZACTIVE")).unwrap();
+        let id = package_id(&db, "1ZACTIVE");
+        db.insert_package_status(id, &PackageStatus::InTransit, &StatusDetails::default())
+            .unwrap();
+
+        let packages = db.get_all_packages_with_status().unwrap();
+        let active = packages.iter().find(|p| p.tracking_number == "1ZACTIVE").unwrap();
+
+        assert_eq!(active.predicted_arrival_date, None);
+    }
+
+    #[test]
+    fn source_email_round_trips_through_package_query() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        let received = Utc.with_ymd_and_hms(2026, 3, 2, 14, 30, 0).unwrap();
+
+        db.insert_package(&NewPackage {
+            source_email_uid: 7,
+            source_email_subject: Some("Your order has shipped".into()),
+            source_email_from: Some("orders@example.com".into()),
+            source_email_date: received,
+            ..new_package("1ZEMAIL")
+        })
+        .unwrap();
+        db.insert_package(&new_package("1ZMANUAL")).unwrap();
+
+        let packages = db.get_all_packages_with_status().unwrap();
+        let emailed = packages.iter().find(|p| p.tracking_number == "1ZEMAIL").unwrap();
+        assert_eq!(emailed.source_email_subject.as_deref(), Some("Your order has shipped"));
+        assert_eq!(emailed.source_email_from.as_deref(), Some("orders@example.com"));
+        assert_eq!(emailed.source_email_date.as_deref(), Some("2026-03-02T14:30:00+00:00"));
+        let manual = packages.iter().find(|p| p.tracking_number == "1ZMANUAL").unwrap();
+        assert_eq!(manual.source_email_date, None);
+
+        let source = db.get_package_source(package_id(&db, "1ZEMAIL")).unwrap().unwrap();
+        assert_eq!(source.email_subject.as_deref(), Some("Your order has shipped"));
+        assert_eq!(source.email_date, "2026-03-02T14:30:00+00:00");
+        assert!(db.get_package_source(package_id(&db, "1ZMANUAL")).unwrap().is_none());
+    }
+
+    #[test]
+    fn readding_deleted_package_restores_row() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        assert!(db.insert_package(&new_package("1ZREADD")).unwrap());
+        let id = package_id(&db, "1ZREADD");
+        assert!(db.delete_package(id).unwrap());
+        assert!(db.get_all_packages_with_status().unwrap().is_empty());
+
+        assert!(db.insert_package(&new_package("1ZREADD")).unwrap());
+
+        let packages = db.get_all_packages_with_status().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].id, id);
+    }
+
+    #[test]
+    fn restored_package_is_active_again() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZRESTORE")).unwrap();
+        let id = package_id(&db, "1ZRESTORE");
+        assert!(!db.restore_package(id).unwrap());
+
+        assert!(db.delete_package(id).unwrap());
+        assert!(db.get_active_packages().unwrap().is_empty());
+        let listed = db.get_all_packages_with_status_including_deleted().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].deleted_at.is_some());
+
+        assert!(db.restore_package(id).unwrap());
+        assert!(!db.restore_package(id).unwrap());
+        assert_eq!(db.get_active_packages().unwrap()[0].id, id);
+        assert_eq!(db.get_all_packages_with_status().unwrap()[0].deleted_at, None);
+    }
+
+    #[test]
+    fn exports_delivered_and_optionally_deleted_packages() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZKEEP")).unwrap();
+        db.insert_package(&new_package("1ZGONE")).unwrap();
+        let kept = package_id(&db, "1ZKEEP");
+        db.insert_package_status(
+            kept,
+            &PackageStatus::Delivered,
+            &StatusDetails { last_known_location: Some("Memphis, TN"), ..Default::default() },
+        )
+        .unwrap();
+        db.delete_package(package_id(&db, "1ZGONE")).unwrap();
+
+        let rows = db.export_packages(false).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].tracking_number, "1ZKEEP");
+        assert_eq!(rows[0].status, "delivered");
+        assert_eq!(rows[0].last_known_location.as_deref(), Some("Memphis, TN"));
+        assert!(rows[0].status_checked_at.is_some());
+
+        let rows = db.export_packages(true).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].tracking_number, "1ZGONE");
+        assert_eq!(rows[1].status, "waiting");
+        assert!(rows[1].deleted_at.is_some());
+    }
+
+    #[test]
+    fn pruning_keeps_history_of_packages_still_polled() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&new_package("1ZTRANSIT")).unwrap();
+        db.insert_package(&new_package("1ZDELIVERED")).unwrap();
+        let transit = package_id(&db, "1ZTRANSIT");
+        let delivered = package_id(&db, "1ZDELIVERED");
+
+        let events = [
+            (PackageStatus::Waiting, "Label created", "2026-03-01T08:00:00Z"),
+            (PackageStatus::InTransit, "Departed facility", "2026-03-02T08:00:00Z"),
+            (PackageStatus::InTransit, "Arrived at facility", "2026-03-03T08:00:00Z"),
+        ];
+        // Couriers return the full event history on every check
+        let record = |db: &mut SqliteDatabase, id: i64, events: &[(PackageStatus, &str, &str)]| {
+            for (status, description, checked_at) in events {
+                let details = StatusDetails { description: Some(description), checked_at: Some(checked_at), ..Default::default() };
+                db.insert_package_status(id, status, &details).unwrap();
+            }
+        };
+        record(&mut db, transit, &events);
+        record(&mut db, delivered, &events);
+        record(&mut db, delivered, &[(PackageStatus::Delivered, "Delivered", "2026-03-04T08:00:00Z")]);
+
+        assert_eq!(db.prune_status_history(1, 100).unwrap(), 3);
+        assert_eq!(db.get_package_status_history(delivered).unwrap().len(), 1);
+        assert_eq!(db.get_package_status_history(transit).unwrap().len(), 3);
+
+        record(&mut db, transit, &events);
+        let history = db.get_package_status_history(transit).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].description.as_deref(), Some("Arrived at facility"));
+        let status = |db: &SqliteDatabase, id| db.get_package_with_status(id).unwrap().unwrap().status;
+        assert_eq!(status(&db, transit), "in_transit");
+        assert_eq!(status(&db, delivered), "delivered");
+    }
+
+    #[test]
+    fn readding_active_package_is_ignored() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        assert!(db.insert_package(&new_package("1ZDUP")).unwrap());
+        assert!(!db.insert_package(&new_package("1ZDUP")).unwrap());
+    }
+
+    #[test]
+    fn eta_survives_later_scan_without_one() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZETA")).unwrap();
+        let id = package_id(&db, "1ZETA");
+        db.insert_package_status(
+            id,
+            &PackageStatus::InTransit,
+            &StatusDetails {
+                estimated_arrival_date: Some("2026-03-02"),
+                description: Some("Departed"),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        db.insert_package_status(
+            id,
+            &PackageStatus::InTransit,
+            &StatusDetails { description: Some("Arrived"), ..Default::default() },
+        )
+        .unwrap();
+
+        let packages = db.get_all_packages_with_status().unwrap();
+
+        assert_eq!(packages[0].estimated_arrival_date.as_deref(), Some("2026-03-02"));
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn encrypted_database_round_trip() {
+        let path = std::env::temp_dir().join(format!("trackage-cipher-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        {
+            let mut db = SqliteDatabase::open_encrypted(path, "s3cret").unwrap();
+            db.insert_package(&new_package("1ZCIPHER")).unwrap();
+        }
+
+        let db = SqliteDatabase::open_encrypted(path, "s3cret").unwrap();
+        assert_eq!(db.get_all_packages_with_status().unwrap().len(), 1);
+        drop(db);
+
+        assert!(SqliteDatabase::open_encrypted(path, "wrong").is_err());
+        assert!(SqliteDatabase::open(path).is_err());
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[test]
+    fn location_path_collapses_consecutive_repeats() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZROUTE")).unwrap();
+        let id = package_id(&db, "1ZROUTE");
+        let scans = [
+            (Some("Louisville, KY"), "Origin scan"),
+            (Some("Louisville, KY"), "Departed facility"),
+            (None, "In transit"),
+            (Some("Memphis, TN"), "Arrived at hub"),
+            (Some("Austin, TX"), "Out for delivery"),
+            (Some("Austin, TX"), "Delivered"),
+        ];
+        for (location, description) in scans {
+            db.insert_package_status(
+                id,
+                &PackageStatus::InTransit,
+                &StatusDetails {
+                    last_known_location: location,
+                    description: Some(description),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            db.location_path(id).unwrap(),
+            vec!["Louisville, KY", "Memphis, TN", "Austin, TX"]
+        );
+    }
+
+    #[test]
+    fn history_includes_short_description() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZSHORT")).unwrap();
+        let id = package_id(&db, "1ZSHORT");
+        let raw = "Arrived at USPS Regional Origin Facility, OKLAHOMA CITY OK DISTRIBUTION CENTER";
+        db.insert_package_status(
+            id,
+            &PackageStatus::InTransit,
+            &StatusDetails { description: Some(raw), short_description: Some("Arrived at facility"), ..Default::default() },
+        )
+        .unwrap();
+
+        let history = db.get_package_status_history(id).unwrap();
+
+        assert_eq!(history[0].description.as_deref(), Some(raw));
+        assert_eq!(history[0].short_description.as_deref(), Some("Arrived at facility"));
+    }
+
+    #[test]
+    fn repolled_event_is_recorded_once() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZREPOLL")).unwrap();
+        let id = package_id(&db, "1ZREPOLL");
+        let scan = |checked_at| StatusDetails {
+            description: Some("Arrived at Facility"),
+            checked_at: Some(checked_at),
+            ..Default::default()
+        };
+        let untimed = StatusDetails { description: Some("Out for delivery"), ..Default::default() };
+
+        for _ in 0..2 {
+            db.insert_package_status(id, &PackageStatus::InTransit, &scan("2026-03-01T08:00:00Z")).unwrap();
+            db.insert_package_status(id, &PackageStatus::InTransit, &untimed).unwrap();
+        }
+        assert_eq!(db.get_package_status_history(id).unwrap().len(), 2);
+
+        // Same description at a later scan is a distinct event
+        db.insert_package_status(id, &PackageStatus::InTransit, &scan("2026-03-02T09:30:00Z")).unwrap();
+        assert_eq!(db.get_package_status_history(id).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn unscanned_packages_excludes_scanned_ones() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+
+        db.insert_package(&new_package("1ZNEW")).unwrap();
+        db.insert_package(&new_package("1ZSCANNED")).unwrap();
+        let scanned = package_id(&db, "1ZSCANNED");
+        db.insert_package_status(scanned, &PackageStatus::InTransit, &StatusDetails::default())
+            .unwrap();
+
+        let unscanned = db.get_unscanned_packages().unwrap();
+
+        assert_eq!(unscanned.len(), 1);
+        assert_eq!(unscanned[0].tracking_number, "1ZNEW");
+        assert_eq!(unscanned[0].status, PackageStatus::Waiting);
+    }
+
+    #[test]
+    fn read_only_handle_rejects_writes() {
+        let path = std::env::temp_dir().join(format!("trackage-ro-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        {
+            let mut db = SqliteDatabase::open(path).unwrap();
+            db.insert_package(&new_package("1ZREADONLY")).unwrap();
+        }
+
+        let mut db = SqliteDatabase::open_read_only(path).unwrap();
+        assert_eq!(db.get_all_packages_with_status().unwrap().len(), 1);
+
+        assert!(db.set_last_seen_uid("INBOX", 1).is_err());
+        assert!(db.insert_package(&new_package("1ZOTHER")).is_err());
+        assert!(db.insert_package_status(1, &PackageStatus::InTransit, &StatusDetails::default()).is_err());
+        assert!(db.delete_all_package_status(1).is_err());
+        assert!(db.delete_package(1).is_err());
+        assert!(db.delete_package(999).is_err());
+        drop(db);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[test]
+    fn read_only_handle_rejects_outdated_schema() {
+        let path = std::env::temp_dir().join(format!("trackage-ro-old-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        {
+            let db = SqliteDatabase::open(path).unwrap();
+            db.conn.pragma_update(None, "user_version", 3).unwrap();
+        }
+
+        let err = SqliteDatabase::open_read_only(path).err().unwrap();
+        assert!(err.to_string().contains("has schema version 3"), "{err}");
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[test]
+    fn integrity_check_reports_ok_for_healthy_database() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&new_package("1ZCHECK")).unwrap();
+
+        assert_eq!(db.integrity_check().unwrap(), vec!["ok"]);
+        db.vacuum().unwrap();
+    }
+
+    #[test]
+    fn stores_delivery_location_detail() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&new_package("1ZLEFT")).unwrap();
+        let id = package_id(&db, "1ZLEFT");
+
+        db.insert_package_status(
+            id,
+            &PackageStatus::Delivered,
+            &StatusDetails { delivery_location_detail: Some("Front Porch"), ..Default::default() },
+        )
+        .unwrap();
+
+        let history = db.get_package_status_history(id).unwrap();
+        assert_eq!(history[0].delivery_location_detail.as_deref(), Some("Front Porch"));
+    }
+
+    #[test]
+    fn counts_packages_per_courier() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        for (number, courier) in [("1ZONE", "ups"), ("1ZTWO", "ups"), ("FDX1", "fedex"), ("USPS1", "usps")] {
+            db.insert_package(&NewPackage { courier: courier.into(), ..new_package(number) }).unwrap();
+        }
+        let delivered = package_id(&db, "1ZTWO");
+        db.insert_package_status(delivered, &PackageStatus::Delivered, &StatusDetails::default())
+            .unwrap();
+        db.delete_package(package_id(&db, "USPS1")).unwrap();
+
+        assert_eq!(
+            db.courier_distribution().unwrap(),
+            vec![
+                CourierCount { courier: "FedEx".into(), active: 1, total: 1 },
+                CourierCount { courier: "UPS".into(), active: 1, total: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn preserves_courier_status_text_verbatim() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&new_package("1ZLATE")).unwrap();
+        let id = package_id(&db, "1ZLATE");
+
+        db.insert_package_status(
+            id,
+            &PackageStatus::InTransit,
+            &StatusDetails { courier_status_text: Some("In transit, arriving late"), ..Default::default() },
+        )
+        .unwrap();
+
+        let history = db.get_package_status_history(id).unwrap();
+        assert_eq!(history[0].courier_status_text.as_deref(), Some("In transit, arriving late"));
+        let package = db.get_package_with_status(id).unwrap().unwrap();
+        assert_eq!(package.status, "in_transit");
+        assert_eq!(package.courier_status_text.as_deref(), Some("In transit, arriving late"));
+    }
+
+    #[test]
+    fn gets_package_by_tracking_number() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&new_package("1ZFIND")).unwrap();
+        db.insert_package(&new_package("1ZGONE")).unwrap();
+        db.delete_package(package_id(&db, "1ZGONE")).unwrap();
+
+        let found = db.get_by_tracking_number("1ZFIND").unwrap().unwrap();
+        assert_eq!(found.tracking_number, "1ZFIND");
+        assert_eq!(found.status, "waiting");
+
+        assert!(db.get_by_tracking_number("1ZGONE").unwrap().is_none());
+        assert!(db.get_by_tracking_number("1ZMISSING").unwrap().is_none());
+    }
+
+    #[test]
+    fn higher_confidence_courier_replaces_stored_one() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&NewPackage { courier: "usps".into(), ..new_package("1ZCONFLICT") }).unwrap();
+
+        let guessed = NewPackage { courier: "fedex".into(), ..new_package("1ZCONFLICT") };
+        assert!(!db.insert_package(&guessed).unwrap());
+        assert!(!db.replace_courier_if_more_confident(&guessed).unwrap());
+
+        let confirmed = NewPackage {
+            courier: "ups".into(),
+            source_confidence: SourceConfidence::Sender,
+            ..new_package("1ZCONFLICT")
+        };
+        assert!(db.replace_courier_if_more_confident(&confirmed).unwrap());
+        assert_eq!(db.get_by_tracking_number("1ZCONFLICT").unwrap().unwrap().courier, "UPS");
+
+        // A later guess doesn't undo the sender-confirmed courier
+        assert!(!db.replace_courier_if_more_confident(&guessed).unwrap());
+    }
+
+    #[test]
+    fn same_number_formatted_differently_is_one_package() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        let merchant = NewPackage { courier: "usps".into(), ..new_package("1z5r 8939-0357 567127") };
+        assert!(db.insert_package(&merchant).unwrap());
+
+        let carrier = NewPackage {
+            courier: "ups".into(),
+            source_confidence: SourceConfidence::Sender,
+            ..new_package("1Z5R89390357567127")
+        };
+        assert!(!db.insert_package(&carrier).unwrap());
+        assert!(db.replace_courier_if_more_confident(&carrier).unwrap());
+
+        let packages = db.get_all_packages_with_status().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].tracking_number, "1Z5R89390357567127");
+        assert_eq!(packages[0].courier, "UPS");
+    }
+
+    #[test]
+    fn poll_progress_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("trackage-progress-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let polled_at = DateTime::parse_from_rfc3339("2026-03-02T08:30:00Z").unwrap().with_timezone(&Utc);
+
+        {
+            let mut db = SqliteDatabase::open(path).unwrap();
+            db.set_last_seen_uid("INBOX", 4242).unwrap();
+            db.set_last_status_poll(polled_at).unwrap();
+        }
+
+        let db = SqliteDatabase::open(path).unwrap();
+        assert_eq!(db.get_last_seen_uid("INBOX").unwrap(), 4242);
+        assert_eq!(db.get_last_status_poll().unwrap(), Some(polled_at));
+        drop(db);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[test]
+    fn preserves_raw_courier_status_code() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&new_package("1ZCODE")).unwrap();
+        let id = package_id(&db, "1ZCODE");
+
+        db.insert_package_status(
+            id,
+            &PackageStatus::Delivered,
+            &StatusDetails { courier_status_code: Some("D"), ..Default::default() },
+        )
+        .unwrap();
+
+        let history = db.get_package_status_history(id).unwrap();
+        assert_eq!(history[0].status, "delivered");
+        assert_eq!(history[0].courier_status_code.as_deref(), Some("D"));
+    }
+
+    #[test]
+    fn links_order_placeholders_once() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        let placeholder = NewOrderPlaceholder {
+            merchant: "Acme".into(),
+            order_number: "A1234567".into(),
+            source_email_uid: 7,
+            source_email_subject: Some("Order confirmation".into()),
+        };
+        assert!(db.insert_order_placeholder(&placeholder).unwrap());
+        assert!(!db.insert_order_placeholder(&placeholder).unwrap());
+
+        db.insert_package(&new_package("1ZORDER")).unwrap();
+        let id = package_id(&db, "1ZORDER");
+
+        assert_eq!(db.link_order_placeholders("A1234567", id).unwrap(), 1);
+        assert_eq!(db.link_order_placeholders("A1234567", id).unwrap(), 0);
+        assert!(db.get_awaiting_orders().unwrap().is_empty());
+        let linked: i64 = db
+            .conn
+            .query_row("SELECT package_id FROM order_placeholders", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(linked, id);
+    }
+
+    #[test]
+    fn normalizes_mixed_format_couriers() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        for (number, courier) in [
+            ("1ZDISPLAY", "UPS"),
+            ("9400FULL", "United States Postal Service"),
+            ("986578788855", "fedex"),
+            ("JD0146", "DHL"),
+        ] {
+            db.insert_package(&NewPackage { courier: courier.into(), ..new_package(number) }).unwrap();
+        }
+
+        assert_eq!(db.normalize_couriers().unwrap(), 2);
+        assert_eq!(db.normalize_couriers().unwrap(), 0);
+
+        let stored: Vec<String> = db
+            .conn
+            .prepare("SELECT courier FROM packages ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(stored, ["ups", "usps", "fedex", "DHL"]);
+    }
+
+    #[test]
+    fn latest_status_lookup_uses_package_status_index() {
+        let db = SqliteDatabase::open(":memory:").unwrap();
+
+        let plan: Vec<String> = db
+            .conn
+            .prepare(
+                "EXPLAIN QUERY PLAN
+                 SELECT ps.status FROM package_status ps
+                 WHERE ps.package_id = ?1
+                 ORDER BY ps.id DESC LIMIT 1",
+            )
+            .unwrap()
+            .query_map([1], |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+
+        assert!(
+            plan.iter().any(|step| step.contains("USING INDEX idx_package_status_package_id")),
+            "{plan:?}"
+        );
+        assert!(!plan.iter().any(|step| step.contains("TEMP B-TREE")), "{plan:?}");
+    }
+}
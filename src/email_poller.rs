@@ -1,38 +1,163 @@
-use crate::config::EmailConfig;
+use crate::barcode;
+use crate::config::{CourierConflictPolicy, EmailConfig};
 use crate::courier::CourierCode;
-use crate::db::{Database, NewPackage};
-use crate::extractors;
-use crate::imap_client::{ImapClient, MailMessage, parse_message};
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::db::{Database, NewOrderPlaceholder, NewPackage, SourceConfidence};
+use crate::extractors::{self, CourierOverride, ShipmentDetails};
+use crate::imap_client::{ImapClient, MailMessage, ParsedMessage, parse_message};
+use crate::metrics::Metrics;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use regex::{Regex, RegexBuilder};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
-use tracing::{debug, error, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use tracking_numbers::TrackingResult;
+
+/// Longest single IMAP IDLE wait, so a shutdown request is noticed promptly.
+const IDLE_SLICE: Duration = Duration::from_secs(10);
 
 pub struct EmailPoller {
     config: EmailConfig,
     db: Box<dyn Database>,
+    courier_overrides: Vec<CourierOverride>,
+    ignore_patterns: Vec<Regex>,
+    ignore_subject_patterns: Vec<Regex>,
+    order_confirmation_patterns: Vec<Regex>,
+    /// Couriers with API credentials, for `email.trust_courier_on_no_match`.
+    configured_couriers: Vec<CourierCode>,
+    /// Seconds between polls; shared so a config reload can change it.
+    interval: Arc<AtomicU64>,
+    /// Cleared once the server turns out not to support IDLE.
+    idle_supported: bool,
+    metrics: Arc<Metrics>,
     running: Arc<AtomicBool>,
 }
 
 impl EmailPoller {
-    pub fn new(config: EmailConfig, db: Box<dyn Database>, running: Arc<AtomicBool>) -> Self {
-        Self { config, db, running }
+    pub fn new(
+        config: EmailConfig,
+        db: Box<dyn Database>,
+        configured_couriers: Vec<CourierCode>,
+        running: Arc<AtomicBool>,
+    ) -> Self {
+        let courier_overrides = config
+            .courier_overrides
+            .iter()
+            .filter_map(|rule| match CourierOverride::compile(rule) {
+                Ok(rule) => Some(rule),
+                Err(err) => {
+                    warn!(error = %err, "Ignoring invalid courier override");
+                    None
+                }
+            })
+            .collect();
+
+        let ignore_patterns = compile_patterns(&config.ignore_patterns);
+        let ignore_subject_patterns = compile_patterns(&config.ignore_subject_patterns);
+        let order_confirmation_patterns = compile_patterns(&config.order_confirmation_patterns);
+
+        Self {
+            interval: Arc::new(AtomicU64::new(config.check_interval_seconds)),
+            config,
+            db,
+            courier_overrides,
+            ignore_patterns,
+            ignore_subject_patterns,
+            order_confirmation_patterns,
+            configured_couriers,
+            idle_supported: true,
+            metrics: Arc::default(),
+            running,
+        }
+    }
+
+    /// Count processed emails and found packages in shared `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Handle for changing the poll interval while the poller runs.
+    pub fn interval_handle(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.interval)
     }
 
     /// Run the poll loop. Blocks until the shutdown signal fires.
     pub fn run(mut self) {
         info!("Email poller starting");
 
+        if !self.config.poll_on_startup {
+            debug!("Waiting for the first interval before polling");
+            self.sleep();
+        }
+
         while self.running.load(Ordering::SeqCst) {
             self.poll_once();
-            self.sleep();
+            if !(self.config.use_idle && self.idle_supported && self.idle()) {
+                self.sleep();
+            }
         }
 
         info!("Email poller shutting down");
     }
 
+    /// Wait in IMAP IDLE until new mail arrives, the check interval passes,
+    /// or shutdown is requested. Returns `false` if IDLE couldn't be used, so
+    /// the caller falls back to sleeping.
+    fn idle(&mut self) -> bool {
+        let mut client = match ImapClient::connect(&self.config) {
+            Ok(client) => client,
+            Err(err) => {
+                error!(error = %err, "IMAP connection for IDLE failed");
+                return false;
+            }
+        };
+
+        match client.supports_idle() {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!(server = self.config.server, "IMAP server doesn't support IDLE, falling back to interval polling");
+                self.idle_supported = false;
+                let _ = client.logout();
+                return false;
+            }
+            Err(err) => {
+                error!(error = %err, "Failed to query IMAP capabilities");
+                let _ = client.logout();
+                return false;
+            }
+        }
+
+        debug!(folder = self.config.folder, "Waiting for new mail with IMAP IDLE");
+        let deadline = Instant::now() + Duration::from_secs(self.interval.load(Ordering::SeqCst));
+        while self.running.load(Ordering::SeqCst) && Instant::now() < deadline {
+            // A zero read timeout is rejected, so wait at least a second
+            let remaining = deadline.saturating_duration_since(Instant::now()).max(Duration::from_secs(1));
+            match client.idle_wait(IDLE_SLICE.min(remaining)) {
+                Ok(true) => {
+                    info!("New mail signalled by IMAP IDLE");
+                    break;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    warn!(error = %err, "IMAP IDLE failed, falling back to interval polling for this cycle");
+                    let _ = client.logout();
+                    return false;
+                }
+            }
+        }
+
+        let _ = client.logout();
+        true
+    }
+
     fn poll_once(&mut self) {
+        let deadline = self
+            .config
+            .max_poll_duration_seconds
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+
         let last_seen_uid = match self.db.get_last_seen_uid(&self.config.folder) {
             Ok(uid) => uid,
             Err(err) => {
@@ -51,27 +176,23 @@ impl EmailPoller {
             }
         };
 
+        // New mail is selected purely by UID. Only a folder's first poll may
+        // look back with an IMAP `SINCE` date to find where to start.
         let last_seen_uid = if last_seen_uid == 0 {
-            if let Some(uid_next) = client.uid_next() {
-                let seeded = uid_next.saturating_sub(1);
-                info!(
-                    uid_next,
-                    seeded_uid = seeded,
-                    folder = self.config.folder,
-                    "No previous UID for folder, starting from current mailbox position"
-                );
-                if let Err(err) = self.db.set_last_seen_uid(&self.config.folder, seeded) {
-                    error!(error = %err, "Failed to save seeded last_seen_uid to database");
+            match self.starting_uid(&mut client) {
+                Some(seeded) => {
+                    if let Err(err) = self.db.set_last_seen_uid(&self.config.folder, seeded) {
+                        error!(error = %err, "Failed to save seeded last_seen_uid to database");
+                    }
+                    seeded
                 }
-                seeded
-            } else {
-                last_seen_uid
+                None => last_seen_uid,
             }
         } else {
             last_seen_uid
         };
 
-        let messages = match client.fetch_messages_since_uid(last_seen_uid) {
+        let mut messages = match client.fetch_messages_since_uid(last_seen_uid) {
             Ok(messages) => messages,
             Err(err) => {
                 error!(error = %err, "IMAP fetch failed");
@@ -82,14 +203,8 @@ impl EmailPoller {
 
         info!(count = messages.len(), "New messages fetched");
 
-        let mut max_uid = last_seen_uid;
-
-        for msg in &messages {
-            if msg.uid > max_uid {
-                max_uid = msg.uid;
-            }
-            self.process_message(msg);
-        }
+        messages.sort_by_key(|msg| msg.uid);
+        let max_uid = self.process_messages(&messages, last_seen_uid, deadline);
 
         if let Err(err) = self.db.set_last_seen_uid(&self.config.folder, max_uid) {
             error!(error = %err, "Failed to save last_seen_uid to database");
@@ -98,14 +213,73 @@ impl EmailPoller {
         let _ = client.logout();
     }
 
+    /// Where a folder with no previous UID starts: just before the first
+    /// message within `initial_lookback_days`, otherwise the current mailbox
+    /// position so only new mail is processed.
+    fn starting_uid(&self, client: &mut ImapClient) -> Option<u32> {
+        if let Some(days) = self.config.initial_lookback_days {
+            let since = lookback_since(Utc::now(), days);
+            match client.first_uid_since(since) {
+                Ok(Some(first_uid)) => {
+                    info!(
+                        %since,
+                        first_uid,
+                        folder = self.config.folder,
+                        "No previous UID for folder, starting from the lookback window"
+                    );
+                    return Some(first_uid - 1);
+                }
+                Ok(None) => {}
+                Err(err) => warn!(error = %err, "Lookback search failed, starting from current mailbox position"),
+            }
+        }
+
+        let uid_next = client.uid_next()?;
+        let seeded = uid_next.saturating_sub(1);
+        info!(
+            uid_next,
+            seeded_uid = seeded,
+            folder = self.config.folder,
+            "No previous UID for folder, starting from current mailbox position"
+        );
+        Some(seeded)
+    }
+
+    /// Process messages in UID order until done, the deadline passes, or
+    /// shutdown is requested. At least one message is always processed so a
+    /// short deadline still makes progress. Returns the highest UID processed.
+    fn process_messages(&mut self, messages: &[MailMessage], last_seen_uid: u32, deadline: Option<Instant>) -> u32 {
+        let mut max_uid = last_seen_uid;
+
+        for (i, msg) in messages.iter().enumerate() {
+            if i > 0 && !self.running.load(Ordering::SeqCst) {
+                info!(remaining = messages.len() - i, "Shutdown requested, deferring remaining messages");
+                break;
+            }
+            if i > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                info!(
+                    remaining = messages.len() - i,
+                    "Maximum poll duration reached, deferring remaining messages to the next cycle"
+                );
+                break;
+            }
+
+            self.process_message(msg);
+            max_uid = max_uid.max(msg.uid);
+        }
+
+        max_uid
+    }
+
     fn process_message(&mut self, msg: &MailMessage) {
-        let parsed = match parse_message(msg) {
+        let parsed = match parse_message(msg, self.config.html_wrap_width) {
             Ok(parsed) => parsed,
             Err(err) => {
                 error!(error = %err, uid = msg.uid, "Failed to parse MIME message");
                 return;
             }
         };
+        Metrics::increment(&self.metrics.emails_processed);
 
         info!(
             uid = msg.uid,
@@ -120,10 +294,76 @@ impl EmailPoller {
             "Email body preview"
         );
 
-        let results = extractors::extract_tracking_numbers(&parsed.body_text);
+        let subject = parsed.subject.as_deref().unwrap_or("");
+        if let Some(pattern) = self
+            .ignore_patterns
+            .iter()
+            .find(|re| re.is_match(subject) || re.is_match(&parsed.body_text))
+            .or_else(|| self.ignore_subject_patterns.iter().find(|re| re.is_match(subject)))
+        {
+            info!(uid = msg.uid, pattern = %pattern, "Skipping message matching ignore pattern");
+            return;
+        }
+
+        let mut text = if self.config.strip_quoted {
+            extractors::strip_quoted(&parsed.body_text)
+        } else {
+            parsed.body_text.clone()
+        };
+        if self.config.scan_barcodes {
+            for payload in barcode::scan_message(msg.body.as_bytes()) {
+                debug!(uid = msg.uid, payload_len = payload.len(), "Decoded QR code");
+                text.push('\n');
+                text.push_str(&payload);
+            }
+        }
+
+        let destination_zip = extractors::extract_destination_zip(&text);
+        let mut results = extractors::extract_tracking_numbers(&text);
+        if let Some(courier) = self.trusted_courier(parsed.from.as_deref()) {
+            for number in extractors::unclassified_candidates(&text) {
+                info!(
+                    tracking_number = %number,
+                    courier = %courier,
+                    "Unrecognized number from the only configured courier, letting its API decide"
+                );
+                results.push(TrackingResult {
+                    courier: courier.to_string(),
+                    service: String::new(),
+                    tracking_number: number,
+                    tracking_url: String::new(),
+                });
+            }
+        }
+        extractors::apply_courier_overrides(&mut results, &self.courier_overrides);
+        Metrics::add(&self.metrics.tracking_numbers_extracted, results.len());
+
+        let order_number = if self.order_confirmation_patterns.is_empty() {
+            None
+        } else {
+            extractors::extract_order_number(&text)
+        };
+        if results.is_empty() {
+            if let Some(order_number) = order_number {
+                self.record_order_confirmation(msg.uid, &parsed, order_number);
+            }
+            return;
+        }
 
+        let numbers: Vec<&str> = results.iter().map(|r| r.tracking_number.as_str()).collect();
         for result in &results {
-            let courier = match result.courier.parse::<CourierCode>() {
+            let details = if self.config.parse_digests && results.len() > 1 {
+                extractors::shipment_details(&text, &result.tracking_number, &numbers)
+            } else {
+                ShipmentDetails::default()
+            };
+
+            let code = result.courier.parse::<CourierCode>();
+            let tracking_url = match &code {
+                Ok(code) if result.tracking_url.is_empty() => code.tracking_url(&result.tracking_number),
+                _ => result.tracking_url.clone(),
+            };
+            let courier = match code {
                 Ok(code) => code.to_string(),
                 Err(_) => {
                     debug!(
@@ -142,15 +382,35 @@ impl EmailPoller {
                 "Validated tracking number"
             );
 
+            let source_confidence = if self
+                .courier_overrides
+                .iter()
+                .any(|rule| rule.matches(&result.tracking_number))
+            {
+                SourceConfidence::Override
+            } else if parsed
+                .from
+                .as_deref()
+                .and_then(extractors::sender_courier)
+                .is_some_and(|sender| sender.to_string() == courier)
+            {
+                SourceConfidence::Sender
+            } else {
+                SourceConfidence::Guessed
+            };
+
             let new_package = NewPackage {
                 tracking_number: result.tracking_number.clone(),
                 courier,
-                service: result.service.clone(),
-                tracking_url: result.tracking_url.clone(),
+                service: details.service.unwrap_or_else(|| result.service.clone()),
+                tracking_url,
                 source_email_uid: msg.uid,
                 source_email_subject: parsed.subject.clone(),
                 source_email_from: parsed.from.clone(),
                 source_email_date: parsed.internal_date,
+                source_confidence,
+                destination_zip: destination_zip.clone(),
+                description: details.description,
             };
 
             match self.db.insert_package(&new_package) {
@@ -159,12 +419,14 @@ impl EmailPoller {
                         tracking_number = %result.tracking_number,
                         "New package saved to database"
                     );
+                    Metrics::increment(&self.metrics.packages_inserted);
                 }
                 Ok(false) => {
                     debug!(
                         tracking_number = %result.tracking_number,
                         "Package already exists in database"
                     );
+                    self.resolve_courier_conflict(&new_package);
                 }
                 Err(err) => {
                     error!(
@@ -174,14 +436,362 @@ impl EmailPoller {
                     );
                 }
             }
+
+            if let Some(order_number) = &order_number {
+                self.link_order(order_number, &result.tracking_number);
+            }
+        }
+    }
+
+    /// Record an order confirmation that has no tracking number yet, if the
+    /// subject or sender matches `email.order_confirmation_patterns`.
+    fn record_order_confirmation(&mut self, uid: u32, parsed: &ParsedMessage, order_number: String) {
+        let subject = parsed.subject.as_deref().unwrap_or("");
+        let from = parsed.from.as_deref().unwrap_or("");
+        if !self
+            .order_confirmation_patterns
+            .iter()
+            .any(|re| re.is_match(subject) || re.is_match(from))
+        {
+            return;
+        }
+
+        let placeholder = NewOrderPlaceholder {
+            merchant: extractors::sender_name(from),
+            order_number,
+            source_email_uid: uid,
+            source_email_subject: parsed.subject.clone(),
+        };
+        match self.db.insert_order_placeholder(&placeholder) {
+            Ok(true) => info!(
+                merchant = %placeholder.merchant,
+                order_number = %placeholder.order_number,
+                "Order confirmation saved, awaiting tracking number"
+            ),
+            Ok(false) => debug!(order_number = %placeholder.order_number, "Order confirmation already recorded"),
+            Err(err) => error!(error = %err, order_number = %placeholder.order_number, "Failed to save order confirmation"),
+        }
+    }
+
+    /// Link any order awaiting tracking under `order_number` to the package
+    /// just found for it.
+    fn link_order(&mut self, order_number: &str, tracking_number: &str) {
+        let package = match self.db.get_by_tracking_number(tracking_number) {
+            Ok(Some(package)) => package,
+            Ok(None) => return,
+            Err(err) => {
+                error!(error = %err, tracking_number, "Failed to look up package for order linking");
+                return;
+            }
+        };
+
+        match self.db.link_order_placeholders(order_number, package.id) {
+            Ok(0) => {}
+            Ok(linked) => info!(order_number, tracking_number, linked, "Linked awaiting order to its package"),
+            Err(err) => error!(error = %err, order_number, "Failed to link order to package"),
+        }
+    }
+
+    /// The courier to assign numbers the tracking-numbers crate can't
+    /// classify: with `email.trust_courier_on_no_match`, the sender's courier
+    /// when it is the only one configured.
+    fn trusted_courier(&self, from: Option<&str>) -> Option<CourierCode> {
+        if !self.config.trust_courier_on_no_match {
+            return None;
+        }
+        let [only] = self.configured_couriers.as_slice() else {
+            return None;
+        };
+        let sender = extractors::sender_courier(from?)?;
+        (sender == *only).then_some(sender)
+    }
+
+    /// Handle an already-stored tracking number that was just detected as a
+    /// different courier, according to `email.courier_conflict`.
+    fn resolve_courier_conflict(&mut self, package: &NewPackage) {
+        let existing = match self.db.get_by_tracking_number(&package.tracking_number) {
+            Ok(Some(existing)) => existing,
+            Ok(None) => return,
+            Err(err) => {
+                error!(error = %err, tracking_number = %package.tracking_number, "Failed to look up existing package");
+                return;
+            }
+        };
+        // The stored courier comes back as a display name ("UPS" for "ups")
+        if existing.courier.eq_ignore_ascii_case(&package.courier) {
+            return;
+        }
+
+        if self.config.courier_conflict == CourierConflictPolicy::PreferConfident {
+            match self.db.replace_courier_if_more_confident(package) {
+                Ok(true) => {
+                    info!(
+                        tracking_number = %package.tracking_number,
+                        old_courier = %existing.courier,
+                        new_courier = %package.courier,
+                        confidence = ?package.source_confidence,
+                        "Updated package courier from a more authoritative source"
+                    );
+                    return;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    error!(error = %err, tracking_number = %package.tracking_number, "Failed to update package courier");
+                    return;
+                }
+            }
         }
+
+        warn!(
+            tracking_number = %package.tracking_number,
+            stored_courier = %existing.courier,
+            detected_courier = %package.courier,
+            "Tracking number detected as a different courier; keeping the stored one"
+        );
     }
 
     fn sleep(&self) {
         let mut slept = 0;
-        while slept < self.config.check_interval_seconds && self.running.load(Ordering::SeqCst) {
+        while slept < self.interval.load(Ordering::SeqCst) && self.running.load(Ordering::SeqCst) {
             thread::sleep(Duration::from_secs(1));
             slept += 1;
         }
     }
 }
+
+/// Compile case-insensitive message patterns, skipping (and logging) any
+/// that are invalid.
+fn compile_patterns(patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(re) => Some(re),
+            Err(err) => {
+                warn!(error = %err, pattern = %pattern, "Ignoring invalid message pattern");
+                None
+            }
+        })
+        .collect()
+}
+
+/// The IMAP `SINCE` date for a lookback of `days` before `now`. Servers
+/// compare `SINCE` against each message's date in their own timezone, which
+/// can be a calendar day behind UTC, so the window starts a day earlier
+/// rather than miss mail received just inside it.
+fn lookback_since(now: DateTime<Utc>, days: u32) -> NaiveDate {
+    now.date_naive() - Days::new(u64::from(days) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SqliteDatabase;
+    use chrono::Utc;
+
+    fn config() -> EmailConfig {
+        serde_json::from_str("{}").unwrap()
+    }
+
+    fn message(uid: u32, body: &str) -> MailMessage {
+        MailMessage {
+            uid,
+            internal_date: Utc::now(),
+            headers: String::new(),
+            body: format!("Subject: Shipped\r\n\r\n{body}"),
+        }
+    }
+
+    fn poller(config: EmailConfig) -> EmailPoller {
+        EmailPoller::new(
+            config,
+            Box::new(SqliteDatabase::open(":memory:").unwrap()),
+            vec![CourierCode::UPS],
+            Arc::new(AtomicBool::new(true)),
+        )
+    }
+
+    #[test]
+    fn processes_all_messages_without_deadline() {
+        let mut poller = poller(config());
+        let messages = vec![message(11, "1Z5R89390357567127"), message(12, "986578788855")];
+
+        assert_eq!(poller.process_messages(&messages, 10, None), 12);
+        assert_eq!(poller.db.get_all_packages_with_status().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stops_at_deadline_with_progress_of_processed_messages() {
+        let mut poller = poller(config());
+        let messages = vec![message(11, "1Z5R89390357567127"), message(12, "986578788855")];
+
+        let max_uid = poller.process_messages(&messages, 10, Some(Instant::now()));
+
+        assert_eq!(max_uid, 11);
+        let packages = poller.db.get_all_packages_with_status().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].tracking_number, "1Z5R89390357567127");
+    }
+
+    #[test]
+    fn skips_marketing_email_matching_ignore_pattern() {
+        let mut poller = poller(config());
+        let marketing = MailMessage {
+            uid: 11,
+            internal_date: Utc::now(),
+            headers: "Subject: Set your delivery preferences today\r\n".into(),
+            body: "Subject: Set your delivery preferences today\r\n\r\n\
+                   Here's an example tracking number: 1Z5R89390357567127"
+                .into(),
+        };
+
+        assert_eq!(poller.process_messages(&[marketing], 10, None), 11);
+        assert!(poller.db.get_all_packages_with_status().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_shipment_email_with_marketing_footer() {
+        let mut poller = poller(config());
+        let shipment = MailMessage {
+            uid: 11,
+            internal_date: Utc::now(),
+            headers: "Subject: UPS Update: Package Scheduled for Delivery Tuesday, 03/03/2026\r\n".into(),
+            body: "Subject: UPS Update: Package Scheduled for Delivery Tuesday, 03/03/2026\r\n\r\n\
+                   Your package is on the way.\r\n\
+                   Tracking Number: 1Z5R89390357567127\r\n\
+                   Scheduled Delivery: Tuesday, 03/03/2026\r\n\r\n\
+                   Set Your Delivery Preferences | Join UPS My Choice\r\n\
+                   \u{a9} 2026 United Parcel Service of America, Inc."
+                .into(),
+        };
+
+        assert_eq!(poller.process_messages(&[shipment], 10, None), 11);
+        let packages = poller.db.get_all_packages_with_status().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].tracking_number, "1Z5R89390357567127");
+    }
+
+    #[test]
+    fn processes_messages_sharing_a_timestamp() {
+        let mut poller = poller(config());
+        let now = Utc::now();
+        let mut first = message(11, "1Z5R89390357567127");
+        let mut second = message(12, "986578788855");
+        first.internal_date = now;
+        second.internal_date = now;
+
+        // Progress is tracked by UID, so messages in the same second as the
+        // last processed one are never dropped.
+        assert_eq!(poller.process_messages(&[first], 10, None), 11);
+        assert_eq!(poller.process_messages(&[second], 11, None), 12);
+        assert_eq!(poller.db.get_all_packages_with_status().unwrap().len(), 2);
+    }
+
+    fn message_from(uid: u32, from: &str, body: &str) -> MailMessage {
+        MailMessage {
+            uid,
+            internal_date: Utc::now(),
+            headers: format!("From: {from}\r\nSubject: Shipped\r\n"),
+            body: format!("From: {from}\r\nSubject: Shipped\r\n\r\n{body}"),
+        }
+    }
+
+    #[test]
+    fn stores_unclassified_number_under_trusted_sender_courier() {
+        let mut poller = poller(EmailConfig { trust_courier_on_no_match: true, ..config() });
+        let msg = message_from(11, "UPS <mcinfo@ups.com>", "Tracking number: 1ZX9Y8W7V6U5T4S3R2");
+
+        poller.process_messages(&[msg], 10, None);
+
+        let stored = poller.db.get_by_tracking_number("1ZX9Y8W7V6U5T4S3R2").unwrap().unwrap();
+        assert!(stored.courier.eq_ignore_ascii_case("ups"));
+        assert_eq!(
+            stored.tracking_url.as_deref(),
+            Some("https://www.ups.com/track?tracknum=1ZX9Y8W7V6U5T4S3R2")
+        );
+    }
+
+    #[test]
+    fn stores_tracking_url_from_the_matched_number() {
+        let mut poller = poller(config());
+
+        poller.process_messages(&[message(11, "Tracking number: 1Z5R89390357567127")], 10, None);
+
+        let stored = poller.db.get_by_tracking_number("1Z5R89390357567127").unwrap().unwrap();
+        let url = stored.tracking_url.unwrap();
+        assert!(url.contains("1Z5R89390357567127"), "{url}");
+    }
+
+    #[test]
+    fn ignores_unclassified_number_unless_trusted() {
+        let msg = || message_from(11, "UPS <mcinfo@ups.com>", "Tracking number: 1ZX9Y8W7V6U5T4S3R2");
+
+        let mut untrusted = poller(config());
+        untrusted.process_messages(&[msg()], 10, None);
+        assert!(untrusted.db.get_all_packages_with_status().unwrap().is_empty());
+
+        // Another sender's courier isn't the only one configured
+        let mut other_sender = poller(EmailConfig { trust_courier_on_no_match: true, ..config() });
+        let fedex = message_from(11, "FedEx <TrackingUpdates@fedex.com>", "Tracking number: 1ZX9Y8W7V6U5T4S3R2");
+        other_sender.process_messages(&[fedex], 10, None);
+        assert!(other_sender.db.get_all_packages_with_status().unwrap().is_empty());
+    }
+
+    #[test]
+    fn links_order_confirmation_to_later_shipping_email() {
+        let mut poller = poller(EmailConfig {
+            order_confirmation_patterns: vec!["order confirm".into()],
+            ..config()
+        });
+        let confirmation = MailMessage {
+            uid: 11,
+            internal_date: Utc::now(),
+            headers: "From: Acme Outfitters <orders@acme.example>\r\nSubject: Order confirmation\r\n".into(),
+            body: "Subject: Order confirmation\r\n\r\nThanks! Order #A1234567 will ship soon.".into(),
+        };
+
+        poller.process_messages(&[confirmation], 10, None);
+
+        let awaiting = poller.db.get_awaiting_orders().unwrap();
+        assert_eq!(awaiting.len(), 1);
+        assert_eq!(awaiting[0].merchant, "Acme Outfitters");
+        assert_eq!(awaiting[0].order_number, "A1234567");
+        assert!(poller.db.get_all_packages_with_status().unwrap().is_empty());
+
+        let shipped = message(12, "Order #A1234567 has shipped: 1Z5R89390357567127");
+        poller.process_messages(&[shipped], 11, None);
+
+        assert!(poller.db.get_awaiting_orders().unwrap().is_empty());
+        assert!(poller.db.get_by_tracking_number("1Z5R89390357567127").unwrap().is_some());
+    }
+
+    #[test]
+    fn stores_per_shipment_details_from_digest_email() {
+        let mut poller = poller(EmailConfig { parse_digests: true, ..config() });
+        let msg = message(
+            11,
+            "Your order shipped in two packages:\n\
+             | Item | Carrier | Tracking |\n\
+             | Blue T-shirt | UPS Ground | 1Z5R89390357567127 |\n\
+             | Coffee grinder | FedEx Home Delivery | 986578788855 |\n",
+        );
+
+        poller.process_messages(&[msg], 10, None);
+
+        let packages = poller.db.get_all_packages_with_status().unwrap();
+        let shirt = packages.iter().find(|p| p.tracking_number == "1Z5R89390357567127").unwrap();
+        let grinder = packages.iter().find(|p| p.tracking_number == "986578788855").unwrap();
+        assert_eq!(shirt.service, "UPS Ground");
+        assert_eq!(shirt.description.as_deref(), Some("Blue T-shirt"));
+        assert_eq!(grinder.service, "FedEx Home Delivery");
+        assert_eq!(grinder.description.as_deref(), Some("Coffee grinder"));
+    }
+
+    #[test]
+    fn lookback_covers_mail_dated_a_day_behind_utc() {
+        let now = DateTime::parse_from_rfc3339("2026-03-11T00:30:00Z").unwrap().with_timezone(&Utc);
+        // Received 23.5 hours ago; a server at UTC-5 dates it March 9
+        let received = DateTime::parse_from_rfc3339("2026-03-09T20:00:00-05:00").unwrap();
+
+        assert!(received.date_naive() >= lookback_since(now, 1));
+    }
+}
@@ -1,6 +1,69 @@
+use crate::config::CourierOverrideConfig;
+use crate::courier::CourierCode;
+use anyhow::{Context, Result};
 use regex::Regex;
 use tracking_numbers::{track, TrackingResult};
 
+/// A compiled `email.courier_overrides` rule.
+pub struct CourierOverride {
+    pattern: Regex,
+    courier: CourierCode,
+}
+
+impl CourierOverride {
+    pub fn compile(config: &CourierOverrideConfig) -> Result<Self> {
+        let pattern = Regex::new(&config.pattern)
+            .with_context(|| format!("Invalid courier override pattern '{}'", config.pattern))?;
+        let courier = config.courier.parse::<CourierCode>()?;
+        Ok(Self { pattern, courier })
+    }
+
+    pub fn matches(&self, tracking_number: &str) -> bool {
+        self.pattern.is_match(tracking_number)
+    }
+}
+
+/// Courier domains whose mail confirms the courier of the numbers it contains.
+const SENDER_DOMAINS: &[(&str, CourierCode)] = &[
+    ("ups.com", CourierCode::UPS),
+    ("fedex.com", CourierCode::FedEx),
+    ("usps.com", CourierCode::USPS),
+    ("usps.gov", CourierCode::USPS),
+    ("canadapost.ca", CourierCode::CanadaPost),
+];
+
+/// The courier that sent an email, judged by the domain of its From address,
+/// e.g. `"UPS <mcinfo@ups.com>"` → UPS.
+pub fn sender_courier(from: &str) -> Option<CourierCode> {
+    let address = from.rsplit('<').next()?.trim_end_matches('>').trim();
+    let domain = address.rsplit_once('@')?.1.to_lowercase();
+
+    SENDER_DOMAINS
+        .iter()
+        .find(|(known, _)| domain == *known || domain.ends_with(&format!(".{known}")))
+        .map(|(_, courier)| courier.clone())
+}
+
+/// Phrases that usually sit right next to the real tracking number. Matched
+/// against the uppercased text.
+const SHIPPING_KEYWORDS: &[&str] = &[
+    "TRACKING NUMBER",
+    "TRACKING #",
+    "TRACKING NO",
+    "TRACK YOUR",
+    "TRACKING",
+    "SHIPMENT",
+    "SHIPPED",
+];
+
+/// How far, in bytes, an unclassified candidate may sit from a shipping
+/// keyword and still be taken as a tracking number.
+const MAX_UNCLASSIFIED_KEYWORD_DISTANCE: usize = 40;
+
+/// Digit-count bounds for a spaced number rejoined across a line wrap.
+const MIN_WRAPPED_DIGITS: usize = 20;
+const MAX_WRAPPED_DIGITS: usize = 34;
+
 /// Extracts tracking-number-like strings from arbitrary text.
 /// This is intentionally carrier-agnostic.
 pub fn extract_candidates(text: &str) -> Vec<String> {
@@ -15,6 +78,22 @@ pub fn extract_candidates(text: &str) -> Vec<String> {
     let re_spaced =
         Regex::new(r"\b\d{2,4}(?: \d{2,4}){3,}\b").expect("invalid spaced tracking regex");
 
+    // Pre-pass: rejoin space-separated digit groups wrapped onto the following
+    // line, e.g. "9400 1112 0620\n6406 2607 87" after html2text wrapping. Only
+    // rejoin when the result is a plausible tracking number length, so
+    // unrelated numbers on adjacent lines aren't merged.
+    let re_wrapped = Regex::new(r"\b\d{2,4}(?:[ \t]+\d{2,4})+[ \t]*\r?\n[ \t]*\d{2,4}(?:[ \t]+\d{2,4})*\b")
+        .expect("invalid wrapped tracking regex");
+    let uppercased = re_wrapped.replace_all(&uppercased, |caps: &regex::Captures| {
+        let joined = caps[0].split_whitespace().collect::<Vec<_>>().join(" ");
+        let digits = joined.chars().filter(|c| c.is_ascii_digit()).count();
+        if (MIN_WRAPPED_DIGITS..=MAX_WRAPPED_DIGITS).contains(&digits) {
+            joined
+        } else {
+            caps[0].to_string()
+        }
+    });
+
     let mut seen = std::collections::HashSet::new();
 
     for m in re_contiguous.find_iter(&uppercased) {
@@ -31,27 +110,282 @@ pub fn extract_candidates(text: &str) -> Vec<String> {
         }
     }
 
+    // Also try rejoining words broken across lines, for numbers hard-wrapped
+    // mid-number, e.g. "1Z5R893903575671\n27". Joining lines is a guess, so
+    // only numbers that validate are kept.
+    for s in rejoin_split_words(&uppercased) {
+        if (12..=34).contains(&s.len()) && track(&s).is_some() && seen.insert(s.clone()) {
+            results.push(s);
+        }
+    }
+
     results
 }
 
+/// Every way of joining the last word of a line with the start of the lines
+/// after it: the first word of the next line, or, through lines holding a
+/// single word, of a later one. Each line's tail starts its own joins, so a
+/// wrapped number is found whatever precedes it, and over any number of lines.
+fn rejoin_split_words(text: &str) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let mut joined = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let tail = line.rsplit(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or_default();
+        if tail.is_empty() {
+            continue;
+        }
+
+        let mut word = tail.to_string();
+        for next in &lines[i + 1..] {
+            let head = next.split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or_default();
+            if head.is_empty() || word.len() + head.len() > 34 {
+                break;
+            }
+            joined.push(format!("{word}{head}"));
+            // Only a line that is one whole word can continue onto the next
+            if head.len() != next.len() {
+                break;
+            }
+            word.push_str(head);
+        }
+    }
+
+    joined
+}
+
+/// The text of an email without its quoted reply and forwarded sections:
+/// lines starting with `>` and everything from a separator such as
+/// "-----Original Message-----" or "On ... wrote:" onwards.
+pub fn strip_quoted(text: &str) -> String {
+    let separator = Regex::new(
+        r"(?mi)^[ \t]*(?:-{2,}[ \t]*(?:original message|forwarded message)[ \t]*-{2,}|begin forwarded message:|on\b[^\n]*(?:\r?\n[^\n]*)?\bwrote:)[ \t]*\r?$",
+    )
+    .expect("invalid quoted section regex");
+
+    let top = separator.find(text).map_or(text, |m| &text[..m.start()]);
+    top.lines()
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// Extracts candidate strings from text, validates each with the
 /// tracking-numbers crate, and returns only confirmed tracking numbers.
+/// Numbers closest to a shipping keyword ("tracking number", "shipment", ...)
+/// come first, so the most likely real tracking number is the primary one.
 pub fn extract_tracking_numbers(text: &str) -> Vec<TrackingResult> {
+    let uppercased = text.to_uppercase();
     let mut seen = std::collections::HashSet::new();
-    extract_candidates(text)
+    let mut results: Vec<(Option<usize>, TrackingResult)> = extract_candidates(text)
         .into_iter()
         .filter_map(|candidate| {
             let cleaned: String = candidate.chars().filter(|c| !c.is_whitespace()).collect();
-            track(&cleaned)
+            track_number(&cleaned).map(|result| (keyword_distance(&uppercased, &candidate), result))
+        })
+        .filter(|(_, result)| seen.insert(result.tracking_number.clone()))
+        .collect();
+
+    // Stable sort: candidates with no nearby keyword keep their text order, last
+    results.sort_by_key(|(distance, _)| distance.unwrap_or(usize::MAX));
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Validate a tracking number with the tracking-numbers crate. International
+/// S10 numbers issued by Canada Post (ending in `CA`) are attributed to it, so
+/// they are checked with the Canada Post client.
+pub fn track_number(number: &str) -> Option<TrackingResult> {
+    let mut result = track(number)?;
+    if result.service == "S10" && result.tracking_number.ends_with("CA") {
+        result.courier = CourierCode::CanadaPost.display_name().to_string();
+    }
+    Some(result)
+}
+
+/// Candidates from text that the tracking-numbers crate can't classify, with
+/// whitespace removed. Only candidates close to a shipping keyword are kept,
+/// so order numbers and the like elsewhere in the email aren't picked up.
+/// Only useful when the courier is known some other way.
+pub fn unclassified_candidates(text: &str) -> Vec<String> {
+    let uppercased = text.to_uppercase();
+    extract_candidates(text)
+        .into_iter()
+        .filter(|candidate| {
+            keyword_distance(&uppercased, candidate)
+                .is_some_and(|distance| distance <= MAX_UNCLASSIFIED_KEYWORD_DISTANCE)
         })
-        .filter(|result| seen.insert(result.tracking_number.clone()))
+        .map(|candidate| candidate.chars().filter(|c| !c.is_whitespace()).collect::<String>())
+        .filter(|cleaned| track(cleaned).is_none())
         .collect()
 }
 
+/// Distance in bytes between the first occurrence of `candidate` in the
+/// uppercased text and the nearest shipping keyword, if both are present.
+fn keyword_distance(uppercased: &str, candidate: &str) -> Option<usize> {
+    let start = uppercased.find(candidate)?;
+    let end = start + candidate.len();
+
+    SHIPPING_KEYWORDS
+        .iter()
+        .flat_map(|keyword| {
+            uppercased.match_indices(keyword).map(|(pos, keyword)| {
+                if pos + keyword.len() <= start {
+                    start - (pos + keyword.len())
+                } else {
+                    pos.saturating_sub(end)
+                }
+            })
+        })
+        .min()
+}
+
+/// Destination ZIP code from a shipping address block, e.g. the "AUSTIN, TX
+/// 78701" line after "Ship to:". Only addresses following a ship-to style
+/// label are considered, so a sender's return address isn't picked up.
+pub fn extract_destination_zip(text: &str) -> Option<String> {
+    let label = Regex::new(r"(?i)\b(?:ship(?:ping)?\s+to|deliver(?:y|ing)?\s+(?:to|address)|shipping\s+address)\b")
+        .expect("invalid ship-to label regex");
+    let city_state_zip = Regex::new(r"\b[A-Za-z][A-Za-z .'-]*,?\s+[A-Z]{2}\s+(\d{5})(?:-\d{4})?\b")
+        .expect("invalid address regex");
+
+    let start = label.find(text)?.end();
+    city_state_zip
+        .captures(&text[start..])
+        .map(|caps| caps[1].to_string())
+}
+
+/// Find the order number in an order confirmation or shipping email, e.g.
+/// "Order #112-3456789-1234567" or "Order number: W123456789". Words right
+/// after "order" without a digit ("Order confirmed") aren't order numbers.
+pub fn extract_order_number(text: &str) -> Option<String> {
+    let order = Regex::new(r"(?i)\border\s*(?:number|no\.?|id|#)?\s*[:#]?\s*([A-Z0-9][A-Z0-9-]{3,})\b")
+        .expect("invalid order number regex");
+
+    order
+        .captures_iter(text)
+        .map(|caps| caps[1].to_uppercase())
+        .find(|number| number.chars().any(|c| c.is_ascii_digit()))
+}
+
+/// A merchant name for an email sender: the display name if there is one,
+/// otherwise the address's domain.
+pub fn sender_name(from: &str) -> String {
+    if let Some((name, _)) = from.split_once('<') {
+        let name = name.trim().trim_matches('"').trim();
+        if !name.is_empty() {
+            return name.to_string();
+        }
+    }
+
+    let address = from.rsplit('<').next().unwrap_or(from).trim_end_matches('>').trim();
+    match address.rsplit_once('@') {
+        Some((_, domain)) => domain.to_lowercase(),
+        None => address.to_string(),
+    }
+}
+
+/// Details a digest email gives about one of the shipments it lists.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ShipmentDetails {
+    /// The shipping service named next to the number, e.g. "UPS Ground".
+    pub service: Option<String>,
+    /// Everything else in the shipment's row, e.g. the item shipped.
+    pub description: Option<String>,
+}
+
+/// Per-shipment details from an email listing several shipments, one per
+/// table row or line: the cells sharing a row with `tracking_number`. A cell
+/// naming a courier or service becomes the service; the rest describe the
+/// shipment. Rows that also hold another of `all_numbers` can't be
+/// attributed to one shipment and give no details.
+pub fn shipment_details(text: &str, tracking_number: &str, all_numbers: &[&str]) -> ShipmentDetails {
+    let cell_separator = Regex::new(r"\s*(?:[|│┃\t]|\s{2,})\s*").expect("invalid cell separator regex");
+    let service_words = Regex::new(r"(?i)\b(?:ups|fedex|usps|dhl|ontrac|lasership|postal|priority mail|ground|express)\b")
+        .expect("invalid service regex");
+    let compact = |s: &str| s.split_whitespace().collect::<String>();
+
+    let Some(row) = text.lines().find(|line| compact(line).contains(tracking_number)) else {
+        return ShipmentDetails::default();
+    };
+    if all_numbers
+        .iter()
+        .any(|other| *other != tracking_number && compact(row).contains(other))
+    {
+        return ShipmentDetails::default();
+    }
+
+    let mut details = ShipmentDetails::default();
+    let mut description = Vec::new();
+    for cell in cell_separator.split(row.trim()) {
+        let cell = cell.trim_matches(|c: char| c.is_whitespace() || "─━-:".contains(c));
+        if cell.is_empty() || compact(cell).contains(tracking_number) {
+            continue;
+        }
+        if details.service.is_none() && service_words.is_match(cell) {
+            details.service = Some(cell.to_string());
+        } else {
+            description.push(cell);
+        }
+    }
+    if !description.is_empty() {
+        details.description = Some(description.join(" · "));
+    }
+    details
+}
+
+/// Force the courier of any result matching an override rule. The first
+/// matching rule wins.
+pub fn apply_courier_overrides(results: &mut [TrackingResult], overrides: &[CourierOverride]) {
+    for result in results.iter_mut() {
+        if let Some(rule) = overrides
+            .iter()
+            .find(|rule| rule.matches(&result.tracking_number))
+            && result.courier.parse::<CourierCode>().ok().as_ref() != Some(&rule.courier)
+        {
+            // The matched courier's URL and service name belong to the wrong courier
+            result.courier = rule.courier.to_string();
+            result.service.clear();
+            result.tracking_url.clear();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn rejoins_numbers_wrapped_mid_number() {
+        let text = "Your UPS tracking number is 1Z5R893903575671\n27 and arrives Wednesday.";
+        let results = extract_tracking_numbers(text);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tracking_number, "1Z5R89390357567127");
+
+        // Adjacent lines that don't join into a valid number stay apart
+        let text = "Order 112233\n445566778899 shipped";
+        assert!(!extract_candidates(text).contains(&"112233445566778899".to_string()));
+    }
+
+    #[test]
+    fn rejoins_number_wrapped_after_a_word_ending_a_line() {
+        // The line before the number ends in a word, which must not claim the
+        // number's first line for itself
+        let text = "Tracking\n1Z5R893903\n57567127\nArriving Wednesday";
+        let results = extract_tracking_numbers(text);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tracking_number, "1Z5R89390357567127");
+    }
+
+    #[test]
+    fn rejoins_number_wrapped_over_three_lines() {
+        let text = "Your UPS tracking number is 1Z5R89\n390357\n567127 and arrives Wednesday.";
+        let results = extract_tracking_numbers(text);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tracking_number, "1Z5R89390357567127");
+    }
+
     #[test]
     fn extracts_basic_tracking_numbers() {
         let text = "Your tracking number is 1Z999AA10123456784.";
@@ -121,4 +455,182 @@ mod tests {
 
         assert!(results.is_empty());
     }
+
+    #[test]
+    fn courier_override_changes_assigned_courier() {
+        let mut results = extract_tracking_numbers("Your package: 1Z5R89390357567127 is on its way");
+        let overrides = vec![
+            CourierOverride::compile(&CourierOverrideConfig {
+                pattern: "^1Z5R8".into(),
+                courier: "fedex".into(),
+            })
+            .unwrap(),
+        ];
+
+        apply_courier_overrides(&mut results, &overrides);
+
+        assert_eq!(results[0].courier, "fedex");
+        assert!(results[0].service.is_empty());
+        assert!(results[0].tracking_url.is_empty());
+    }
+
+    #[test]
+    fn courier_override_ignores_non_matching_numbers() {
+        let mut results = extract_tracking_numbers("Your package: 1Z5R89390357567127 is on its way");
+        let courier = results[0].courier.clone();
+        let overrides = vec![
+            CourierOverride::compile(&CourierOverrideConfig {
+                pattern: "^9400".into(),
+                courier: "usps".into(),
+            })
+            .unwrap(),
+        ];
+
+        apply_courier_overrides(&mut results, &overrides);
+
+        assert_eq!(results[0].courier, courier);
+    }
+
+    #[test]
+    fn recovers_number_split_across_lines() {
+        let text = "USPS tracking: 9400 1112 0620 6406\n2607 87\nThanks!";
+        let results = extract_tracking_numbers(text);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tracking_number, "9400111206206406260787");
+    }
+
+    #[test]
+    fn does_not_merge_short_numbers_on_adjacent_lines() {
+        let text = "Call 555 123\n4567 today";
+        let result = extract_candidates(text);
+
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn ranks_keyword_adjacent_number_first() {
+        let text = "Order reference 1Z999AA10123456784 for your records.\n\
+                    Thanks for shopping with us! We appreciate your business and hope to see you again soon.\n\
+                    Your tracking number is 1Z5R89390357567127.";
+        let results = extract_tracking_numbers(text);
+
+        let numbers: Vec<&str> = results.iter().map(|r| r.tracking_number.as_str()).collect();
+        assert_eq!(numbers, vec!["1Z5R89390357567127", "1Z999AA10123456784"]);
+    }
+
+    #[test]
+    fn attributes_canadian_s10_numbers_to_canada_post() {
+        let canadian = track_number("RB123456785CA").unwrap();
+        assert_eq!(canadian.courier.parse::<CourierCode>().ok(), Some(CourierCode::CanadaPost));
+
+        let british = track_number("RB123456785GB").unwrap();
+        assert_eq!(british.courier, track("RB123456785GB").unwrap().courier);
+    }
+
+    #[test]
+    fn identifies_courier_sender_domains() {
+        assert_eq!(sender_courier("UPS <mcinfo@ups.com>"), Some(CourierCode::UPS));
+        assert_eq!(sender_courier("TrackingUpdates@fedex.com"), Some(CourierCode::FedEx));
+        assert_eq!(sender_courier("USPS <auto-reply@email.usps.com>"), Some(CourierCode::USPS));
+        assert_eq!(sender_courier("Canada Post <noreply@notifications.canadapost.ca>"), Some(CourierCode::CanadaPost));
+        assert_eq!(sender_courier("Shop <orders@notups.com>"), None);
+        assert_eq!(sender_courier("not an address"), None);
+    }
+
+    #[test]
+    fn extracts_destination_zip_after_ship_to_label() {
+        let text = "Acme Store, 1 Main St, Seattle, WA 98101\n\
+                    Ship to:\nJane Doe\n500 Congress Ave\nAUSTIN, TX 78701-1234\n";
+        assert_eq!(extract_destination_zip(text).as_deref(), Some("78701"));
+
+        assert_eq!(extract_destination_zip("Acme Store, Seattle, WA 98101"), None);
+    }
+
+    #[test]
+    fn lists_only_unclassified_candidates() {
+        let text = "Tracking: 1Z5R89390357567127 and 1ZX9Y8W7V6U5T4S3R2";
+
+        assert_eq!(unclassified_candidates(text), vec!["1ZX9Y8W7V6U5T4S3R2"]);
+    }
+
+    #[test]
+    fn lists_unclassified_candidates_only_near_a_keyword() {
+        let text = "Your tracking number is 1ZX9Y8W7V6U5T4S3R2.\n\n\
+                    Thanks for your order! Keep this receipt for your records.\n\
+                    Reference: ABC123DEF456GHI789";
+
+        assert_eq!(unclassified_candidates(text), vec!["1ZX9Y8W7V6U5T4S3R2"]);
+        assert!(unclassified_candidates("Reference: ABC123DEF456GHI789").is_empty());
+    }
+
+    #[test]
+    fn extracts_order_numbers_with_a_digit() {
+        assert_eq!(
+            extract_order_number("Order Confirmation\nOrder #112-3456789-1234567"),
+            Some("112-3456789-1234567".into())
+        );
+        assert_eq!(extract_order_number("Your order number: w123456789"), Some("W123456789".into()));
+        assert_eq!(extract_order_number("Your order has been received"), None);
+    }
+
+    #[test]
+    fn names_merchant_from_display_name_or_domain() {
+        assert_eq!(sender_name("\"Acme Outfitters\" <orders@acme.example>"), "Acme Outfitters");
+        assert_eq!(sender_name("<orders@Shop.Example>"), "shop.example");
+        assert_eq!(sender_name("orders@shop.example"), "shop.example");
+    }
+
+    #[test]
+    fn reads_service_and_description_from_a_shipment_row() {
+        let text = "| Item | Carrier | Tracking |\n\
+                    | Blue T-shirt | UPS Ground | 1Z5R89390357567127 |\n\
+                    | Coffee grinder | FedEx Home Delivery | 986578788855 |";
+        let numbers = ["1Z5R89390357567127", "986578788855"];
+
+        assert_eq!(
+            shipment_details(text, "986578788855", &numbers),
+            ShipmentDetails {
+                service: Some("FedEx Home Delivery".into()),
+                description: Some("Coffee grinder".into()),
+            }
+        );
+        assert_eq!(
+            shipment_details("Both: 1Z5R89390357567127 and 986578788855", "986578788855", &numbers),
+            ShipmentDetails::default()
+        );
+    }
+
+    #[test]
+    fn ignores_numbers_in_inline_replies() {
+        let text = "> Your order shipped! Tracking number: 986578788855\n\
+                    > Thanks for shopping with us.\n\
+                    That one was lost, here's the replacement: 1Z5R89390357567127\n\
+                    \n\
+                    On Mon, Mar 2, 2026 at 9:00 AM Acme Outfitters <orders@acme.example>\n\
+                    wrote:\n\
+                    Earlier shipment: 1Z999AA10123456784";
+        let results = extract_tracking_numbers(&strip_quoted(text));
+
+        let numbers: Vec<&str> = results.iter().map(|r| r.tracking_number.as_str()).collect();
+        assert_eq!(numbers, vec!["1Z5R89390357567127"]);
+    }
+
+    #[test]
+    fn ignores_numbers_in_forwarded_messages() {
+        let text = "FYI, the new tracking number is 1Z5R89390357567127\n\
+                    \n\
+                    -----Original Message-----\n\
+                    From: Acme Outfitters <orders@acme.example>\n\
+                    Subject: Your order has shipped\n\
+                    Tracking number: 1Z5R89390357567127\n\
+                    Previous shipment: 986578788855";
+        let results = extract_tracking_numbers(&strip_quoted(text));
+
+        let numbers: Vec<&str> = results.iter().map(|r| r.tracking_number.as_str()).collect();
+        assert_eq!(numbers, vec!["1Z5R89390357567127"]);
+
+        let gmail = "See below\n---------- Forwarded message ---------\nTracking: 986578788855";
+        assert_eq!(strip_quoted(gmail), "See below");
+    }
 }
@@ -1,7 +1,11 @@
-use crate::config::EmailConfig;
+use crate::config::{EmailConfig, ImapAuth, ImapSecurity, OAuth2Config};
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
-use tracing::info;
+use chrono::{DateTime, NaiveDate, Utc};
+use imap::ConnectionMode;
+use imap::extensions::idle::WaitOutcome;
+use imap::types::UnsolicitedResponse;
+use std::time::Duration;
+use tracing::{info, warn};
 
 #[derive(Debug)]
 pub struct MailMessage {
@@ -19,25 +23,60 @@ pub struct ParsedMessage {
     pub body_text: String,
 }
 
+type Session = imap::Session<Box<dyn imap::ImapConnection>>;
+
 pub struct ImapClient {
-    session: imap::Session<Box<dyn imap::ImapConnection>>,
+    config: EmailConfig,
+    session: Session,
     uid_next: Option<u32>,
 }
 
+/// A mailbox that can fetch messages by UID and re-establish its connection.
+/// Split out from [`ImapClient`] so batch/retry logic can be tested without a server.
+pub trait MessageSource {
+    fn fetch_uids(&mut self, uids: &[u32]) -> Result<Vec<MailMessage>>;
+    fn reconnect(&mut self) -> Result<()>;
+}
+
 impl ImapClient {
     pub fn connect(config: &EmailConfig) -> Result<Self> {
+        let (session, uid_next) = Self::open_session(config)?;
+        Ok(Self {
+            config: config.clone(),
+            session,
+            uid_next,
+        })
+    }
+
+    fn open_session(config: &EmailConfig) -> Result<(Session, Option<u32>)> {
         let server = config.server.as_ref().context("email.server missing")?;
         let username = config.username.as_ref().context("email.username missing")?;
-        let password = config.password.as_ref().context("email.password missing")?;
 
+        let mode = match config.security {
+            ImapSecurity::Tls => ConnectionMode::Tls,
+            ImapSecurity::Starttls => ConnectionMode::StartTls,
+            ImapSecurity::Plaintext => ConnectionMode::Plaintext,
+        };
         let client = imap::ClientBuilder::new(server, config.port)
+            .mode(mode)
             .connect()
             .context("Failed to connect to IMAP server")?;
 
-        let mut session = client
-            .login(username, password)
-            .map_err(|e| e.0)
-            .context("Failed to authenticate to IMAP server")?;
+        let mut session = match config.auth {
+            ImapAuth::Password => {
+                let password = config.password.as_ref().context("email.password missing")?;
+                client.login(username, password).map_err(|e| e.0)
+            }
+            ImapAuth::Xoauth2 => {
+                let oauth2 = config.oauth2.as_ref().context("email.oauth2 missing")?;
+                let authenticator = XOAuth2 {
+                    user: username.clone(),
+                    access_token: access_token(oauth2)?,
+                };
+                client.authenticate("XOAUTH2", &authenticator).map_err(|e| e.0)
+            }
+        }
+        .context("Failed to authenticate to IMAP server")?;
 
         let mailbox = session
             .select(&config.folder)
@@ -47,7 +86,7 @@ impl ImapClient {
 
         info!(folder = %config.folder, uid_next = ?uid_next, "IMAP folder selected");
 
-        Ok(Self { session, uid_next })
+        Ok((session, uid_next))
     }
 
     /// Returns the `UIDNEXT` value from the mailbox SELECT response.
@@ -61,18 +100,14 @@ impl ImapClient {
     /// This catches newly delivered, moved, and copied messages regardless
     /// of their internal date.
     pub fn fetch_messages_since_uid(&mut self, last_seen_uid: u32) -> Result<Vec<MailMessage>> {
-        let search_range = format!("UID {}:*", last_seen_uid + 1);
-
         info!(since_uid = last_seen_uid + 1, "Searching for new messages");
 
         let uids = self
             .session
-            .uid_search(search_range)
+            .uid_search(uid_range(last_seen_uid))
             .context("IMAP UID search failed")?;
 
-        // Filter out UIDs we've already seen (IMAP `UID x:*` always includes
-        // at least the highest existing UID even if it's <= x)
-        let new_uids: Vec<u32> = uids.into_iter().filter(|&uid| uid > last_seen_uid).collect();
+        let new_uids = unseen_uids(uids, last_seen_uid);
 
         info!(count = new_uids.len(), "New messages found");
 
@@ -80,7 +115,50 @@ impl ImapClient {
             return Ok(vec![]);
         }
 
-        let uid_list = new_uids
+        let batch_size = self.config.fetch_batch_size;
+        let retries = self.config.fetch_retries;
+        fetch_in_batches(self, &new_uids, batch_size, retries)
+    }
+
+    /// The lowest UID of the messages the server received on or after
+    /// `since`, if there are any.
+    pub fn first_uid_since(&mut self, since: NaiveDate) -> Result<Option<u32>> {
+        let uids = self
+            .session
+            .uid_search(since_query(since))
+            .context("IMAP SINCE search failed")?;
+        Ok(uids.into_iter().min())
+    }
+
+    /// Whether the server advertises the IDLE capability.
+    pub fn supports_idle(&mut self) -> Result<bool> {
+        let capabilities = self.session.capabilities().context("IMAP CAPABILITY failed")?;
+        Ok(capabilities.has_str("IDLE"))
+    }
+
+    /// Wait in IMAP IDLE for up to `timeout`. Returns `true` when the server
+    /// signals new mail in the selected folder, `false` on timeout.
+    pub fn idle_wait(&mut self, timeout: Duration) -> Result<bool> {
+        let outcome = self
+            .session
+            .idle()
+            .timeout(timeout)
+            .keepalive(false)
+            .wait_while(|response| !matches!(response, UnsolicitedResponse::Exists(_)))
+            .context("IMAP IDLE failed")?;
+        Ok(outcome == WaitOutcome::MailboxChanged)
+    }
+
+    pub fn logout(mut self) -> Result<()> {
+        info!("Closing IMAP server connection");
+        self.session.logout()?;
+        Ok(())
+    }
+}
+
+impl MessageSource for ImapClient {
+    fn fetch_uids(&mut self, uids: &[u32]) -> Result<Vec<MailMessage>> {
+        let uid_list = uids
             .iter()
             .map(ToString::to_string)
             .collect::<Vec<_>>()
@@ -99,17 +177,21 @@ impl ImapClient {
                 None => continue,
             };
 
-            let internal_date = match msg.internal_date() {
-                Some(d) => d.with_timezone(&Utc),
-                None => continue,
-            };
-
             let headers = msg
                 .header()
                 .and_then(|h| std::str::from_utf8(h).ok())
                 .unwrap_or("")
                 .to_string();
 
+            let internal_date = msg.internal_date().map(|d| d.with_timezone(&Utc));
+            let Some(internal_date) = message_date(internal_date, &headers, self.config.date_header_fallback) else {
+                warn!(uid, "Skipping message without INTERNALDATE or Date header");
+                continue;
+            };
+            if msg.internal_date().is_none() {
+                warn!(uid, date = %internal_date, "Message has no INTERNALDATE, using its Date header");
+            }
+
             let body = msg
                 .body()
                 .and_then(|b| std::str::from_utf8(b).ok())
@@ -127,16 +209,154 @@ impl ImapClient {
         Ok(messages)
     }
 
-    pub fn logout(mut self) -> Result<()> {
-        info!("Closing IMAP server connection");
-        self.session.logout()?;
+    fn reconnect(&mut self) -> Result<()> {
+        let (session, uid_next) = Self::open_session(&self.config)?;
+        self.session = session;
+        self.uid_next = uid_next;
         Ok(())
     }
 }
 
-use mailparse::{ParsedMail, parse_mail};
+/// SASL XOAUTH2 authenticator: sends the user and bearer token as the
+/// initial response.
+struct XOAuth2 {
+    user: String,
+    access_token: String,
+}
+
+impl imap::Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(&self, _challenge: &[u8]) -> Self::Response {
+        format!("user={}\x01auth=Bearer {}\x01\x01", self.user, self.access_token)
+    }
+}
+
+/// The configured access token, or a fresh one minted from the refresh token.
+fn access_token(oauth2: &OAuth2Config) -> Result<String> {
+    if let Some(token) = &oauth2.access_token {
+        return Ok(token.clone());
+    }
+
+    let token_url = oauth2.token_url.as_deref().context("email.oauth2.token_url missing")?;
+    let refresh_token = oauth2.refresh_token.as_deref().context("email.oauth2.refresh_token missing")?;
+    let client_id = oauth2.client_id.as_deref().context("email.oauth2.client_id missing")?;
+    let client_secret = oauth2.client_secret.as_deref().context("email.oauth2.client_secret missing")?;
+
+    info!(token_url, "Refreshing OAuth2 access token");
+    let response = ureq::post(token_url)
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .send_form([
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+        ])
+        .with_context(|| format!("OAuth2 token refresh request to {token_url} failed"))?;
+    let status = response.status();
+    let body: serde_json::Value = response
+        .into_body()
+        .read_json()
+        .with_context(|| format!("OAuth2 token refresh returned HTTP {status} without a JSON body"))?;
+
+    match body["access_token"].as_str() {
+        Some(token) if status.is_success() => Ok(token.to_string()),
+        _ => {
+            let reason = body["error_description"].as_str().or(body["error"].as_str()).unwrap_or("no access_token");
+            anyhow::bail!("OAuth2 token refresh failed (HTTP {status}): {reason}")
+        }
+    }
+}
+
+/// The message's INTERNALDATE, or when the server didn't return one and
+/// `fallback` is set, the date in its `Date:` header.
+/// Search criteria for the messages after `last_seen_uid`.
+fn uid_range(last_seen_uid: u32) -> String {
+    format!("UID {}:*", last_seen_uid.saturating_add(1))
+}
+
+/// The UIDs above `last_seen_uid`, in ascending order. IMAP `UID x:*` always
+/// matches at least the highest existing UID even if it's below `x`, so an
+/// unchanged mailbox returns an already-seen message that must be dropped.
+fn unseen_uids(uids: impl IntoIterator<Item = u32>, last_seen_uid: u32) -> Vec<u32> {
+    let mut new_uids: Vec<u32> = uids.into_iter().filter(|&uid| uid > last_seen_uid).collect();
+    new_uids.sort_unstable();
+    new_uids
+}
+
+/// Search criteria for messages received on or after `since`, e.g.
+/// `SINCE 2-Mar-2026`.
+fn since_query(since: NaiveDate) -> String {
+    format!("SINCE {}", since.format("%-d-%b-%Y"))
+}
+
+fn message_date(internal_date: Option<DateTime<Utc>>, headers: &str, fallback: bool) -> Option<DateTime<Utc>> {
+    internal_date.or_else(|| {
+        let header = get_header(headers, "Date").filter(|_| fallback)?;
+        let timestamp = mailparse::dateparse(&header).ok()?;
+        DateTime::from_timestamp(timestamp, 0)
+    })
+}
+
+/// Fetch `uids` (ascending) in batches, reconnecting and retrying a batch up
+/// to `retries` times if the connection drops mid-fetch. If a batch still
+/// fails, the messages from earlier batches are returned so the caller can
+/// advance past them; the error is only returned when nothing was fetched.
+pub fn fetch_in_batches(
+    source: &mut impl MessageSource,
+    uids: &[u32],
+    batch_size: usize,
+    retries: u32,
+) -> Result<Vec<MailMessage>> {
+    let mut messages = Vec::new();
+
+    for batch in uids.chunks(batch_size.max(1)) {
+        let mut attempt = 0;
+        let fetched = loop {
+            match source.fetch_uids(batch) {
+                Ok(fetched) => break Ok(fetched),
+                Err(err) if attempt < retries => {
+                    attempt += 1;
+                    warn!(
+                        error = %err,
+                        attempt,
+                        first_uid = batch[0],
+                        "IMAP fetch failed, reconnecting to retry batch"
+                    );
+                    if let Err(err) = source.reconnect() {
+                        break Err(err);
+                    }
+                }
+                Err(err) => break Err(err),
+            }
+        };
+
+        match fetched {
+            Ok(fetched) => messages.extend(fetched),
+            Err(err) if messages.is_empty() => return Err(err),
+            Err(err) => {
+                warn!(
+                    error = %err,
+                    fetched = messages.len(),
+                    "IMAP fetch failed, keeping messages fetched so far"
+                );
+                break;
+            }
+        }
+    }
+
+    Ok(messages)
+}
+
+use mailparse::{MailHeaderMap, ParsedMail, parse_mail};
 
-fn extract_text_from_part(part: &ParsedMail) -> Option<String> {
+/// The readable text of a message part. `get_body` undoes the transfer
+/// encoding (quoted-printable, base64) and charset before HTML is converted
+/// to text, wrapped at `html_wrap_width` columns. Empty parts are skipped, and
+/// in `multipart/alternative` the plain text version is preferred over HTML.
+fn extract_text_from_part(part: &ParsedMail, html_wrap_width: usize) -> Option<String> {
     let ctype = part.ctype.mimetype.to_lowercase();
 
     if ctype == "text/plain" {
@@ -145,39 +365,269 @@ fn extract_text_from_part(part: &ParsedMail) -> Option<String> {
 
     if ctype == "text/html" {
         let html = part.get_body().ok()?;
-        return Some(html2text::from_read(html.as_bytes(), 80));
+        return Some(html2text::from_read(html.as_bytes(), html_wrap_width));
     }
 
-    for subpart in &part.subparts {
-        if let Some(text) = extract_text_from_part(subpart) {
-            return Some(text);
-        }
+    let mut subparts: Vec<&ParsedMail> = part.subparts.iter().collect();
+    if ctype == "multipart/alternative" {
+        subparts.sort_by_key(|subpart| !subpart.ctype.mimetype.eq_ignore_ascii_case("text/plain"));
     }
 
-    None
+    subparts
+        .into_iter()
+        .filter_map(|subpart| extract_text_from_part(subpart, html_wrap_width))
+        .find(|text| !text.trim().is_empty())
+}
+
+/// The value of the first header called `name` (case-insensitive), with
+/// folded lines joined and RFC 2047 encoded-words decoded by mailparse, e.g.
+/// `=?UTF-8?B?U2hpcHBlZA==?=` → "Shipped".
+fn decoded_header(headers: &str, name: &str) -> Option<String> {
+    let (headers, _) = mailparse::parse_headers(headers.as_bytes()).ok()?;
+    headers.get_first_value(name)
 }
 
+/// The value of the first header called `name` (case-insensitive). Folded
+/// continuation lines, which start with a space or tab, are joined onto the
+/// header with a single space.
 fn get_header(headers: &str, name: &str) -> Option<String> {
+    let mut value: Option<String> = None;
     for line in headers.lines() {
-        if line.to_lowercase().starts_with(&name.to_lowercase()) {
-            return Some(line[name.len() + 1..].trim().to_string());
+        let is_continuation = line.starts_with([' ', '\t']);
+        match value.as_mut() {
+            Some(value) if is_continuation => {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            Some(_) => break,
+            None if is_continuation => {}
+            None => {
+                if let Some((key, rest)) = line.split_once(':')
+                    && key.trim_end().eq_ignore_ascii_case(name)
+                {
+                    value = Some(rest.trim().to_string());
+                }
+            }
         }
     }
-    None
+    value
 }
 
-pub fn parse_message(msg: &MailMessage) -> Result<ParsedMessage> {
+pub fn parse_message(msg: &MailMessage, html_wrap_width: usize) -> Result<ParsedMessage> {
     let parsed = parse_mail(msg.body.as_bytes())?;
 
-    let body_text = extract_text_from_part(&parsed)
-        .unwrap_or_else(|| "".to_string())
+    let body_text = extract_text_from_part(&parsed, html_wrap_width)
+        .unwrap_or_default()
         .trim()
         .to_string();
 
     Ok(ParsedMessage {
         internal_date: msg.internal_date,
-        subject: get_header(&msg.headers, "Subject"),
-        from: get_header(&msg.headers, "From"),
+        subject: decoded_header(&msg.headers, "Subject"),
+        from: decoded_header(&msg.headers, "From"),
         body_text,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails the fetch of any batch containing `drop_at` until reconnected.
+    struct FlakySource {
+        drop_at: u32,
+        connected: bool,
+        reconnects: u32,
+    }
+
+    impl MessageSource for FlakySource {
+        fn fetch_uids(&mut self, uids: &[u32]) -> Result<Vec<MailMessage>> {
+            if !self.connected || (self.reconnects == 0 && uids.contains(&self.drop_at)) {
+                self.connected = false;
+                anyhow::bail!("connection reset by peer");
+            }
+            Ok(uids
+                .iter()
+                .map(|&uid| MailMessage {
+                    uid,
+                    internal_date: Utc::now(),
+                    headers: String::new(),
+                    body: String::new(),
+                })
+                .collect())
+        }
+
+        fn reconnect(&mut self) -> Result<()> {
+            self.connected = true;
+            self.reconnects += 1;
+            Ok(())
+        }
+    }
+
+    fn uids(messages: &[MailMessage]) -> Vec<u32> {
+        messages.iter().map(|m| m.uid).collect()
+    }
+
+    #[test]
+    fn retries_batch_after_dropped_connection() {
+        let mut source = FlakySource { drop_at: 4, connected: true, reconnects: 0 };
+
+        let messages = fetch_in_batches(&mut source, &[1, 2, 3, 4, 5], 2, 1).unwrap();
+
+        assert_eq!(uids(&messages), vec![1, 2, 3, 4, 5]);
+        assert_eq!(source.reconnects, 1);
+    }
+
+    #[test]
+    fn keeps_earlier_batches_when_retries_exhausted() {
+        let mut source = FlakySource { drop_at: 4, connected: true, reconnects: 0 };
+
+        let messages = fetch_in_batches(&mut source, &[1, 2, 3, 4, 5], 2, 0).unwrap();
+
+        assert_eq!(uids(&messages), vec![1, 2]);
+    }
+
+    #[test]
+    fn errors_when_first_batch_fails() {
+        let mut source = FlakySource { drop_at: 1, connected: true, reconnects: 0 };
+
+        assert!(fetch_in_batches(&mut source, &[1, 2, 3], 2, 0).is_err());
+    }
+
+    #[test]
+    fn decodes_base64_utf8_subject() {
+        assert_eq!(
+            decoded_header("Subject: =?UTF-8?B?WW91ciBvcmRlciBoYXMgc2hpcHBlZCDwn5OmIQ==?=\r\n", "Subject").as_deref(),
+            Some("Your order has shipped 📦!")
+        );
+    }
+
+    #[test]
+    fn decodes_quoted_printable_latin1_subject() {
+        assert_eq!(
+            decoded_header("Subject: =?ISO-8859-1?Q?Votre_colis_a_=E9t=E9_exp=E9di=E9?=\r\n", "Subject").as_deref(),
+            Some("Votre colis a été expédié")
+        );
+    }
+
+    #[test]
+    fn joins_adjacent_encoded_words_and_keeps_plain_text() {
+        let headers = "From: =?UTF-8?Q?Caf=C3=A9?= <shop@example.com>\r\n\
+                       Subject: Re: =?UTF-8?Q?Caf=C3=A9?=\r\n =?UTF-8?Q?_order?= shipped\r\n";
+
+        assert_eq!(decoded_header(headers, "subject").as_deref(), Some("Re: Café order shipped"));
+        assert_eq!(decoded_header(headers, "From").as_deref(), Some("Café <shop@example.com>"));
+        assert_eq!(decoded_header("Subject: No encoding here\r\n", "Subject").as_deref(), Some("No encoding here"));
+        assert_eq!(decoded_header(headers, "Date"), None);
+    }
+
+    #[test]
+    fn unfolds_continued_header() {
+        let headers = "From: UPS <mcinfo@ups.com>\r\n\
+                       Subject: Your UPS package is arriving\r\n\
+                       \ttomorrow by end of day\r\n\
+                       To: me@example.com\r\n";
+
+        assert_eq!(
+            get_header(headers, "Subject").as_deref(),
+            Some("Your UPS package is arriving tomorrow by end of day")
+        );
+        assert_eq!(get_header(headers, "to").as_deref(), Some("me@example.com"));
+    }
+
+    #[test]
+    fn requires_exact_header_name() {
+        let headers = "X-Subject-Foo: wrong\r\nSubjects: also wrong\r\nSubject: right\r\n";
+
+        assert_eq!(get_header(headers, "Subject").as_deref(), Some("right"));
+        assert_eq!(get_header("X-Subject-Foo: wrong\r\n", "Subject"), None);
+    }
+
+    #[test]
+    fn falls_back_to_date_header_without_internal_date() {
+        let headers = "Subject: Shipped\r\nDate: Tue, 3 Mar 2026 10:00:00 -0500\r\n";
+        let expected = DateTime::parse_from_rfc3339("2026-03-03T15:00:00Z").unwrap().with_timezone(&Utc);
+
+        assert_eq!(message_date(None, headers, true), Some(expected));
+        assert_eq!(message_date(None, headers, false), None);
+        assert_eq!(message_date(None, "Subject: Shipped\r\n", true), None);
+
+        let internal = Utc::now();
+        assert_eq!(message_date(Some(internal), headers, true), Some(internal));
+    }
+
+    fn parsed_body(body: &str) -> String {
+        let msg = MailMessage { uid: 1, internal_date: Utc::now(), headers: String::new(), body: body.into() };
+        parse_message(&msg, 1000).unwrap().body_text
+    }
+
+    #[test]
+    fn decodes_quoted_printable_and_base64_bodies() {
+        let quoted_printable = "Content-Type: text/plain; charset=utf-8\r\n\
+                                Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+                                Tracking number =3D 1Z5R8939035=\r\n7567127";
+        assert_eq!(parsed_body(quoted_printable), "Tracking number = 1Z5R89390357567127");
+
+        let base64 = "Content-Type: text/plain\r\nContent-Transfer-Encoding: base64\r\n\r\n\
+                      VHJhY2tpbmcgbnVtYmVyOiAxWjVSODkzOTAzNTc1NjcxMjc=";
+        assert_eq!(parsed_body(base64), "Tracking number: 1Z5R89390357567127");
+    }
+
+    #[test]
+    fn prefers_plain_text_alternative_and_falls_back_to_html() {
+        let alternative = |plain: &str| {
+            format!(
+                "Content-Type: multipart/alternative; boundary=b\r\n\r\n\
+                 --b\r\nContent-Type: text/html\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n\
+                 <p style=3D\"x\">HTML 986578788855</p>\r\n\
+                 --b\r\nContent-Type: text/plain\r\n\r\n{plain}\r\n\
+                 --b--\r\n"
+            )
+        };
+
+        assert_eq!(parsed_body(&alternative("Plain 1Z5R89390357567127")), "Plain 1Z5R89390357567127");
+        assert_eq!(parsed_body(&alternative("")), "HTML 986578788855");
+    }
+
+    #[test]
+    fn wide_html_wrapping_keeps_tracking_numbers_in_table_cells_whole() {
+        let html = "Content-Type: text/html\r\n\r\n\
+                    <table><tr><td>Order</td><td>Carrier</td><td>Shipping method</td><td>Tracking number</td><td>Estimated delivery</td></tr>\
+                    <tr><td>#112-4456789-1234567</td><td>UPS</td><td>UPS Ground Residential</td><td>1Z5R89390357567127</td><td>Wednesday, March 4</td></tr></table>";
+        let msg = MailMessage { uid: 1, internal_date: Utc::now(), headers: String::new(), body: html.into() };
+
+        // At 80 columns the tracking number cell wraps mid-number
+        let narrow = parse_message(&msg, 80).unwrap().body_text;
+        assert!(!narrow.contains("1Z5R89390357567127"), "{narrow}");
+
+        let wide = parse_message(&msg, 1000).unwrap().body_text;
+        let results = crate::extractors::extract_tracking_numbers(&wide);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tracking_number, "1Z5R89390357567127");
+    }
+
+    #[test]
+    fn builds_search_criteria() {
+        assert_eq!(uid_range(0), "UID 1:*");
+        assert_eq!(uid_range(4127), "UID 4128:*");
+        assert_eq!(since_query(NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()), "SINCE 2-Mar-2026");
+    }
+
+    #[test]
+    fn drops_already_seen_uid_matched_by_open_range() {
+        assert_eq!(unseen_uids([4127], 4127), Vec::<u32>::new());
+        assert_eq!(unseen_uids([4130, 4127, 4128], 4127), [4128, 4130]);
+    }
+
+    #[test]
+    fn formats_xoauth2_initial_response() {
+        use imap::Authenticator;
+
+        let authenticator = XOAuth2 {
+            user: "me@gmail.com".into(),
+            access_token: "ya29.token".into(),
+        };
+
+        assert_eq!(authenticator.process(b""), "user=me@gmail.com\x01auth=Bearer ya29.token\x01\x01");
+    }
+}
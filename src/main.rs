@@ -1,31 +1,39 @@
+mod barcode;
 mod config;
 mod courier;
 mod db;
 mod email_poller;
 mod extractors;
 mod imap_client;
+mod maintenance;
+mod metrics;
+mod notify;
+mod redact;
+mod shutdown;
 mod status_poller;
+#[cfg(test)]
+mod test_support;
 mod util;
 mod web;
 
-use config::{load as config_load, validate as config_validate};
+use config::{Config, load as config_load, validate as config_validate};
+use db::Database;
 use std::{sync::{
     Arc,
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
 }};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 fn main() {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .init();
+    let command = std::env::args().nth(1);
 
-    info!("trackage starting");
+    // Load the config before logging starts so it can choose the log format;
+    // a config that fails to load is reported in the default format
+    let config = config_load();
+    init_logging(config.as_ref().map_or(config::LogFormat::default(), |config| config.logging.format));
 
-    let config = match config_load() {
+    let config = match config {
         Ok(config) => config,
         Err(err) => {
             error!("Failed to load configuration: {err}");
@@ -34,6 +42,46 @@ fn main() {
         }
     };
 
+    match command.as_deref() {
+        None => run(config),
+        Some("config") => print!("{}", config::dump(&config, &config::sources())),
+        Some("test-notify") => test_notify(&config),
+        Some("check-db") => check_db(&config, std::env::args().nth(2).as_deref() == Some("--vacuum")),
+        Some("add") => match std::env::args().nth(2) {
+            Some(tracking_number) => add_package(&config, &tracking_number),
+            None => usage_error("add requires a tracking number"),
+        },
+        Some("list") => list_packages(&config),
+        Some("rm") => match std::env::args().nth(2).map(|id| id.parse::<i64>()) {
+            Some(Ok(id)) => remove_package(&config, id),
+            _ => usage_error("rm requires a numeric package id"),
+        },
+        Some(other) => usage_error(&format!("Unknown command: {other}")),
+    }
+}
+
+fn init_logging(format: config::LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    match format {
+        config::LogFormat::Text => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        config::LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_env_filter(filter)
+            .init(),
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    error!("{message}");
+    error!("Usage: trackage [config | test-notify | check-db [--vacuum] | add <TRACKING_NUMBER> | list | rm <ID>]");
+    std::process::exit(2);
+}
+
+/// Run the daemon: email poller, status poller, and optional web server.
+fn run(config: Config) {
+    info!("trackage starting");
+
     if let Err(err) = config_validate(&config) {
         error!("Configuration error: {err}");
         std::process::exit(1);
@@ -41,10 +89,22 @@ fn main() {
 
     info!(config = ?config.sanitized_for_log(), "Effective configuration");
 
-    let db_path = config.database.path.clone();
-    let web_config = config.web;
+    if config.allow_short_intervals {
+        warn!(
+            minimum = config::MIN_CHECK_INTERVAL_SECONDS,
+            "allow_short_intervals is set; short check intervals may get you rate limited or banned"
+        );
+    }
+
+    redact::set_enabled(config.logging.redact);
+    if !config.logging.redact {
+        info!("Log redaction disabled; courier payloads will be logged verbatim");
+    }
+
+    let db_config = config.database.clone();
+    let web_config = config.web.clone();
 
-    let email_db = match db::SqliteDatabase::open(&config.database.path) {
+    let mut email_db = match db::SqliteDatabase::from_config(&config.database) {
         Ok(db) => db,
         Err(err) => {
             error!(error = %err, "Failed to open database");
@@ -52,7 +112,11 @@ fn main() {
         }
     };
 
-    let status_db = match db::SqliteDatabase::open(&config.database.path) {
+    if let Err(err) = email_db.normalize_couriers() {
+        error!(error = %err, "Failed to normalize stored courier names");
+    }
+
+    let status_db = match db::SqliteDatabase::from_config(&config.database) {
         Ok(db) => db,
         Err(err) => {
             error!(error = %err, "Failed to open status poller database connection");
@@ -69,80 +133,375 @@ fn main() {
     })
     .expect("Error setting Ctrl-C handler");
 
-    let mut router = courier::CourierRouter::new();
-    if let Some(ref fedex_config) = config.courier.fedex {
-        info!("FedEx courier client enabled");
-        router.register(&courier::CourierCode::FedEx, Box::new(courier::fedex::FedexClient::new(fedex_config)));
-    }
-    if let Some(ref ups_config) = config.courier.ups {
-        info!("UPS courier client enabled (API)");
-        router.register(&courier::CourierCode::UPS, Box::new(courier::ups::UpsClient::new(ups_config)));
-    } else {
-        info!("UPS courier client enabled (web fallback)");
-        router.register(&courier::CourierCode::UPS, Box::new(courier::ups_web::UpsWebClient::new()));
-    }
-    if let Some(ref usps_config) = config.courier.usps {
-        info!("USPS courier client enabled");
-        router.register(&courier::CourierCode::USPS, Box::new(courier::usps::UspsClient::new(usps_config)));
-    }
+    let courier = Arc::new(courier::ReloadableCourier::new(
+        courier::CourierRouter::from_config(&config.courier).with_shutdown(Arc::clone(&running)),
+    ));
+    let router: Arc<dyn courier::CourierClient> = courier.clone();
+    let notifiers = Arc::new(notify::NotifierSet::from_pointee(notify::build_notifiers(&config)));
+    let metrics = Arc::new(metrics::Metrics::default());
 
     let email_poller = email_poller::EmailPoller::new(
-        config.email,
+        config.email.clone(),
         Box::new(email_db),
+        configured_couriers(&config.courier),
         Arc::clone(&running),
-    );
+    )
+    .with_metrics(Arc::clone(&metrics));
+    let email_interval = email_poller.interval_handle();
     let email_handle = std::thread::Builder::new()
         .name("email-poller".into())
         .spawn(move || email_poller.run())
         .expect("Failed to spawn email poller thread");
 
     let status_poller = status_poller::StatusPoller::new(
-        config.status,
+        config.status.clone(),
+        config.notify.clone(),
         Box::new(status_db),
-        Box::new(router),
+        Arc::clone(&router),
+        Arc::clone(&notifiers),
         Arc::clone(&running),
-    );
+    )
+    .with_metrics(Arc::clone(&metrics));
+    let status_interval = status_poller.interval_handle();
+    let poller_handle = status_poller.handle();
     let status_handle = std::thread::Builder::new()
         .name("status-poller".into())
         .spawn(move || status_poller.run())
         .expect("Failed to spawn status poller thread");
 
+    let maintenance_handle = if config.maintenance.enabled {
+        let maintenance_db = match db::SqliteDatabase::from_config(&config.database) {
+            Ok(db) => db,
+            Err(err) => {
+                error!(error = %err, "Failed to open maintenance database connection");
+                std::process::exit(1);
+            }
+        };
+        let task = maintenance::Maintenance::new(
+            config.maintenance.clone(),
+            Box::new(maintenance_db),
+            Arc::clone(&running),
+        );
+        Some(
+            std::thread::Builder::new()
+                .name("maintenance".into())
+                .spawn(move || task.run())
+                .expect("Failed to spawn maintenance thread"),
+        )
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    spawn_reload_handler(config, ReloadTargets {
+        running: Arc::clone(&running),
+        courier,
+        notifiers,
+        email_interval,
+        status_interval,
+    });
+
     let web_handle = if web_config.enabled {
+        let web_db_config = db_config.clone();
         let web_running = Arc::clone(&running);
-        let web_db_path = db_path.clone();
-        let port = web_config.port;
         Some(
             std::thread::Builder::new()
                 .name("web-server".into())
-                .spawn(move || web::start(web_db_path, port, web_running))
+                .spawn(move || web::start(web_db_config, web_config, router, poller_handle, metrics, web_running))
                 .expect("Failed to spawn web server thread"),
         )
     } else {
         None
     };
 
-    let mut exit_code = 0;
+    let mut workers = vec![("email-poller", email_handle), ("status-poller", status_handle)];
+    workers.extend(web_handle.map(|handle| ("web-server", handle)));
+    workers.extend(maintenance_handle.map(|handle| ("maintenance", handle)));
+    let clean = shutdown::wait_for_workers(&running, workers, shutdown::GRACE_PERIOD);
 
-    if let Err(err) = email_handle.join() {
-        error!("Email poller thread panicked: {:?}", err);
-        exit_code = 1;
+    match db::SqliteDatabase::from_config(&db_config) {
+        Ok(mut db) => {
+            shutdown::checkpoint(&mut db);
+        }
+        Err(err) => warn!(error = %err, "Failed to open database to checkpoint on shutdown"),
     }
 
-    if let Err(err) = status_handle.join() {
-        error!("Status poller thread panicked: {:?}", err);
-        exit_code = 1;
+    if clean {
+        info!("trackage stopped");
+    } else {
+        std::process::exit(1);
     }
+}
 
-    if let Some(handle) = web_handle {
-        if let Err(err) = handle.join() {
-            error!("Web server thread panicked: {:?}", err);
-            exit_code = 1;
+/// Couriers with API credentials configured. The UPS web fallback doesn't
+/// count, since it is always available.
+fn configured_couriers(config: &config::CourierConfig) -> Vec<courier::CourierCode> {
+    let mut couriers = Vec::new();
+    if config.fedex.is_some() {
+        couriers.push(courier::CourierCode::FedEx);
+    }
+    if config.ups.is_some() && config.ups_client != config::UpsClientKind::Web {
+        couriers.push(courier::CourierCode::UPS);
+    }
+    if config.usps.is_some() {
+        couriers.push(courier::CourierCode::USPS);
+    }
+    if config.canada_post.is_some() {
+        couriers.push(courier::CourierCode::CanadaPost);
+    }
+    couriers
+}
+
+/// Live state that a SIGHUP config reload swaps out.
+#[cfg(unix)]
+struct ReloadTargets {
+    running: Arc<AtomicBool>,
+    courier: Arc<courier::ReloadableCourier>,
+    notifiers: Arc<notify::NotifierSet>,
+    email_interval: Arc<AtomicU64>,
+    status_interval: Arc<AtomicU64>,
+}
+
+/// Re-read the configuration on SIGHUP. Courier clients, notifiers, and poll
+/// intervals are swapped in place; polls already in flight finish with the old
+/// clients. Other changed settings only take effect after a restart.
+#[cfg(unix)]
+fn spawn_reload_handler(mut current: Config, targets: ReloadTargets) {
+    let mut signals = match signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP]) {
+        Ok(signals) => signals,
+        Err(err) => {
+            warn!(error = %err, "Failed to install SIGHUP handler; config reload disabled");
+            return;
         }
+    };
+
+    std::thread::Builder::new()
+        .name("config-reload".into())
+        .spawn(move || {
+            for _ in signals.forever() {
+                info!("SIGHUP received, reloading configuration");
+                let config = match config_load() {
+                    Ok(config) => config,
+                    Err(err) => {
+                        error!("Config reload failed, keeping current configuration: {err}");
+                        continue;
+                    }
+                };
+                if let Err(err) = config_validate(&config) {
+                    error!("Reloaded configuration is invalid, keeping current configuration: {err}");
+                    continue;
+                }
+
+                targets.courier.replace(
+                    courier::CourierRouter::from_config(&config.courier).with_shutdown(Arc::clone(&targets.running)),
+                );
+                targets.notifiers.store(Arc::new(notify::build_notifiers(&config)));
+                targets.email_interval.store(config.email.check_interval_seconds, Ordering::SeqCst);
+                targets.status_interval.store(config.status.check_interval_seconds, Ordering::SeqCst);
+
+                for section in restart_required_changes(&current, &config) {
+                    warn!(section, "Configuration changed; restart trackage to apply");
+                }
+                info!("Configuration reloaded");
+                current = config;
+            }
+        })
+        .expect("Failed to spawn config reload thread");
+}
+
+/// Config sections that changed between `old` and `new` in ways a reload
+/// can't apply. Poll intervals and courier credentials are hot-reloaded.
+#[cfg(unix)]
+fn restart_required_changes(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut old_email = old.email.clone();
+    old_email.check_interval_seconds = new.email.check_interval_seconds;
+    let mut old_status = old.status.clone();
+    old_status.check_interval_seconds = new.status.check_interval_seconds;
+
+    let mut changed = Vec::new();
+    if old_email != new.email {
+        changed.push("email");
+    }
+    if old.database != new.database {
+        changed.push("database");
+    }
+    if old_status != new.status {
+        changed.push("status");
     }
+    if old.web != new.web {
+        changed.push("web");
+    }
+    if old.logging != new.logging {
+        changed.push("logging");
+    }
+    if old.notify != new.notify {
+        changed.push("notify");
+    }
+    if old.maintenance != new.maintenance {
+        changed.push("maintenance");
+    }
+    changed
+}
 
-    if exit_code == 0 {
-        info!("trackage stopped");
-    } else {
-        std::process::exit(exit_code);
+/// Send a synthetic delivery through every configured notifier and report the
+/// result per channel. Exits non-zero if any channel fails.
+fn test_notify(config: &Config) {
+    let notifiers = notify::build_notifiers(config);
+    if notifiers.is_empty() {
+        println!("No notifiers configured");
+        return;
+    }
+
+    let mut failed = false;
+    for (name, failure) in notify::self_test(&notifiers) {
+        match failure {
+            None => println!("{name}: ok"),
+            Some(err) => {
+                println!("{name}: FAILED ({err})");
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// Run SQLite's integrity check against the configured database and print the
+/// result, optionally reclaiming free space afterwards. Exits non-zero if the
+/// database is corrupt or can't be checked.
+fn check_db(config: &Config, vacuum: bool) {
+    let mut db = open_db_or_exit(config);
+
+    let problems = match db.integrity_check() {
+        Ok(rows) if rows == ["ok"] => Vec::new(),
+        Ok(rows) => rows,
+        Err(err) => {
+            println!("Integrity check failed to run: {err:#}");
+            std::process::exit(1);
+        }
+    };
+
+    if !problems.is_empty() {
+        println!("{}: CORRUPT", config.database.path);
+        for problem in &problems {
+            println!("  {problem}");
+        }
+        std::process::exit(1);
+    }
+    println!("{}: ok", config.database.path);
+
+    if vacuum {
+        match db.vacuum() {
+            Ok(()) => println!("Vacuum complete"),
+            Err(err) => {
+                println!("Vacuum failed: {err:#}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn open_db_or_exit(config: &Config) -> db::SqliteDatabase {
+    match db::SqliteDatabase::from_config(&config.database) {
+        Ok(db) => db,
+        Err(err) => {
+            println!("Failed to open database {}: {err:#}", config.database.path);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Validate a tracking number and store it as a manually added package.
+/// Exits non-zero if the number isn't recognized or can't be stored.
+fn add_package(config: &Config, tracking_number: &str) {
+    let cleaned: String = tracking_number
+        .to_uppercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let Some(result) = extractors::track_number(&cleaned) else {
+        println!("{cleaned}: not a recognized tracking number");
+        std::process::exit(1);
+    };
+
+    let courier = result
+        .courier
+        .parse::<courier::CourierCode>()
+        .map_or_else(|_| result.courier.clone(), |code| code.to_string());
+    let new_package = db::NewPackage {
+        tracking_number: result.tracking_number.clone(),
+        courier: courier.clone(),
+        service: result.service.clone(),
+        tracking_url: result.tracking_url,
+        source_email_uid: 0,
+        source_email_subject: None,
+        source_email_from: None,
+        source_email_date: chrono::Utc::now(),
+        source_confidence: db::SourceConfidence::Manual,
+        destination_zip: None,
+        description: None,
+    };
+
+    let mut db = open_db_or_exit(config);
+    match db.insert_package(&new_package) {
+        Ok(true) => println!("Added {}: {courier} {}", result.tracking_number, result.service),
+        Ok(false) => println!("{} is already being tracked", result.tracking_number),
+        Err(err) => {
+            println!("Failed to add {}: {err:#}", result.tracking_number);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print the packages still being polled as a table.
+fn list_packages(config: &Config) {
+    let packages = match open_db_or_exit(config).get_active_packages() {
+        Ok(packages) => packages,
+        Err(err) => {
+            println!("Failed to list packages: {err:#}");
+            std::process::exit(1);
+        }
+    };
+    if packages.is_empty() {
+        println!("No active packages");
+        return;
+    }
+
+    let header = ["ID", "TRACKING NUMBER", "COURIER", "SERVICE", "STATUS"];
+    let rows: Vec<[String; 5]> = packages
+        .into_iter()
+        .map(|p| [p.id.to_string(), p.tracking_number, p.courier, p.service, p.status.to_string()])
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: [&str; 5]| {
+        let line: Vec<String> = cells.iter().zip(widths).map(|(cell, width)| format!("{cell:<width$}")).collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+    print_row(header);
+    for row in &rows {
+        print_row(row.each_ref().map(String::as_str));
+    }
+}
+
+/// Soft-delete a package by id. Exits non-zero if no such package exists.
+fn remove_package(config: &Config, id: i64) {
+    match open_db_or_exit(config).delete_package(id) {
+        Ok(true) => println!("Removed package {id}"),
+        Ok(false) => {
+            println!("No package with id {id}");
+            std::process::exit(1);
+        }
+        Err(err) => {
+            println!("Failed to remove package {id}: {err:#}");
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,181 @@
+use crate::config::MaintenanceConfig;
+use crate::db::Database;
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// What a maintenance run did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    pub archived: usize,
+    pub pruned: usize,
+    pub checkpointed: bool,
+    pub vacuumed: bool,
+}
+
+pub struct Maintenance {
+    config: MaintenanceConfig,
+    db: Box<dyn Database>,
+    running: Arc<AtomicBool>,
+}
+
+impl Maintenance {
+    pub fn new(config: MaintenanceConfig, db: Box<dyn Database>, running: Arc<AtomicBool>) -> Self {
+        Self { config, db, running }
+    }
+
+    /// Run maintenance now and then every `interval_hours` until the shutdown
+    /// signal fires.
+    pub fn run(mut self) {
+        info!(interval_hours = self.config.interval_hours, "Maintenance task starting");
+
+        while self.running.load(Ordering::SeqCst) {
+            self.run_once(Utc::now());
+            self.sleep();
+        }
+
+        info!("Maintenance task shutting down");
+    }
+
+    /// Run every enabled step once. A failing step is logged and the rest
+    /// still run.
+    fn run_once(&mut self, now: DateTime<Utc>) -> MaintenanceReport {
+        let mut report = MaintenanceReport::default();
+        let limit = self.config.max_rows_per_run;
+
+        if let Some(days) = self.config.archive_delivered_after_days {
+            let cutoff = now - chrono::Duration::days(i64::from(days));
+            match self.db.archive_delivered_before(cutoff, limit) {
+                Ok(archived) => report.archived = archived,
+                Err(err) => error!(error = %err, "Failed to archive delivered packages"),
+            }
+        }
+
+        if let Some(keep) = self.config.max_history_per_package {
+            match self.db.prune_status_history(keep, limit) {
+                Ok(pruned) => report.pruned = pruned,
+                Err(err) => error!(error = %err, "Failed to prune status history"),
+            }
+        }
+
+        if self.config.checkpoint_wal {
+            match self.db.checkpoint_wal() {
+                Ok(()) => report.checkpointed = true,
+                Err(err) => error!(error = %err, "Failed to checkpoint WAL"),
+            }
+        }
+
+        if self.config.vacuum {
+            match self.db.vacuum() {
+                Ok(()) => report.vacuumed = true,
+                Err(err) => error!(error = %err, "Failed to vacuum database"),
+            }
+        }
+
+        info!(
+            archived = report.archived,
+            pruned = report.pruned,
+            checkpointed = report.checkpointed,
+            vacuumed = report.vacuumed,
+            "Maintenance complete"
+        );
+        report
+    }
+
+    fn sleep(&self) {
+        let mut slept = 0;
+        while slept < self.config.interval_hours * 3600 && self.running.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_secs(1));
+            slept += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{PackageStatus, SqliteDatabase, StatusDetails};
+    use crate::test_support::db_with;
+
+    /// A database with one package delivered 30 days ago and one in transit,
+    /// each with three status entries.
+    fn seeded_db() -> SqliteDatabase {
+        let mut db = db_with(&["1ZOLD", "1ZNEW"]);
+
+        let long_ago = (Utc::now() - chrono::Duration::days(30)).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        let ids: Vec<i64> = db.get_all_packages_with_status().unwrap().iter().map(|p| p.id).collect();
+        for (id, last) in ids.iter().zip([PackageStatus::InTransit, PackageStatus::Delivered]) {
+            for status in [PackageStatus::Waiting, PackageStatus::InTransit, last] {
+                let details = StatusDetails { checked_at: Some(&long_ago), ..Default::default() };
+                db.insert_package_status(*id, &status, &details).unwrap();
+            }
+        }
+        db
+    }
+
+    fn maintenance(config: MaintenanceConfig) -> Maintenance {
+        Maintenance::new(config, Box::new(seeded_db()), Arc::new(AtomicBool::new(true)))
+    }
+
+    #[test]
+    fn does_nothing_by_default() {
+        let mut task = maintenance(MaintenanceConfig::default());
+
+        assert_eq!(task.run_once(Utc::now()), MaintenanceReport::default());
+        assert_eq!(task.db.get_all_packages_with_status().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn archives_only_when_configured() {
+        let mut task = maintenance(MaintenanceConfig {
+            archive_delivered_after_days: Some(14),
+            ..MaintenanceConfig::default()
+        });
+
+        let report = task.run_once(Utc::now());
+
+        assert_eq!(report.archived, 1);
+        let remaining = task.db.get_all_packages_with_status().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].status, "in_transit");
+    }
+
+    #[test]
+    fn prunes_history_only_when_configured_and_bounded() {
+        let mut task = maintenance(MaintenanceConfig {
+            max_history_per_package: Some(1),
+            max_rows_per_run: 1,
+            ..MaintenanceConfig::default()
+        });
+
+        // Two of the delivered package's entries are beyond the cap, but only
+        // one may go per run; the package in transit is still polled, so it
+        // keeps its full history
+        assert_eq!(task.run_once(Utc::now()).pruned, 1);
+        assert_eq!(task.run_once(Utc::now()).pruned, 1);
+        assert_eq!(task.run_once(Utc::now()).pruned, 0);
+        for package in task.db.get_all_packages_with_status().unwrap() {
+            let expected = if package.status == "delivered" { 1 } else { 3 };
+            assert_eq!(task.db.get_package_status_history(package.id).unwrap().len(), expected);
+        }
+    }
+
+    #[test]
+    fn checkpoints_and_vacuums_only_when_configured() {
+        let mut task = maintenance(MaintenanceConfig {
+            checkpoint_wal: true,
+            vacuum: true,
+            ..MaintenanceConfig::default()
+        });
+
+        let report = task.run_once(Utc::now());
+
+        assert!(report.checkpointed);
+        assert!(report.vacuumed);
+        assert_eq!(report.archived, 0);
+        assert_eq!(report.pruned, 0);
+    }
+}
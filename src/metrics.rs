@@ -0,0 +1,124 @@
+//! Operational counters shared by the pollers, rendered by `GET /metrics` in
+//! the Prometheus text exposition format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How a courier status check turned out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CourierOutcome {
+    Success,
+    Error,
+    NotFound,
+}
+
+impl CourierOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Error => "error",
+            Self::NotFound => "not_found",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// Emails parsed by the email poller.
+    pub emails_processed: AtomicU64,
+    /// Tracking numbers found in those emails.
+    pub tracking_numbers_extracted: AtomicU64,
+    /// Packages newly stored from emails.
+    pub packages_inserted: AtomicU64,
+    courier_requests: Mutex<BTreeMap<(String, CourierOutcome), u64>>,
+}
+
+impl Metrics {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add(counter: &AtomicU64, count: usize) {
+        counter.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_courier_request(&self, courier: &str, outcome: CourierOutcome) {
+        *self
+            .courier_requests
+            .lock()
+            .unwrap()
+            .entry((courier.to_string(), outcome))
+            .or_default() += 1;
+    }
+
+    /// The metrics in Prometheus text format. `active_packages` is read from
+    /// the database at scrape time.
+    pub fn render(&self, active_packages: usize) -> String {
+        let mut out = String::new();
+
+        let counters = [
+            ("trackage_emails_processed_total", "Emails parsed by the email poller.", &self.emails_processed),
+            (
+                "trackage_tracking_numbers_extracted_total",
+                "Tracking numbers found in emails.",
+                &self.tracking_numbers_extracted,
+            ),
+            ("trackage_packages_inserted_total", "Packages newly stored from emails.", &self.packages_inserted),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+        }
+
+        let name = "trackage_courier_requests_total";
+        let _ = writeln!(out, "# HELP {name} Courier status checks by courier and outcome.");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for ((courier, outcome), count) in self.courier_requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{name}{{courier=\"{}\",outcome=\"{}\"}} {count}",
+                escape_label(courier),
+                outcome.as_str()
+            );
+        }
+
+        let name = "trackage_active_packages";
+        let _ = writeln!(out, "# HELP {name} Packages not yet delivered or marked not found.");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(out, "{name} {active_packages}");
+
+        out
+    }
+}
+
+/// Escape a label value for the text format.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_gauge_in_prometheus_format() {
+        let metrics = Metrics::default();
+        Metrics::increment(&metrics.emails_processed);
+        Metrics::add(&metrics.tracking_numbers_extracted, 2);
+        metrics.record_courier_request("ups", CourierOutcome::Success);
+        metrics.record_courier_request("ups", CourierOutcome::Success);
+        metrics.record_courier_request("fedex", CourierOutcome::NotFound);
+
+        let text = metrics.render(3);
+
+        assert!(text.contains("# TYPE trackage_emails_processed_total counter\ntrackage_emails_processed_total 1\n"));
+        assert!(text.contains("trackage_tracking_numbers_extracted_total 2\n"));
+        assert!(text.contains("trackage_packages_inserted_total 0\n"));
+        assert!(text.contains("trackage_courier_requests_total{courier=\"ups\",outcome=\"success\"} 2\n"));
+        assert!(text.contains("trackage_courier_requests_total{courier=\"fedex\",outcome=\"not_found\"} 1\n"));
+        assert!(text.contains("# TYPE trackage_active_packages gauge\ntrackage_active_packages 3\n"));
+    }
+}
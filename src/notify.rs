@@ -0,0 +1,213 @@
+pub mod smtp;
+pub mod webhook;
+
+use crate::config::Config;
+use crate::db::PackageStatus;
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use tracing::{error, info, warn};
+
+/// A package moving from one status to another.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct StatusChangeEvent {
+    pub tracking_number: String,
+    pub courier: String,
+    pub old_status: PackageStatus,
+    pub new_status: PackageStatus,
+    pub location: Option<String>,
+    pub checked_at: Option<String>,
+}
+
+impl StatusChangeEvent {
+    /// One-line human summary, e.g. "UPS 1Z... is now delivered (Austin, TX)".
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} {} is now {}",
+            self.courier, self.tracking_number, self.new_status
+        );
+        if let Some(location) = &self.location {
+            summary.push_str(&format!(" ({location})"));
+        }
+        summary
+    }
+}
+
+/// A package whose ETA is approaching, sent once per ETA value.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ArrivingSoonEvent {
+    pub tracking_number: String,
+    pub courier: String,
+    pub estimated_arrival_date: String,
+    pub days_away: u32,
+}
+
+impl ArrivingSoonEvent {
+    /// One-line human summary, e.g. "UPS 1Z... is arriving tomorrow (2026-03-02)".
+    pub fn summary(&self) -> String {
+        let when = match self.days_away {
+            0 => "today".to_string(),
+            1 => "tomorrow".to_string(),
+            n => format!("in {n} days"),
+        };
+        format!(
+            "{} {} is arriving {when} ({})",
+            self.courier, self.tracking_number, self.estimated_arrival_date
+        )
+    }
+}
+
+/// Anything a notifier can be asked to deliver.
+#[derive(Debug, Clone)]
+pub enum NotifyEvent {
+    StatusChange(StatusChangeEvent),
+    ArrivingSoon(ArrivingSoonEvent),
+    /// Status changes consolidated into one notification after downtime.
+    Batch(Vec<StatusChangeEvent>),
+}
+
+impl NotifyEvent {
+    pub fn summary(&self) -> String {
+        match self {
+            NotifyEvent::StatusChange(event) => event.summary(),
+            NotifyEvent::ArrivingSoon(event) => event.summary(),
+            NotifyEvent::Batch(events) => {
+                let lines: Vec<String> = events.iter().map(StatusChangeEvent::summary).collect();
+                format!("{} packages updated while trackage was offline: {}", events.len(), lines.join("; "))
+            }
+        }
+    }
+}
+
+/// A channel that notifications are delivered through.
+pub trait Notifier: Send + Sync {
+    /// Short channel name used in logs and self-test output.
+    fn name(&self) -> &str;
+
+    fn notify(&self, event: &NotifyEvent) -> Result<()>;
+}
+
+/// Deliver an event through every notifier, logging (not propagating) failures.
+pub fn dispatch(notifiers: &[Box<dyn Notifier>], event: &NotifyEvent) {
+    for notifier in notifiers {
+        match notifier.notify(event) {
+            Ok(()) => info!(notifier = notifier.name(), event = %event.summary(), "Notification sent"),
+            Err(err) => warn!(notifier = notifier.name(), error = %err, "Notification failed"),
+        }
+    }
+}
+
+/// The active notifiers, swappable at runtime when the config is reloaded.
+pub type NotifierSet = ArcSwap<Vec<Box<dyn Notifier>>>;
+
+/// Build every notifier that has been configured.
+pub fn build_notifiers(config: &Config) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(webhook) = &config.webhook {
+        notifiers.push(Box::new(webhook::WebhookNotifier::new(webhook)));
+    }
+    if let Some(smtp) = &config.smtp {
+        match smtp::SmtpNotifier::new(smtp, &config.email) {
+            Ok(notifier) => notifiers.push(Box::new(notifier)),
+            Err(err) => error!(error = %err, "Failed to set up SMTP notifier; delivery emails disabled"),
+        }
+    }
+    notifiers
+}
+
+/// A fake delivery used to exercise notifiers without a real package.
+pub fn synthetic_event() -> NotifyEvent {
+    NotifyEvent::StatusChange(StatusChangeEvent {
+        tracking_number: "TRACKAGE-TEST-0001".into(),
+        courier: "test".into(),
+        old_status: PackageStatus::InTransit,
+        new_status: PackageStatus::Delivered,
+        location: Some("Front Porch".into()),
+        checked_at: None,
+    })
+}
+
+/// Send the synthetic event through every notifier, returning each channel's
+/// name and the failure message, if any.
+pub fn self_test(notifiers: &[Box<dyn Notifier>]) -> Vec<(String, Option<String>)> {
+    let event = synthetic_event();
+    notifiers
+        .iter()
+        .map(|notifier| match notifier.notify(&event) {
+            Ok(()) => {
+                info!(notifier = notifier.name(), event = %event.summary(), "Test notification sent");
+                (notifier.name().to_string(), None)
+            }
+            Err(err) => {
+                error!(notifier = notifier.name(), error = %err, "Test notification failed");
+                (notifier.name().to_string(), Some(err.to_string()))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingNotifier {
+        received: Arc<Mutex<Vec<NotifyEvent>>>,
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn notify(&self, event: &NotifyEvent) -> Result<()> {
+            self.received.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    struct FailingNotifier;
+
+    impl Notifier for FailingNotifier {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn notify(&self, _event: &NotifyEvent) -> Result<()> {
+            anyhow::bail!("connection refused")
+        }
+    }
+
+    #[test]
+    fn self_test_delivers_synthetic_event() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let notifiers: Vec<Box<dyn Notifier>> = vec![
+            Box::new(RecordingNotifier { received: Arc::clone(&received) }),
+            Box::new(FailingNotifier),
+        ];
+
+        let results = self_test(&notifiers);
+
+        assert_eq!(results[0], ("recording".to_string(), None));
+        assert_eq!(results[1], ("failing".to_string(), Some("connection refused".to_string())));
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(matches!(
+            &received[0],
+            NotifyEvent::StatusChange(event) if event.new_status == PackageStatus::Delivered
+        ));
+    }
+
+    #[test]
+    fn arriving_soon_summary_describes_lead_time() {
+        let event = ArrivingSoonEvent {
+            tracking_number: "1ZAAA".into(),
+            courier: "UPS".into(),
+            estimated_arrival_date: "2026-03-02".into(),
+            days_away: 1,
+        };
+
+        assert_eq!(event.summary(), "UPS 1ZAAA is arriving tomorrow (2026-03-02)");
+    }
+}
@@ -0,0 +1,207 @@
+//! Emails a summary when a package is delivered.
+
+use super::{Notifier, NotifyEvent, StatusChangeEvent};
+use crate::config::{EmailConfig, SmtpConfig};
+use crate::db::PackageStatus;
+use anyhow::{Context, Result};
+use lettre::message::{Mailbox, Message, header::ContentType};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use std::time::Duration;
+
+/// Give up on an unresponsive SMTP server rather than stalling the poll.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers a built message. Split out from [`SmtpNotifier`] so it can be
+/// tested without a mail server.
+pub trait MailSender: Send + Sync {
+    fn send(&self, message: &Message) -> Result<()>;
+}
+
+impl MailSender for SmtpTransport {
+    fn send(&self, message: &Message) -> Result<()> {
+        Transport::send(self, message).context("SMTP send failed")?;
+        Ok(())
+    }
+}
+
+pub struct SmtpNotifier {
+    sender: Box<dyn MailSender>,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+impl SmtpNotifier {
+    /// Connect settings from `[smtp]`, with credentials and addresses
+    /// defaulting to the `[email]` login.
+    pub fn new(config: &SmtpConfig, email: &EmailConfig) -> Result<Self> {
+        let username = config.username.as_ref().or(email.username.as_ref()).context("smtp.username missing")?;
+        let password = config.password.as_ref().or(email.password.as_ref()).context("smtp.password missing")?;
+
+        let builder = if config.port == 465 {
+            SmtpTransport::relay(&config.server)
+        } else {
+            SmtpTransport::starttls_relay(&config.server)
+        }
+        .context("Failed to set up SMTP transport")?;
+        let transport = builder
+            .port(config.port)
+            .credentials(Credentials::new(username.clone(), password.clone()))
+            .timeout(Some(SMTP_TIMEOUT))
+            .build();
+
+        let (from, to) = addresses(config, email)?;
+        Ok(Self::with_sender(Box::new(transport), from, to))
+    }
+
+    pub fn with_sender(sender: Box<dyn MailSender>, from: Mailbox, to: Mailbox) -> Self {
+        Self { sender, from, to }
+    }
+
+    fn send_delivered(&self, event: &StatusChangeEvent) -> Result<()> {
+        self.send(format!("Delivered: {} {}", event.courier, event.tracking_number), delivery_details(event))
+    }
+
+    /// One email listing every delivery in a batch.
+    fn send_delivered_batch(&self, events: &[&StatusChangeEvent]) -> Result<()> {
+        match events {
+            [] => Ok(()),
+            [event] => self.send_delivered(event),
+            events => {
+                let body: Vec<String> = events.iter().map(|event| delivery_details(event)).collect();
+                self.send(format!("Delivered: {} packages", events.len()), body.join("\n"))
+            }
+        }
+    }
+
+    fn send(&self, subject: String, body: String) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .context("Failed to build delivery email")?;
+        self.sender.send(&message)
+    }
+}
+
+/// The email lines describing one delivery.
+fn delivery_details(event: &StatusChangeEvent) -> String {
+    let mut details = format!("Tracking number: {}\nCourier: {}\n", event.tracking_number, event.courier);
+    if let Some(location) = &event.location {
+        details.push_str(&format!("Delivered at: {location}\n"));
+    }
+    if let Some(checked_at) = &event.checked_at {
+        details.push_str(&format!("Scanned: {checked_at}\n"));
+    }
+    details
+}
+
+/// The sender and recipient, each defaulting to the IMAP username.
+pub fn addresses(config: &SmtpConfig, email: &EmailConfig) -> Result<(Mailbox, Mailbox)> {
+    let parse = |field: &str, value: Option<&String>| -> Result<Mailbox> {
+        let value = value.or(email.username.as_ref()).with_context(|| format!("smtp.{field} missing"))?;
+        value.parse().with_context(|| format!("smtp.{field} is not a valid address: {value}"))
+    };
+    Ok((parse("from", config.from.as_ref())?, parse("to", config.to.as_ref())?))
+}
+
+impl Notifier for SmtpNotifier {
+    fn name(&self) -> &str {
+        "smtp"
+    }
+
+    /// Only deliveries are emailed: one message per delivered package, or
+    /// one listing all the deliveries in a batch.
+    fn notify(&self, event: &NotifyEvent) -> Result<()> {
+        match event {
+            NotifyEvent::StatusChange(change) if change.new_status == PackageStatus::Delivered => {
+                self.send_delivered(change)
+            }
+            NotifyEvent::Batch(changes) => {
+                let delivered: Vec<_> =
+                    changes.iter().filter(|change| change.new_status == PackageStatus::Delivered).collect();
+                self.send_delivered_batch(&delivered)
+            }
+            NotifyEvent::StatusChange(_) | NotifyEvent::ArrivingSoon(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingSender {
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MailSender for RecordingSender {
+        fn send(&self, message: &Message) -> Result<()> {
+            self.sent.lock().unwrap().push(String::from_utf8(message.formatted()).unwrap());
+            Ok(())
+        }
+    }
+
+    fn change(new_status: PackageStatus) -> StatusChangeEvent {
+        change_for("1Z5R89390357567127", new_status)
+    }
+
+    fn change_for(tracking_number: &str, new_status: PackageStatus) -> StatusChangeEvent {
+        StatusChangeEvent {
+            tracking_number: tracking_number.into(),
+            courier: "ups".into(),
+            old_status: PackageStatus::InTransit,
+            new_status,
+            location: Some("Front Porch".into()),
+            checked_at: None,
+        }
+    }
+
+    fn notifier() -> (SmtpNotifier, Arc<Mutex<Vec<String>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let notifier = SmtpNotifier::with_sender(
+            Box::new(RecordingSender { sent: Arc::clone(&sent) }),
+            "trackage@example.com".parse().unwrap(),
+            "me@example.com".parse().unwrap(),
+        );
+        (notifier, sent)
+    }
+
+    #[test]
+    fn emails_a_summary_for_deliveries_only() {
+        let (notifier, sent) = notifier();
+
+        notifier.notify(&NotifyEvent::StatusChange(change(PackageStatus::InTransit))).unwrap();
+        notifier.notify(&NotifyEvent::StatusChange(change(PackageStatus::Delivered))).unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains("Subject: Delivered: ups 1Z5R89390357567127"), "{}", sent[0]);
+        assert!(sent[0].contains("To: me@example.com"), "{}", sent[0]);
+        assert!(sent[0].contains("Delivered at: Front Porch"), "{}", sent[0]);
+    }
+
+    #[test]
+    fn emails_one_summary_per_batch() {
+        let (notifier, sent) = notifier();
+
+        notifier
+            .notify(&NotifyEvent::Batch(vec![
+                change_for("1ZAAA", PackageStatus::Delivered),
+                change_for("1ZBBB", PackageStatus::InTransit),
+                change_for("1ZCCC", PackageStatus::Delivered),
+            ]))
+            .unwrap();
+        notifier.notify(&NotifyEvent::Batch(vec![change_for("1ZDDD", PackageStatus::InTransit)])).unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains("Subject: Delivered: 2 packages"), "{}", sent[0]);
+        assert!(sent[0].contains("Tracking number: 1ZAAA"), "{}", sent[0]);
+        assert!(sent[0].contains("Tracking number: 1ZCCC"), "{}", sent[0]);
+        assert!(!sent[0].contains("1ZBBB"), "{}", sent[0]);
+    }
+}
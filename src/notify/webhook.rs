@@ -0,0 +1,93 @@
+//! POSTs package status changes as JSON to a user-supplied URL, e.g. a
+//! home automation webhook.
+
+use super::{Notifier, NotifyEvent, StatusChangeEvent};
+use crate::config::WebhookConfig;
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+pub struct WebhookNotifier {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &WebhookConfig) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(config.timeout_seconds)))
+            .build()
+            .into();
+        Self { url: config.url.clone(), agent }
+    }
+
+    fn post(&self, body: Value, what: &str) -> Result<()> {
+        self.agent
+            .post(&self.url)
+            .send_json(body)
+            .with_context(|| format!("Webhook POST for {what} failed"))?;
+        Ok(())
+    }
+}
+
+fn payload(event: &StatusChangeEvent) -> Value {
+    json!({
+        "tracking_number": event.tracking_number,
+        "courier": event.courier,
+        "old_status": event.old_status.to_string(),
+        "new_status": event.new_status.to_string(),
+        "location": event.location,
+        "checked_at": event.checked_at,
+    })
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    /// One POST per status change; a batch after downtime is sent as one
+    /// POST of `{"changes": [...]}`. Arriving-soon events aren't status
+    /// changes and are skipped.
+    fn notify(&self, event: &NotifyEvent) -> Result<()> {
+        match event {
+            NotifyEvent::StatusChange(change) => self.post(payload(change), &change.tracking_number),
+            NotifyEvent::Batch(changes) => {
+                let changes: Vec<Value> = changes.iter().map(payload).collect();
+                self.post(json!({ "changes": changes }), &format!("{} changes", changes.len()))
+            }
+            NotifyEvent::ArrivingSoon(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::PackageStatus;
+    use crate::test_support::webhook_server;
+
+    fn change(tracking_number: &str) -> StatusChangeEvent {
+        StatusChangeEvent {
+            tracking_number: tracking_number.into(),
+            courier: "ups".into(),
+            old_status: PackageStatus::InTransit,
+            new_status: PackageStatus::Delivered,
+            location: None,
+            checked_at: None,
+        }
+    }
+
+    #[test]
+    fn posts_a_batch_as_one_request() {
+        let (url, received) = webhook_server();
+        let webhook = WebhookNotifier::new(&WebhookConfig { url, timeout_seconds: 5 });
+
+        webhook.notify(&NotifyEvent::Batch(vec![change("1ZAAA"), change("1ZBBB")])).unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let numbers: Vec<_> = received[0]["changes"].as_array().unwrap().iter().map(|c| &c["tracking_number"]).collect();
+        assert_eq!(numbers, ["1ZAAA", "1ZBBB"]);
+    }
+}
@@ -0,0 +1,124 @@
+//! Redaction of tracking numbers and personal details from logged courier
+//! payloads. Controlled globally by `logging.redact` (on by default).
+
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// JSON keys (matched case-insensitively as substrings) whose string values
+/// are treated as personal details.
+const PII_KEYS: &[&str] = &["name", "address", "street", "phone", "email", "signed", "signature"];
+
+const MASK: &str = "[redacted]";
+
+/// Number of trailing characters of a tracking number left visible.
+const VISIBLE_SUFFIX: usize = 4;
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Mask all but the last few characters of a tracking number.
+pub fn mask_tracking_number(tracking_number: &str) -> String {
+    let len = tracking_number.chars().count();
+    tracking_number
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i + VISIBLE_SUFFIX < len { '*' } else { c })
+        .collect()
+}
+
+/// A tracking number as it should appear in logs: masked unless redaction
+/// is off.
+pub fn tracking_number(tracking_number: &str) -> String {
+    if !enabled() {
+        return tracking_number.to_string();
+    }
+    mask_tracking_number(tracking_number)
+}
+
+/// Redact a logged request/response body. JSON bodies have personal details
+/// masked by key; any body has occurrences of `tracking_number` masked.
+pub fn body(text: &str, tracking_number: &str) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    redact_body(text, tracking_number)
+}
+
+fn redact_body(text: &str, tracking_number: &str) -> String {
+    let text = match serde_json::from_str::<Value>(text) {
+        Ok(mut json) => {
+            redact_json(&mut json);
+            json.to_string()
+        }
+        Err(_) => text.to_string(),
+    };
+
+    if tracking_number.is_empty() {
+        text
+    } else {
+        text.replace(tracking_number, &mask_tracking_number(tracking_number))
+    }
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if PII_KEYS.iter().any(|pii| key.contains(pii)) {
+                    mask_strings(child);
+                } else {
+                    redact_json(child);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+fn mask_strings(value: &mut Value) {
+    match value {
+        Value::String(s) if !s.is_empty() => *s = MASK.to_string(),
+        Value::Object(map) => map.values_mut().for_each(mask_strings),
+        Value::Array(items) => items.iter_mut().for_each(mask_strings),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_tracking_number_keeping_suffix() {
+        assert_eq!(mask_tracking_number("1Z5R89390357567127"), "**************7127");
+        assert_eq!(mask_tracking_number("123"), "123");
+    }
+
+    #[test]
+    fn redacts_tracking_number_and_pii_in_json_body() {
+        let body = r#"{"trackDetails":[{"trackingNumber":"1Z5R89390357567127","shipToAddress":{"streetAddress1":"1 Main St","city":"AUSTIN"},"receivedBy":{"signedByName":"J SMITH"}}]}"#;
+
+        let redacted = redact_body(body, "1Z5R89390357567127");
+
+        assert!(!redacted.contains("1Z5R89390357567127"));
+        assert!(redacted.contains("**************7127"));
+        assert!(!redacted.contains("1 Main St"));
+        assert!(!redacted.contains("J SMITH"));
+    }
+
+    #[test]
+    fn redacts_tracking_number_in_plain_text() {
+        let redacted = redact_body("https://www.ups.com/track?tracknum=1Z5R89390357567127", "1Z5R89390357567127");
+
+        assert_eq!(redacted, "https://www.ups.com/track?tracknum=**************7127");
+    }
+}
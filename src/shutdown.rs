@@ -0,0 +1,129 @@
+//! Coordinated shutdown. Once the shutdown signal fires, each worker thread
+//! gets a grace period to finish what it is doing, then the database's
+//! write-ahead log is checkpointed so the database file is complete on its own.
+
+use crate::db::Database;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// How long worker threads get to stop after the shutdown signal.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A named worker thread.
+pub type Worker = (&'static str, JoinHandle<()>);
+
+/// Wait for the worker threads: until `running` is cleared, then at most
+/// `grace` longer. Threads still running after that are logged and left to
+/// be killed when the process exits. Returns false if any thread panicked.
+pub fn wait_for_workers(running: &AtomicBool, mut workers: Vec<Worker>, grace: Duration) -> bool {
+    let mut panicked = false;
+    let mut deadline = None;
+
+    loop {
+        let (finished, pending): (Vec<Worker>, Vec<Worker>) =
+            workers.into_iter().partition(|(_, handle)| handle.is_finished());
+        for (name, handle) in finished {
+            if let Err(err) = handle.join() {
+                error!(thread = name, "Thread panicked: {:?}", err);
+                panicked = true;
+            }
+        }
+        workers = pending;
+        if workers.is_empty() {
+            break;
+        }
+
+        if !running.load(Ordering::SeqCst) {
+            let deadline = *deadline.get_or_insert_with(|| Instant::now() + grace);
+            if Instant::now() >= deadline {
+                for (name, _) in &workers {
+                    warn!(thread = name, grace_secs = grace.as_secs(), "Thread did not stop in time, exiting anyway");
+                }
+                break;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    !panicked
+}
+
+/// Fold the write-ahead log into the database file and truncate it. Returns
+/// whether the checkpoint completed; a thread still writing makes it fail.
+pub fn checkpoint(db: &mut dyn Database) -> bool {
+    match db.checkpoint_wal() {
+        Ok(()) => {
+            info!("Database checkpointed");
+            true
+        }
+        Err(err) => {
+            warn!(error = %err, "Failed to checkpoint database on shutdown");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SqliteDatabase;
+    use crate::test_support::new_package;
+    use std::sync::Arc;
+
+    #[test]
+    fn checkpoint_truncates_the_wal() {
+        let path = std::env::temp_dir().join(format!("trackage-shutdown-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let wal = format!("{path}-wal");
+
+        let mut db = SqliteDatabase::open(path).unwrap();
+        db.insert_package(&new_package("1Z5R89390357567127")).unwrap();
+        assert!(std::fs::metadata(&wal).unwrap().len() > 0);
+
+        assert!(checkpoint(&mut db));
+        assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[test]
+    fn stops_waiting_for_a_stuck_thread_after_the_grace_period() {
+        let running = Arc::new(AtomicBool::new(true));
+        let release = Arc::new(AtomicBool::new(false));
+
+        let polite = {
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            })
+        };
+        let stuck = {
+            let release = Arc::clone(&release);
+            thread::spawn(move || {
+                while !release.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            })
+        };
+
+        running.store(false, Ordering::SeqCst);
+        let started = Instant::now();
+        let clean = wait_for_workers(&running, vec![("polite", polite), ("stuck", stuck)], Duration::from_millis(300));
+
+        assert!(clean);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        release.store(true, Ordering::SeqCst);
+
+        let panicking = thread::spawn(|| panic!("worker failed"));
+        assert!(!wait_for_workers(&running, vec![("panicking", panicking)], Duration::from_millis(300)));
+    }
+}
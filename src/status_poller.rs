@@ -1,39 +1,206 @@
-use crate::config::StatusPollerConfig;
-use crate::courier::CourierClient;
-use crate::db::{Database, Package, PackageStatus};
+use crate::config::{AdaptivePollingConfig, BackfillConfig, NotifyConfig, StatusPollerConfig};
+use crate::courier::{CourierClient, CourierError, CourierStatus, short_description};
+use crate::db::{Database, Package, PackageStatus, StatusDetails, StatusHistoryEntry};
+use crate::metrics::{CourierOutcome, Metrics};
+use crate::notify::{self, ArrivingSoonEvent, NotifierSet, NotifyEvent, StatusChangeEvent};
+use chrono::{DateTime, Local, NaiveDate, Timelike, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use tracing::{debug, error, info};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, warn};
+
+/// Scan wording, matched case-insensitively, meaning a package is about to be
+/// delivered. Used to infer a delivery the courier never reported.
+const NEAR_DELIVERY_KEYWORDS: &[&str] = &[
+    "out for delivery",
+    "arrived at post office",
+    "arrived at delivery facility",
+    "at local facility",
+    "at local post office",
+];
+
+/// Outcome of one status poll.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PollReport {
+    /// Packages sent to a courier.
+    pub checked: usize,
+    /// Packages whose status changed.
+    pub updated: usize,
+    /// Failed courier checks, by courier.
+    pub failed_by_courier: BTreeMap<String, usize>,
+}
+
+/// The most recent poll's report, shared with the web server.
+pub type PollReportHandle = Arc<Mutex<Option<PollReport>>>;
+
+/// Sends packages to the poller for an immediate status check.
+pub type RescanQueue = SyncSender<RescanRequest>;
+
+/// A package queued for an on-demand status check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RescanRequest {
+    pub package_id: i64,
+    /// Discard the package's status history before recording the result.
+    pub clear: bool,
+}
+
+/// On-demand rescans that may wait at once; further requests are refused.
+const RESCAN_QUEUE_CAPACITY: usize = 32;
+
+/// The parts of a package's latest scan compared between checks to tell
+/// whether anything changed: status, description and scan time.
+type ScanKey = (String, Option<String>, Option<String>);
+
+/// A package's adaptive polling state (`status.adaptive`).
+struct PollBackoff {
+    /// The latest scan at the last check.
+    scan: Option<ScanKey>,
+    /// Checks in a row that found the same latest scan.
+    unchanged: u32,
+    next_check: DateTime<Utc>,
+}
 
 pub struct StatusPoller {
     config: StatusPollerConfig,
+    notify_config: NotifyConfig,
     db: Box<dyn Database>,
-    courier: Box<dyn CourierClient>,
+    courier: Arc<dyn CourierClient>,
+    notifiers: Arc<NotifierSet>,
+    /// Seconds between polls; shared so a config reload can change it.
+    interval: Arc<AtomicU64>,
+    /// Status changes found during the current poll, sent when it finishes.
+    pending: Vec<StatusChangeEvent>,
+    /// Tallies for the current poll.
+    report: PollReport,
+    last_report: PollReportHandle,
+    /// Packages queued for an on-demand check, drained between polls.
+    rescans: Receiver<RescanRequest>,
+    rescan_queue: RescanQueue,
+    /// Runtime and HTTP client for `status.async_requests`.
+    async_http: Option<(tokio::runtime::Runtime, reqwest::Client)>,
+    recorder: StatusRecorder,
+    /// When the current poll started. Next checks are
+    /// scheduled from here so a package checked late in one poll is still
+    /// due at the start of a later one.
+    cycle_started: DateTime<Utc>,
     running: Arc<AtomicBool>,
 }
 
+/// What the web server needs from the status poller.
+#[derive(Clone)]
+pub struct PollerHandle {
+    pub report: PollReportHandle,
+    pub rescans: RescanQueue,
+    pub recorder: StatusRecorder,
+}
+
+/// Records the outcome of courier checks: status history, last error,
+/// delivery confirmation, adaptive backoff and notifications. Shared by the
+/// poller and the web server's rescans, so a package is handled the same
+/// whichever of them checked it.
+#[derive(Clone)]
+pub struct StatusRecorder {
+    config: StatusPollerConfig,
+    notify_config: NotifyConfig,
+    notifiers: Arc<NotifierSet>,
+    /// Per-package check schedule for `status.adaptive`, by package id.
+    backoff: Arc<Mutex<HashMap<i64, PollBackoff>>>,
+    metrics: Arc<Metrics>,
+}
+
+/// What [`StatusRecorder::record`] made of a courier check.
+#[derive(Debug)]
+pub enum CheckOutcome {
+    /// No request was sent; the package is simply checked next poll.
+    Skipped,
+    /// The courier check failed.
+    Failed,
+    /// The result was recorded, with the package's status change if it has one.
+    Recorded(Option<StatusChangeEvent>),
+}
+
 impl StatusPoller {
     pub fn new(
         config: StatusPollerConfig,
+        notify_config: NotifyConfig,
         db: Box<dyn Database>,
-        courier: Box<dyn CourierClient>,
+        courier: Arc<dyn CourierClient>,
+        notifiers: Arc<NotifierSet>,
         running: Arc<AtomicBool>,
     ) -> Self {
+        let (rescan_queue, rescans) = mpsc::sync_channel(RESCAN_QUEUE_CAPACITY);
+        let async_http = config.async_requests.as_ref().map(|_| {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to create tokio runtime for status poller");
+            (runtime, reqwest::Client::new())
+        });
         Self {
+            interval: Arc::new(AtomicU64::new(config.check_interval_seconds)),
+            recorder: StatusRecorder::new(config.clone(), notify_config.clone(), Arc::clone(&notifiers)),
             config,
+            notify_config,
             db,
             courier,
+            notifiers,
+            pending: Vec::new(),
+            report: PollReport::default(),
+            last_report: Arc::default(),
+            rescans,
+            rescan_queue,
+            async_http,
+            cycle_started: Utc::now(),
             running,
         }
     }
 
+    /// Count courier checks in shared `metrics`.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.recorder.metrics = metrics;
+        self
+    }
+
+    /// Handles for the web server: the latest report, the rescan queue, and
+    /// the recorder for checks it makes itself.
+    pub fn handle(&self) -> PollerHandle {
+        PollerHandle {
+            report: self.report_handle(),
+            rescans: self.rescan_queue(),
+            recorder: self.recorder.clone(),
+        }
+    }
+
+    /// Handle for changing the poll interval while the poller runs.
+    pub fn interval_handle(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.interval)
+    }
+
+    /// Handle to the most recent poll's report.
+    pub fn report_handle(&self) -> PollReportHandle {
+        Arc::clone(&self.last_report)
+    }
+
+    /// Handle for queueing on-demand package checks.
+    pub fn rescan_queue(&self) -> RescanQueue {
+        self.rescan_queue.clone()
+    }
+
     /// Run the poll loop. Blocks until the shutdown signal fires.
     pub fn run(mut self) {
         info!("Status poller starting");
 
+        if !self.config.poll_on_startup {
+            debug!("Waiting for the first interval before polling");
+            self.sleep();
+        }
+
         while self.running.load(Ordering::SeqCst) {
             self.poll_once();
             self.sleep();
@@ -42,8 +209,86 @@ impl StatusPoller {
         info!("Status poller shutting down");
     }
 
-    fn poll_once(&mut self) {
-        let packages = match self.db.get_active_packages() {
+    fn poll_once(&mut self) -> PollReport {
+        let started = Utc::now();
+        let after_downtime = self.is_after_downtime(started);
+        self.cycle_started = started;
+
+        self.poll_at_hour(Local::now().hour());
+        self.flush_notifications(after_downtime);
+
+        if let Err(err) = self.db.set_last_status_poll(started) {
+            error!(error = %err, "Failed to record status poll time");
+        }
+
+        let report = std::mem::take(&mut self.report);
+        info!(
+            checked = report.checked,
+            updated = report.updated,
+            failed = ?report.failed_by_courier,
+            "Status poll complete"
+        );
+        *self.last_report.lock().unwrap() = Some(report.clone());
+        report
+    }
+
+    /// Whether the previous poll was long enough ago (over two intervals) that
+    /// this one is catching up after the daemon was down.
+    fn is_after_downtime(&self, now: DateTime<Utc>) -> bool {
+        let last_poll = match self.db.get_last_status_poll() {
+            Ok(Some(last_poll)) => last_poll,
+            Ok(None) => return false,
+            Err(err) => {
+                error!(error = %err, "Failed to query last status poll time");
+                return false;
+            }
+        };
+
+        let gap = (now - last_poll).num_seconds();
+        gap > 2 * self.interval.load(Ordering::SeqCst) as i64
+    }
+
+    /// Send the status changes gathered during a poll. After downtime, more
+    /// than `notify.batch_after_downtime` changes are sent as one notification.
+    fn flush_notifications(&mut self, after_downtime: bool) {
+        let events = std::mem::take(&mut self.pending);
+        let notifiers = self.notifiers.load();
+
+        match self.notify_config.batch_after_downtime {
+            Some(limit) if after_downtime && events.len() > limit => {
+                info!(count = events.len(), "Batching status changes after downtime");
+                notify::dispatch(&notifiers, &NotifyEvent::Batch(events));
+            }
+            _ => {
+                for event in events {
+                    notify::dispatch(&notifiers, &NotifyEvent::StatusChange(event));
+                }
+            }
+        }
+    }
+
+    fn poll_at_hour(&mut self, hour: u32) {
+        if let Some(hours) = &self.config.active_hours
+            && !hours.contains(hour)
+        {
+            if hours.exempt_arriving_today {
+                debug!("Outside active hours, checking only packages arriving today");
+                match self.db.get_packages_arriving_today() {
+                    Ok(packages) => self.check_packages(&packages),
+                    Err(err) => error!(error = %err, "Failed to query packages arriving today"),
+                }
+            } else {
+                debug!("Outside active hours, skipping status poll");
+            }
+            return;
+        }
+
+        if let Some(backfill) = self.backfill_config() {
+            self.backfill(&backfill);
+            return;
+        }
+
+        let packages = match self.packages_to_check() {
             Ok(packages) => packages,
             Err(err) => {
                 error!(error = %err, "Failed to query active packages");
@@ -57,101 +302,1473 @@ impl StatusPoller {
         }
 
         info!(count = packages.len(), "Checking active packages");
+        self.check_packages(&packages);
+    }
 
-        for package in &packages {
-            self.check_package(package);
+    /// Check packages one at a time, or concurrently when
+    /// `status.async_requests` is configured.
+    fn check_packages(&mut self, packages: &[Package]) {
+        let max_concurrent = match &self.config.async_requests {
+            Some(async_requests) => async_requests.max_concurrent,
+            None => {
+                packages.iter().for_each(|package| self.check_package(package));
+                return;
+            }
+        };
+
+        let results = self.check_concurrently(packages, max_concurrent);
+        for (package, result) in packages.iter().zip(results) {
+            self.record_check(package, result);
         }
     }
 
-    fn check_package(&mut self, package: &Package) {
-        let statuses = match self.courier.check_status(package) {
-            Ok(statuses) => statuses,
+    /// Send up to `max_concurrent` async courier requests at a time. Results
+    /// are in the order of `packages`.
+    fn check_concurrently(
+        &self,
+        packages: &[Package],
+        max_concurrent: usize,
+    ) -> Vec<anyhow::Result<Vec<CourierStatus>>> {
+        let Some((runtime, http)) = &self.async_http else {
+            return packages.iter().map(|package| self.courier.check_status(package)).collect();
+        };
+
+        runtime.block_on(async {
+            let mut results: Vec<Option<anyhow::Result<Vec<CourierStatus>>>> =
+                packages.iter().map(|_| None).collect();
+            let mut tasks = JoinSet::new();
+
+            for (i, package) in packages.iter().enumerate() {
+                if tasks.len() >= max_concurrent
+                    && let Some(Ok((done, result))) = tasks.join_next().await
+                {
+                    results[done] = Some(result);
+                }
+
+                let courier = Arc::clone(&self.courier);
+                let http = http.clone();
+                let package = package.clone();
+                tasks.spawn(async move { (i, courier.check_status_async(http, package).await) });
+            }
+
+            while let Some(joined) = tasks.join_next().await {
+                if let Ok((done, result)) = joined {
+                    results[done] = Some(result);
+                }
+            }
+
+            results
+                .into_iter()
+                .map(|result| result.unwrap_or_else(|| Err(anyhow::anyhow!("Courier status check panicked"))))
+                .collect()
+        })
+    }
+
+    /// The backfill settings, if backfill is configured and enough packages
+    /// have never been checked to warrant it.
+    fn backfill_config(&self) -> Option<BackfillConfig> {
+        let backfill = self.config.backfill.as_ref()?;
+        match self.db.get_unscanned_packages() {
+            Ok(unscanned) if unscanned.len() >= backfill.unscanned_threshold => Some(backfill.clone()),
+            Ok(_) => None,
             Err(err) => {
-                error!(
-                    error = %err,
-                    tracking_number = %package.tracking_number,
-                    "Courier status check failed"
-                );
+                error!(error = %err, "Failed to query unscanned packages");
+                None
+            }
+        }
+    }
+
+    /// Catch up on a large backlog gently: never-checked packages first, then
+    /// the rest by soonest ETA, at most `max_per_cycle` packages, pausing
+    /// between courier requests. Packages not reached are picked up by later
+    /// polls.
+    fn backfill(&mut self, backfill: &BackfillConfig) {
+        let packages = match self.backfill_packages() {
+            Ok(packages) => packages,
+            Err(err) => {
+                error!(error = %err, "Failed to query active packages");
                 return;
             }
         };
 
-        if statuses.is_empty() {
-            info!(
-                tracking_number = %package.tracking_number,
-                "No status update available, marking as not_found"
-            );
-            if let Err(err) = self.db.insert_package_status(
-                package.id,
-                &PackageStatus::NotFound,
-                None,
-                None,
-                None,
-                None,
-            ) {
-                error!(
-                    error = %err,
+        info!(
+            count = packages.len(),
+            max_per_cycle = backfill.max_per_cycle,
+            delay_ms = backfill.delay_ms,
+            "Backfilling package statuses"
+        );
+
+        let delay = Duration::from_millis(backfill.delay_ms);
+        for (i, package) in packages.iter().take(backfill.max_per_cycle).enumerate() {
+            if i > 0 {
+                if !self.running.load(Ordering::SeqCst) {
+                    break;
+                }
+                thread::sleep(delay);
+            }
+            self.check_package(package);
+        }
+    }
+
+    /// Packages to backfill, never-checked ones first so the backlog that
+    /// started backfill always shrinks, then active ones by soonest ETA.
+    fn backfill_packages(&self) -> anyhow::Result<Vec<Package>> {
+        let mut seen = HashSet::new();
+        Ok(self
+            .db
+            .get_unscanned_packages()?
+            .into_iter()
+            .chain(self.db.get_active_packages_by_eta()?)
+            .filter(|package| seen.insert(package.id))
+            .collect())
+    }
+
+    /// Active packages to check this cycle: never-scanned packages first so
+    /// newly added ones show progress quickly, then packages arriving today
+    /// since their status changes rapidly, then everything else that is due.
+    fn packages_to_check(&self) -> anyhow::Result<Vec<Package>> {
+        let mut packages = Vec::new();
+        let mut seen = HashSet::new();
+
+        let active = self.db.get_active_packages()?;
+        self.recorder.prune_backoff(&active);
+        let due: Vec<Package> = active.into_iter().filter(|package| self.recorder.is_due(package, self.cycle_started)).collect();
+        for package in self
+            .db
+            .get_unscanned_packages()?
+            .into_iter()
+            .chain(self.db.get_packages_arriving_today()?)
+            .chain(due)
+        {
+            if seen.insert(package.id) {
+                packages.push(package);
+            }
+        }
+
+        Ok(packages)
+    }
+
+    fn check_package(&mut self, package: &Package) {
+        let result = self.courier.check_status(package);
+        self.record_check(package, result);
+    }
+
+    /// Record the outcome of a courier check for `package` and tally it in
+    /// this poll's report.
+    fn record_check(&mut self, package: &Package, result: anyhow::Result<Vec<CourierStatus>>) {
+        match self.recorder.record(self.db.as_mut(), package, result, self.cycle_started, false) {
+            CheckOutcome::Skipped => {}
+            CheckOutcome::Failed => {
+                self.report.checked += 1;
+                *self.report.failed_by_courier.entry(package.courier.clone()).or_default() += 1;
+            }
+            CheckOutcome::Recorded(change) => {
+                self.report.checked += 1;
+                if let Some(change) = change {
+                    self.report.updated += 1;
+                    self.pending.push(change);
+                }
+            }
+        }
+    }
+
+    /// Check every package queued for an on-demand rescan, recording each
+    /// as a web rescan is recorded. These checks don't count towards the
+    /// next poll's report.
+    fn drain_rescans(&mut self) {
+        let requests: Vec<RescanRequest> = self.rescans.try_iter().collect();
+        for RescanRequest { package_id: id, clear } in requests {
+            match self.db.get_package(id) {
+                Ok(Some(package)) => {
+                    info!(package_id = id, tracking_number = %package.tracking_number, "Servicing on-demand rescan");
+                    let result = self.courier.check_status(&package);
+                    self.recorder.record_rescan(self.db.as_mut(), &package, result, clear);
+                }
+                Ok(None) => debug!(package_id = id, "Queued rescan for a package that no longer exists"),
+                Err(err) => error!(error = %err, package_id = id, "Failed to load package for rescan"),
+            }
+        }
+    }
+
+    fn sleep(&mut self) {
+        let mut slept = 0;
+        while slept < self.interval.load(Ordering::SeqCst) && self.running.load(Ordering::SeqCst) {
+            self.drain_rescans();
+            thread::sleep(Duration::from_secs(1));
+            slept += 1;
+        }
+    }
+}
+
+impl StatusRecorder {
+    pub fn new(config: StatusPollerConfig, notify_config: NotifyConfig, notifiers: Arc<NotifierSet>) -> Self {
+        Self { config, notify_config, notifiers, backoff: Arc::default(), metrics: Arc::default() }
+    }
+
+    /// Whether adaptive polling has the package due for a check in the cycle
+    /// started at `cycle_started`.
+    fn is_due(&self, package: &Package, cycle_started: DateTime<Utc>) -> bool {
+        match self.backoff.lock().unwrap().get(&package.id) {
+            Some(backoff) if backoff.next_check > cycle_started => {
+                debug!(
                     tracking_number = %package.tracking_number,
-                    "Failed to insert not_found status"
+                    next_check = %backoff.next_check,
+                    "Unchanged package not due yet, skipping"
                 );
+                false
             }
+            _ => true,
+        }
+    }
+
+    /// Forget the adaptive polling state of packages no longer `active`, so
+    /// delivered and deleted packages don't accumulate.
+    fn prune_backoff(&self, active: &[Package]) {
+        let active: HashSet<i64> = active.iter().map(|package| package.id).collect();
+        self.backoff.lock().unwrap().retain(|id, _| active.contains(id));
+    }
+
+    /// With `status.adaptive`, schedule the package's next check: further off
+    /// when this check found the same latest scan as the last one, back to
+    /// the base interval when anything changed.
+    fn schedule_next_check(&self, package: &Package, latest: Option<&CourierStatus>, cycle_started: DateTime<Utc>) {
+        let Some(adaptive) = &self.config.adaptive else {
             return;
+        };
+
+        let mut backoff = self.backoff.lock().unwrap();
+        let scan = latest.map(|latest| (latest.status.clone(), latest.description.clone(), latest.checked_at.clone()));
+        let unchanged = match backoff.get(&package.id) {
+            Some(previous) if previous.scan == scan => previous.unchanged + 1,
+            _ => 0,
+        };
+        let delay = backoff_delay(adaptive, unchanged);
+        if unchanged > 0 {
+            debug!(
+                tracking_number = %package.tracking_number,
+                unchanged,
+                delay_secs = delay.as_secs(),
+                "No change since last check, backing off"
+            );
         }
 
-        let last_idx = statuses.len() - 1;
-        for (i, courier_status) in statuses.iter().enumerate() {
-            let status = match PackageStatus::from_str(&courier_status.status) {
-                Ok(s) => s,
-                Err(err) => {
-                    error!(
-                        error = %err,
-                        tracking_number = %package.tracking_number,
-                        status = %courier_status.status,
-                        "Invalid status from courier"
-                    );
-                    continue;
-                }
-            };
+        let next_check = cycle_started + chrono::Duration::seconds(delay.as_secs() as i64);
+        backoff.insert(package.id, PollBackoff { scan, unchanged, next_check });
+    }
+
+    /// Record the outcome of a courier check for `package` made in the cycle
+    /// started at `cycle_started`. With `clear`, the package's existing
+    /// history is discarded before the new results are recorded.
+    pub fn record(
+        &self,
+        db: &mut dyn Database,
+        package: &Package,
+        result: anyhow::Result<Vec<CourierStatus>>,
+        cycle_started: DateTime<Utc>,
+        clear: bool,
+    ) -> CheckOutcome {
+        // No request was sent; the package is simply checked next poll
+        if let Err(err) = &result
+            && matches!(
+                err.downcast_ref::<CourierError>(),
+                Some(CourierError::CoolingDown | CourierError::ShuttingDown)
+            )
+        {
+            return CheckOutcome::Skipped;
+        }
 
-            // Log status change only for the most recent entry
-            if i == last_idx {
-                if status != package.status {
-                    info!(
+        let (statuses, last_error) = match result {
+            Ok(statuses) => {
+                self.metrics.record_courier_request(&package.courier, CourierOutcome::Success);
+                (statuses, None)
+            }
+            Err(err) => match err.downcast_ref::<CourierError>() {
+                // The courier definitively has no record; record that like an empty result
+                Some(courier_err @ CourierError::NotFound) => {
+                    self.metrics.record_courier_request(&package.courier, CourierOutcome::NotFound);
+                    (vec![], Some(courier_err.message()))
+                }
+                Some(courier_err) => {
+                    warn!(
                         tracking_number = %package.tracking_number,
-                        old_status = %package.status,
-                        new_status = %status,
-                        "Package status changed"
+                        error = %courier_err,
+                        "Courier reported an error"
                     );
-                } else {
-                    info!(
+                    self.set_last_error(db, package, Some(&courier_err.message()));
+                    self.metrics.record_courier_request(&package.courier, CourierOutcome::Error);
+                    return CheckOutcome::Failed;
+                }
+                None => {
+                    error!(
+                        error = %err,
                         tracking_number = %package.tracking_number,
-                        "Updating status information"
+                        "Courier status check failed"
                     );
+                    self.set_last_error(db, package, Some(&format!("Courier status check failed: {err}")));
+                    self.metrics.record_courier_request(&package.courier, CourierOutcome::Error);
+                    return CheckOutcome::Failed;
                 }
+            },
+        };
+
+        self.schedule_next_check(package, statuses.last(), cycle_started);
+        if self.config.confirm_delivered && !self.delivery_confirmed(db, package, statuses.last()) {
+            return CheckOutcome::Recorded(None);
+        }
+
+        if clear && let Err(err) = db.delete_all_package_status(package.id) {
+            error!(error = %err, tracking_number = %package.tracking_number, "Failed to delete all package history");
+        }
+        // The courier's answer replaces an inferred delivery, which is
+        // inferred again below if its scans are still stale
+        if let Err(err) = db.delete_assumed_deliveries(package.id) {
+            error!(error = %err, tracking_number = %package.tracking_number, "Failed to delete assumed delivery");
+        }
+        record_statuses(db, package, &statuses, self.config.short_descriptions);
+        self.set_last_error(db, package, last_error.as_deref());
+        let assumed = self
+            .config
+            .assume_delivered_after_days
+            .is_some_and(|days| self.assume_delivered_if_stale(db, package, days, Utc::now()));
+        let change = if assumed { assumed_delivery_change(package) } else { status_change(package, statuses.last()) };
+        self.notify_if_arriving(db, package, Local::now().date_naive());
+        CheckOutcome::Recorded(change)
+    }
+
+    /// Record an on-demand check made outside any poll, sending a status
+    /// change straight away rather than with the next poll's notifications.
+    /// Someone asked about the package, so adaptive polling starts over from
+    /// the base interval.
+    pub fn record_rescan(
+        &self,
+        db: &mut dyn Database,
+        package: &Package,
+        result: anyhow::Result<Vec<CourierStatus>>,
+        clear: bool,
+    ) -> CheckOutcome {
+        self.backoff.lock().unwrap().remove(&package.id);
+        let outcome = self.record(db, package, result, Utc::now(), clear);
+        if let CheckOutcome::Recorded(Some(change)) = &outcome {
+            notify::dispatch(&self.notifiers.load(), &NotifyEvent::StatusChange(change.clone()));
+        }
+        outcome
+    }
+
+    /// With `status.confirm_delivered`, whether this check's result may be
+    /// recorded. A newly delivered result is held back until the next poll
+    /// reports delivered again, so a spurious scan that reverts neither
+    /// notifies nor stops polling.
+    fn delivery_confirmed(&self, db: &mut dyn Database, package: &Package, latest: Option<&CourierStatus>) -> bool {
+        let delivered = latest.is_some_and(|latest| latest.status == PackageStatus::Delivered.to_string());
+        let pending = match db.is_delivery_pending(package.id) {
+            Ok(pending) => pending,
+            Err(err) => {
+                error!(error = %err, tracking_number = %package.tracking_number, "Failed to query pending delivery");
+                false
             }
+        };
 
-            if let Err(err) = self.db.insert_package_status(
-                package.id,
-                &status,
-                courier_status.estimated_arrival_date.as_deref(),
-                courier_status.last_known_location.as_deref(),
-                courier_status.description.as_deref(),
-                courier_status.checked_at.as_deref(),
-            ) {
+        if delivered && package.status != PackageStatus::Delivered && !pending {
+            info!(tracking_number = %package.tracking_number, "Delivered scan awaiting confirmation by the next poll");
+            set_delivery_pending(db, package, true);
+            return false;
+        }
+        if pending {
+            if delivered {
+                info!(tracking_number = %package.tracking_number, "Delivery confirmed by a second consecutive scan");
+            } else {
+                info!(tracking_number = %package.tracking_number, "Delivered scan not confirmed, continuing to poll");
+            }
+            set_delivery_pending(db, package, false);
+        }
+        true
+    }
+
+    /// Mark the package delivered when its latest scan says it is about to be
+    /// delivered and hasn't changed in `days`, returning whether it did. The
+    /// inferred entry says so in its description, and the next courier result
+    /// replaces it.
+    fn assume_delivered_if_stale(&self, db: &mut dyn Database, package: &Package, days: u32, now: DateTime<Utc>) -> bool {
+        let history = match db.get_package_status_history(package.id) {
+            Ok(history) => history,
+            Err(err) => {
+                error!(error = %err, tracking_number = %package.tracking_number, "Failed to query status history");
+                return false;
+            }
+        };
+        let Some(scan) = stale_near_delivery_scan(&history, days, now) else {
+            return false;
+        };
+
+        info!(
+            tracking_number = %package.tracking_number,
+            days,
+            scan = %scan,
+            "No courier update since near-delivery scan, assuming delivered"
+        );
+        let description = format!("Assumed delivered: no courier update for {days} days after \"{scan}\"");
+        if let Err(err) = db.insert_assumed_delivery(package.id, &description) {
+            error!(error = %err, tracking_number = %package.tracking_number, "Failed to record assumed delivery");
+            return false;
+        }
+        true
+    }
+
+    /// Send a one-time arriving-soon notification once the package's ETA is
+    /// `notify.eta_lead_days` or fewer days away, including an ETA that first
+    /// appears inside that window. The notified ETA is remembered, so a
+    /// changed ETA notifies again.
+    fn notify_if_arriving(&self, db: &mut dyn Database, package: &Package, today: NaiveDate) {
+        let Some(lead_days) = self.notify_config.eta_lead_days else {
+            return;
+        };
+
+        let details = match db.get_package_with_status(package.id) {
+            Ok(Some(details)) => details,
+            Ok(None) => return,
+            Err(err) => {
+                error!(error = %err, tracking_number = %package.tracking_number, "Failed to query package ETA");
+                return;
+            }
+        };
+
+        if details.status == PackageStatus::Delivered.to_string() {
+            return;
+        }
+        let Some(eta) = details.estimated_arrival_date else {
+            return;
+        };
+        let Some(eta_date) = eta.get(..10).and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()) else {
+            debug!(tracking_number = %package.tracking_number, eta = %eta, "Unparseable ETA, skipping arrival notification");
+            return;
+        };
+        let Ok(days_away) = u32::try_from((eta_date - today).num_days()) else {
+            return;
+        };
+        if days_away > lead_days {
+            return;
+        }
+
+        match db.get_eta_notified(package.id) {
+            Ok(Some(notified)) if notified == eta => return,
+            Ok(_) => {}
+            Err(err) => {
+                error!(error = %err, tracking_number = %package.tracking_number, "Failed to query notified ETA");
+                return;
+            }
+        }
+
+        let event = NotifyEvent::ArrivingSoon(ArrivingSoonEvent {
+            tracking_number: details.tracking_number,
+            courier: details.courier,
+            estimated_arrival_date: eta.clone(),
+            days_away,
+        });
+        notify::dispatch(&self.notifiers.load(), &event);
+
+        if let Err(err) = db.set_eta_notified(package.id, &eta) {
+            error!(error = %err, tracking_number = %package.tracking_number, "Failed to record notified ETA");
+        }
+    }
+
+    fn set_last_error(&self, db: &mut dyn Database, package: &Package, message: Option<&str>) {
+        if let Err(err) = db.set_last_error(package.id, message) {
+            error!(
+                error = %err,
+                tracking_number = %package.tracking_number,
+                "Failed to update package last_error"
+            );
+        }
+    }
+
+}
+
+fn set_delivery_pending(db: &mut dyn Database, package: &Package, pending: bool) {
+    if let Err(err) = db.set_delivery_pending(package.id, pending) {
+        error!(error = %err, tracking_number = %package.tracking_number, "Failed to update pending delivery");
+    }
+}
+
+/// The change in the package's latest status this check found, if any.
+fn status_change(package: &Package, latest: Option<&CourierStatus>) -> Option<StatusChangeEvent> {
+    let latest = latest?;
+    let new_status = PackageStatus::from_str(&latest.status).ok()?;
+    if new_status == package.status {
+        return None;
+    }
+
+    Some(StatusChangeEvent {
+        tracking_number: package.tracking_number.clone(),
+        courier: package.courier.clone(),
+        old_status: package.status,
+        new_status,
+        location: latest.last_known_location.clone(),
+        checked_at: latest.checked_at.clone(),
+    })
+}
+
+/// The status change for a delivery inferred by
+/// `status.assume_delivered_after_days`, unless it was already delivered.
+fn assumed_delivery_change(package: &Package) -> Option<StatusChangeEvent> {
+    (package.status != PackageStatus::Delivered).then(|| StatusChangeEvent {
+        tracking_number: package.tracking_number.clone(),
+        courier: package.courier.clone(),
+        old_status: package.status,
+        new_status: PackageStatus::Delivered,
+        location: None,
+        checked_at: None,
+    })
+}
+
+/// How long to wait before re-checking a package whose last `unchanged`
+/// checks found nothing new: the base interval, doubled for each, capped at
+/// the maximum.
+fn backoff_delay(config: &AdaptivePollingConfig, unchanged: u32) -> Duration {
+    Duration::from_secs(config.base_interval_seconds)
+        .saturating_mul(1 << unchanged.min(16))
+        .min(Duration::from_secs(config.max_interval_seconds))
+}
+
+/// The wording of the latest scan if it indicates imminent delivery and the
+/// package's status, description and courier text have been unchanged for at
+/// least `days`. `history` is newest first.
+fn stale_near_delivery_scan(history: &[StatusHistoryEntry], days: u32, now: DateTime<Utc>) -> Option<String> {
+    let latest = history.first()?;
+    if latest.status != PackageStatus::InTransit.to_string() {
+        return None;
+    }
+
+    let scan = [&latest.courier_status_text, &latest.description]
+        .into_iter()
+        .flatten()
+        .find(|text| {
+            let lower = text.to_lowercase();
+            NEAR_DELIVERY_KEYWORDS.iter().any(|keyword| lower.contains(keyword))
+        })?;
+
+    // Repeated polls record the same scan again; go back to when it first appeared
+    let first_seen = history
+        .iter()
+        .take_while(|entry| {
+            entry.status == latest.status
+                && entry.description == latest.description
+                && entry.courier_status_text == latest.courier_status_text
+        })
+        .last()?;
+    let since = DateTime::parse_from_rfc3339(&first_seen.checked_at).ok()?;
+
+    (now.signed_duration_since(since) >= chrono::Duration::days(i64::from(days))).then(|| scan.clone())
+}
+
+/// Record a courier's results for a package in its status history. An empty
+/// result marks the package as not found. With `short_descriptions`, each
+/// scan's description is stored with its short form.
+pub fn record_statuses(db: &mut dyn Database, package: &Package, statuses: &[CourierStatus], short_descriptions: bool) {
+    if statuses.is_empty() {
+        info!(
+            tracking_number = %package.tracking_number,
+            "No status update available, marking as not_found"
+        );
+        if let Err(err) = db.insert_package_status(
+            package.id,
+            &PackageStatus::NotFound,
+            &StatusDetails::default(),
+        ) {
+            error!(
+                error = %err,
+                tracking_number = %package.tracking_number,
+                "Failed to insert not_found status"
+            );
+        }
+        return;
+    }
+
+    let last_idx = statuses.len() - 1;
+    for (i, courier_status) in statuses.iter().enumerate() {
+        let status = match PackageStatus::from_str(&courier_status.status) {
+            Ok(s) => s,
+            Err(err) => {
                 error!(
                     error = %err,
                     tracking_number = %package.tracking_number,
-                    "Failed to insert package status history"
+                    status = %courier_status.status,
+                    "Invalid status from courier"
+                );
+                continue;
+            }
+        };
+
+        // Log status change only for the most recent entry
+        if i == last_idx {
+            if status != package.status {
+                info!(
+                    tracking_number = %package.tracking_number,
+                    old_status = %package.status,
+                    new_status = %status,
+                    "Package status changed"
+                );
+            } else {
+                info!(
+                    tracking_number = %package.tracking_number,
+                    "Updating status information"
                 );
             }
         }
+
+        if let Err(err) = db.insert_package_status(
+            package.id,
+            &status,
+            &StatusDetails {
+                estimated_arrival_date: courier_status.estimated_arrival_date.as_deref(),
+                last_known_location: courier_status.last_known_location.as_deref(),
+                description: courier_status.description.as_deref(),
+                short_description: courier_status
+                    .description
+                    .as_deref()
+                    .filter(|_| short_descriptions)
+                    .and_then(short_description),
+                checked_at: courier_status.checked_at.as_deref(),
+                delivery_location_detail: courier_status.delivery_location_detail.as_deref(),
+                courier_status_text: courier_status.courier_status_text.as_deref(),
+                courier_status_code: courier_status.courier_status_code.as_deref(),
+            },
+        ) {
+            error!(
+                error = %err,
+                tracking_number = %package.tracking_number,
+                "Failed to insert package status history"
+            );
+        }
     }
+}
 
-    fn sleep(&self) {
-        let mut slept = 0;
-        while slept < self.config.check_interval_seconds && self.running.load(Ordering::SeqCst) {
-            thread::sleep(Duration::from_secs(1));
-            slept += 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ActiveHoursConfig, AsyncRequestsConfig};
+    use crate::db::{NewPackage, SqliteDatabase};
+    use crate::test_support::{db_with, new_package, recording_notifiers, status_poller, webhook_server};
+    use std::sync::Mutex;
+
+    /// Courier that reports every package in transit and records what it was asked about.
+    struct RecordingCourier {
+        checked: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CourierClient for RecordingCourier {
+        fn check_status(&self, package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            self.checked.lock().unwrap().push(package.tracking_number.clone());
+            Ok(vec![CourierStatus {
+                status: "in_transit".into(),
+                estimated_arrival_date: None,
+                last_known_location: None,
+                description: None,
+                checked_at: None,
+                delivery_location_detail: None,
+                courier_status_text: None,
+                courier_status_code: None,
+            }])
+        }
+    }
+
+    fn poller(config: StatusPollerConfig, tracking_numbers: &[&str]) -> (StatusPoller, Arc<Mutex<Vec<String>>>) {
+        let checked = Arc::new(Mutex::new(Vec::new()));
+        let courier = RecordingCourier { checked: Arc::clone(&checked) };
+        let poller = status_poller(config, NotifyConfig::default(), db_with(tracking_numbers), courier, Arc::default());
+        (poller, checked)
+    }
+
+    fn business_hours() -> StatusPollerConfig {
+        StatusPollerConfig {
+            active_hours: Some(ActiveHoursConfig { start: 8, end: 18, exempt_arriving_today: false }),
+            ..StatusPollerConfig::default()
+        }
+    }
+
+    /// Courier that always fails with the given error.
+    struct FailingCourier(CourierError);
+
+    impl CourierClient for FailingCourier {
+        fn check_status(&self, _package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            Err(self.0.clone().into())
+        }
+    }
+
+    #[test]
+    fn stores_courier_error_message_as_last_error() {
+        let db = db_with(&["1ZAAA"]);
+        let package = db.get_active_packages().unwrap().remove(0);
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig::default(),
+            db,
+            FailingCourier(CourierError::RateLimited),
+            Arc::default(),
+        );
+
+        poller.check_package(&package);
+
+        let stored = poller.db.get_package_with_status(package.id).unwrap().unwrap();
+        assert_eq!(stored.last_error.as_deref(), Some(CourierError::RateLimited.message().as_str()));
+        assert_eq!(stored.status, "waiting");
+    }
+
+    #[test]
+    fn replaces_last_error_after_an_unclassified_failure() {
+        let db = db_with(&["1ZAAA"]);
+        let package = db.get_active_packages().unwrap().remove(0);
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig::default(),
+            db,
+            FailingCourier(CourierError::RateLimited),
+            Arc::default(),
+        );
+        poller.check_package(&package);
+
+        poller.record_check(&package, Err(anyhow::anyhow!("connection reset")));
+
+        let stored = poller.db.get_package_with_status(package.id).unwrap().unwrap();
+        assert_eq!(stored.last_error.as_deref(), Some("Courier status check failed: connection reset"));
+    }
+
+    #[test]
+    fn courier_not_found_error_marks_package_not_found() {
+        let db = db_with(&["1ZAAA"]);
+        let package = db.get_active_packages().unwrap().remove(0);
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig::default(),
+            db,
+            FailingCourier(CourierError::NotFound),
+            Arc::default(),
+        );
+
+        poller.check_package(&package);
+
+        let stored = poller.db.get_package_with_status(package.id).unwrap().unwrap();
+        assert_eq!(stored.status, "not_found");
+        assert!(stored.last_error.is_some());
+    }
+
+    #[test]
+    fn includes_packages_arriving_today() {
+        let (mut poller, _) = poller(StatusPollerConfig::default(), &["1ZAAA", "1ZBBB"]);
+        let today = Local::now().format("%Y-%m-%d").to_string();
+        let ids: Vec<i64> = poller.db.get_active_packages().unwrap().iter().map(|p| p.id).collect();
+        poller
+            .db
+            .insert_package_status(
+                ids[0],
+                &PackageStatus::InTransit,
+                &StatusDetails {
+                    estimated_arrival_date: Some("2000-01-01"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        poller
+            .db
+            .insert_package_status(
+                ids[1],
+                &PackageStatus::InTransit,
+                &StatusDetails { estimated_arrival_date: Some(&today), ..Default::default() },
+            )
+            .unwrap();
+
+        let arriving: Vec<i64> = poller.db.get_packages_arriving_today().unwrap().iter().map(|p| p.id).collect();
+        assert_eq!(arriving, vec![ids[1]]);
+
+        let order: Vec<i64> = poller.packages_to_check().unwrap().iter().map(|p| p.id).collect();
+        assert_eq!(order, vec![ids[1], ids[0]]);
+    }
+
+    #[test]
+    fn arriving_soon_notification_fires_once_per_eta() {
+        let db = db_with(&["1ZAAA"]);
+        let package = db.get_active_packages().unwrap().remove(0);
+        let (notifiers, received) = recording_notifiers();
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig { eta_lead_days: Some(1), ..NotifyConfig::default() },
+            db,
+            RecordingCourier { checked: Arc::default() },
+            notifiers,
+        );
+        let day = |d| NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+
+        poller
+            .db
+            .insert_package_status(
+                package.id,
+                &PackageStatus::InTransit,
+                &StatusDetails {
+                    estimated_arrival_date: Some("2026-03-02"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        poller.recorder.notify_if_arriving(poller.db.as_mut(), &package, day(1));
+        poller.recorder.notify_if_arriving(poller.db.as_mut(), &package, day(1));
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // Not yet within the lead time
+        poller
+            .db
+            .insert_package_status(
+                package.id,
+                &PackageStatus::InTransit,
+                &StatusDetails {
+                    estimated_arrival_date: Some("2026-03-05"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        poller.recorder.notify_if_arriving(poller.db.as_mut(), &package, day(1));
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // The day before was skipped: notified on the day itself
+        poller.recorder.notify_if_arriving(poller.db.as_mut(), &package, day(5));
+        poller.recorder.notify_if_arriving(poller.db.as_mut(), &package, day(5));
+        // Past ETAs aren't announced
+        poller.recorder.notify_if_arriving(poller.db.as_mut(), &package, day(6));
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                "UPS 1ZAAA is arriving tomorrow (2026-03-02)",
+                "UPS 1ZAAA is arriving today (2026-03-05)",
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_first_interval_when_poll_on_startup_disabled() {
+        let config = StatusPollerConfig {
+            check_interval_seconds: 60,
+            poll_on_startup: false,
+            ..StatusPollerConfig::default()
+        };
+        let (poller, checked) = poller(config, &["1ZAAA"]);
+        let running = Arc::clone(&poller.running);
+
+        let handle = thread::spawn(move || poller.run());
+        thread::sleep(Duration::from_millis(300));
+        running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert!(checked.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn polls_immediately_on_startup_by_default() {
+        let config = StatusPollerConfig { check_interval_seconds: 60, ..StatusPollerConfig::default() };
+        let (poller, checked) = poller(config, &["1ZAAA"]);
+        let running = Arc::clone(&poller.running);
+
+        let handle = thread::spawn(move || poller.run());
+        let started = std::time::Instant::now();
+        while checked.lock().unwrap().is_empty() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert_eq!(*checked.lock().unwrap(), vec!["1ZAAA"]);
+    }
+
+    #[test]
+    fn backs_off_unchanged_packages_until_they_change() {
+        let adaptive = AdaptivePollingConfig { base_interval_seconds: 3600, max_interval_seconds: 6 * 3600 };
+        let config = StatusPollerConfig { adaptive: Some(adaptive), ..StatusPollerConfig::default() };
+        let (mut poller, checked) = poller(config, &["1ZAAA"]);
+        let package = poller.db.get_active_packages().unwrap().remove(0);
+
+        let mut delays = Vec::new();
+        for _ in 0..5 {
+            poller.check_package(&package);
+            let backoff = &poller.recorder.backoff.lock().unwrap()[&package.id];
+            delays.push((backoff.next_check - poller.cycle_started).num_hours());
+        }
+        assert_eq!(delays, [1, 2, 4, 6, 6]);
+
+        // Not due again until the scheduled time, so the next poll skips it
+        checked.lock().unwrap().clear();
+        poller.poll_at_hour(12);
+        assert!(checked.lock().unwrap().is_empty());
+
+        let moved = CourierStatus {
+            status: "in_transit".into(),
+            description: Some("Departed facility".into()),
+            estimated_arrival_date: None,
+            last_known_location: None,
+            checked_at: None,
+            delivery_location_detail: None,
+            courier_status_text: None,
+            courier_status_code: None,
+        };
+        poller.recorder.schedule_next_check(&package, Some(&moved), poller.cycle_started);
+        assert_eq!((poller.recorder.backoff.lock().unwrap()[&package.id].next_check - poller.cycle_started).num_hours(), 1);
+    }
+
+    #[test]
+    fn rescan_restarts_adaptive_backoff() {
+        let adaptive = AdaptivePollingConfig { base_interval_seconds: 3600, max_interval_seconds: 6 * 3600 };
+        let config = StatusPollerConfig { adaptive: Some(adaptive), ..StatusPollerConfig::default() };
+        let (mut poller, _) = poller(config, &["1ZAAA"]);
+        let package = poller.db.get_active_packages().unwrap().remove(0);
+        for _ in 0..3 {
+            poller.check_package(&package);
         }
+
+        let recorder = poller.handle().recorder;
+        let result = poller.courier.check_status(&package);
+        recorder.record_rescan(poller.db.as_mut(), &package, result, false);
+
+        let backoff = &recorder.backoff.lock().unwrap()[&package.id];
+        assert_eq!(backoff.unchanged, 0);
+        assert!(backoff.next_check <= Utc::now() + chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn forgets_backoff_of_packages_no_longer_active() {
+        let adaptive = AdaptivePollingConfig { base_interval_seconds: 3600, max_interval_seconds: 6 * 3600 };
+        let config = StatusPollerConfig { adaptive: Some(adaptive), ..StatusPollerConfig::default() };
+        let (mut poller, _) = poller(config, &["1ZAAA", "1ZBBB"]);
+        poller.poll_at_hour(12);
+        assert_eq!(poller.recorder.backoff.lock().unwrap().len(), 2);
+
+        let deleted = poller.db.get_active_packages().unwrap().remove(0);
+        poller.db.delete_package(deleted.id).unwrap();
+        poller.poll_at_hour(12);
+
+        let backoff = poller.recorder.backoff.lock().unwrap();
+        assert_eq!(backoff.len(), 1);
+        assert!(!backoff.contains_key(&deleted.id));
+    }
+
+    #[test]
+    fn skips_polling_outside_active_hours() {
+        let (mut poller, checked) = poller(business_hours(), &["1ZAAA"]);
+
+        poller.poll_at_hour(3);
+
+        assert!(checked.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn polls_inside_active_hours() {
+        let (mut poller, checked) = poller(business_hours(), &["1ZAAA"]);
+
+        poller.poll_at_hour(10);
+
+        assert_eq!(*checked.lock().unwrap(), vec!["1ZAAA"]);
+    }
+
+    #[test]
+    fn batches_status_changes_after_downtime() {
+        let mut db = db_with(&["1ZAAA", "1ZBBB", "1ZCCC"]);
+        db.set_last_status_poll(Utc::now() - chrono::Duration::days(1)).unwrap();
+        let (notifiers, received) = recording_notifiers();
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig { batch_after_downtime: Some(2), ..NotifyConfig::default() },
+            db,
+            RecordingCourier { checked: Arc::default() },
+            notifiers,
+        );
+
+        poller.poll_once();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].starts_with("3 packages updated while trackage was offline"));
+    }
+
+    #[test]
+    fn sends_individual_notifications_without_downtime() {
+        let mut db = db_with(&["1ZAAA", "1ZBBB", "1ZCCC"]);
+        db.set_last_status_poll(Utc::now()).unwrap();
+        let (notifiers, received) = recording_notifiers();
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig { batch_after_downtime: Some(2), ..NotifyConfig::default() },
+            db,
+            RecordingCourier { checked: Arc::default() },
+            notifiers,
+        );
+
+        poller.poll_once();
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    fn history_entry(status: &str, text: &str, checked_at: &str) -> StatusHistoryEntry {
+        StatusHistoryEntry {
+            status: status.into(),
+            description: None,
+            short_description: None,
+            last_known_location: None,
+            checked_at: checked_at.into(),
+            delivery_location_detail: None,
+            courier_status_text: Some(text.into()),
+            courier_status_code: None,
+        }
+    }
+
+    #[test]
+    fn infers_delivery_only_after_stale_near_delivery_scan() {
+        let now = DateTime::parse_from_rfc3339("2026-03-10T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        // Out for delivery since March 2, re-recorded by later polls
+        let stale = vec![
+            history_entry("in_transit", "Out for Delivery", "2026-03-09T12:00:00Z"),
+            history_entry("in_transit", "Out for Delivery", "2026-03-02T12:00:00Z"),
+            history_entry("in_transit", "Departed facility", "2026-03-01T12:00:00Z"),
+        ];
+        assert_eq!(stale_near_delivery_scan(&stale, 7, now).as_deref(), Some("Out for Delivery"));
+        assert_eq!(stale_near_delivery_scan(&stale, 9, now), None);
+
+        let recent = vec![history_entry("in_transit", "Out for Delivery", "2026-03-09T12:00:00Z")];
+        assert_eq!(stale_near_delivery_scan(&recent, 7, now), None);
+
+        let not_near = vec![history_entry("in_transit", "Departed facility", "2026-03-01T12:00:00Z")];
+        assert_eq!(stale_near_delivery_scan(&not_near, 7, now), None);
+
+        let pickup = vec![history_entry("ready_for_pickup", "Out for Delivery", "2026-03-01T12:00:00Z")];
+        assert_eq!(stale_near_delivery_scan(&pickup, 7, now), None);
+    }
+
+    #[test]
+    fn courier_results_replace_an_assumed_delivery() {
+        let (notifiers, received) = recording_notifiers();
+        let out_for_delivery =
+            || CourierStatus { checked_at: Some("2026-01-02T12:00:00Z".into()), ..scan("in_transit", "O", "Out for Delivery") };
+        let mut poller = status_poller(
+            StatusPollerConfig { assume_delivered_after_days: Some(7), ..StatusPollerConfig::default() },
+            NotifyConfig::default(),
+            db_with(&["1ZAAA"]),
+            ScriptedCourier {
+                responses: Mutex::new(vec![
+                    out_for_delivery(),
+                    out_for_delivery(),
+                    scan("in_transit", "I", "Departed facility"),
+                ]),
+            },
+            notifiers,
+        );
+        let id = poller.db.get_by_tracking_number("1ZAAA").unwrap().unwrap().id;
+        let rescan = |poller: &mut StatusPoller| {
+            let package = poller.db.get_package(id).unwrap().unwrap();
+            let result = poller.courier.check_status(&package);
+            poller.recorder.clone().record_rescan(poller.db.as_mut(), &package, result, false);
+            poller.db.get_package_status_history(id).unwrap()
+        };
+
+        poller.poll_once();
+        assert_eq!(poller.db.get_package(id).unwrap().unwrap().status, PackageStatus::Delivered);
+        assert_eq!(*received.lock().unwrap(), vec!["ups 1ZAAA is now delivered"]);
+
+        // The courier still has nothing newer: the delivery is inferred again, once
+        let history = rescan(&mut poller);
+        assert_eq!(history.len(), 2);
+        assert!(history[0].description.as_deref().unwrap().starts_with("Assumed delivered"));
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // A real scan replaces the inferred delivery and the package is active again
+        let history = rescan(&mut poller);
+        let descriptions: Vec<_> = history.iter().map(|e| e.description.as_deref().unwrap()).collect();
+        assert_eq!(descriptions, ["Departed facility", "Out for Delivery"]);
+        assert_eq!(poller.db.get_active_packages().unwrap().len(), 1);
+        assert_eq!(received.lock().unwrap()[1], "ups 1ZAAA is now in_transit");
+    }
+
+    /// Courier that returns the next scripted status on each check.
+    struct ScriptedCourier {
+        responses: Mutex<Vec<CourierStatus>>,
+    }
+
+    impl CourierClient for ScriptedCourier {
+        fn check_status(&self, _package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            Ok(vec![self.responses.lock().unwrap().remove(0)])
+        }
+    }
+
+    fn scan(status: &str, code: &str, description: &str) -> CourierStatus {
+        CourierStatus {
+            status: status.into(),
+            estimated_arrival_date: None,
+            last_known_location: None,
+            description: Some(description.into()),
+            checked_at: None,
+            delivery_location_detail: None,
+            courier_status_text: None,
+            courier_status_code: Some(code.into()),
+        }
+    }
+
+    #[test]
+    fn notifies_when_usps_accepts_a_pre_shipment_package() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&NewPackage {
+            courier: "usps".into(),
+            ..new_package("9400111206206406260787")
+        })
+        .unwrap();
+        let (notifiers, received) = recording_notifiers();
+        let mut poller = status_poller(
+            StatusPollerConfig { short_descriptions: true, ..StatusPollerConfig::default() },
+            NotifyConfig::default(),
+            db,
+            ScriptedCourier {
+                responses: Mutex::new(vec![
+                    scan("waiting", "Pre-Shipment", "Label created, not yet accepted by USPS"),
+                    scan("in_transit", "Accepted", "Accepted by USPS"),
+                ]),
+            },
+            notifiers,
+        );
+
+        // Pre-shipment is still waiting: nothing to announce
+        poller.poll_once();
+        assert!(received.lock().unwrap().is_empty());
+
+        poller.poll_once();
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec!["usps 9400111206206406260787 is now in_transit"]
+        );
+        let package = poller.db.get_active_packages().unwrap().remove(0);
+        let history = poller.db.get_package_status_history(package.id).unwrap();
+        assert_eq!(history[0].short_description.as_deref(), Some("Picked up"));
+        assert_eq!(history[1].short_description.as_deref(), Some("Label created"));
+    }
+
+    #[test]
+    fn records_every_courier_event_and_takes_status_from_the_newest() {
+        let mut db = db_with(&["1ZAAA"]);
+        let package = db.get_active_packages().unwrap().remove(0);
+        let statuses = [
+            scan("waiting", "M", "Label created"),
+            scan("in_transit", "I", "Departed facility"),
+            scan("delivered", "D", "Delivered"),
+        ];
+
+        record_statuses(&mut db, &package, &statuses, false);
+
+        let history = db.get_package_status_history(package.id).unwrap();
+        let descriptions: Vec<_> = history.iter().map(|e| e.description.as_deref().unwrap()).collect();
+        assert_eq!(descriptions, ["Delivered", "Departed facility", "Label created"]);
+        assert_eq!(db.get_package_with_status(package.id).unwrap().unwrap().status, "delivered");
+        // Short descriptions are off by default
+        assert!(history.iter().all(|e| e.short_description.is_none()));
+    }
+
+    #[test]
+    fn backfills_unscanned_then_soonest_eta_first_with_cap_and_delay() {
+        let config = StatusPollerConfig {
+            backfill: Some(BackfillConfig { unscanned_threshold: 3, max_per_cycle: 5, delay_ms: 50 }),
+            ..StatusPollerConfig::default()
+        };
+        let (mut poller, checked) = poller(config, &["1ZNONE", "1ZLATE", "1ZSOON", "1ZNEW1", "1ZNEW2"]);
+        for (number, eta) in [("1ZLATE", "2026-03-09"), ("1ZSOON", "2026-03-02")] {
+            let id = poller.db.get_by_tracking_number(number).unwrap().unwrap().id;
+            let details = StatusDetails { estimated_arrival_date: Some(eta), ..Default::default() };
+            poller.db.insert_package_status(id, &PackageStatus::InTransit, &details).unwrap();
+        }
+
+        let started = std::time::Instant::now();
+        poller.poll_at_hour(12);
+
+        assert_eq!(*checked.lock().unwrap(), vec!["1ZNONE", "1ZNEW1", "1ZNEW2", "1ZSOON", "1ZLATE"]);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // Below the threshold, steady-state polling checks everything again
+        checked.lock().unwrap().clear();
+        poller.poll_at_hour(12);
+        assert_eq!(checked.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn backfill_reaches_unscanned_packages_behind_a_full_cycle_of_etas() {
+        let config = StatusPollerConfig {
+            backfill: Some(BackfillConfig { unscanned_threshold: 1, max_per_cycle: 2, delay_ms: 0 }),
+            ..StatusPollerConfig::default()
+        };
+        let (mut poller, checked) = poller(config, &["1ZETA1", "1ZETA2", "1ZETA3", "1ZNEW"]);
+        for number in ["1ZETA1", "1ZETA2", "1ZETA3"] {
+            let id = poller.db.get_by_tracking_number(number).unwrap().unwrap().id;
+            let details = StatusDetails { estimated_arrival_date: Some("2026-03-02"), ..Default::default() };
+            poller.db.insert_package_status(id, &PackageStatus::InTransit, &details).unwrap();
+        }
+
+        poller.poll_at_hour(12);
+        assert_eq!(*checked.lock().unwrap(), vec!["1ZNEW", "1ZETA1"]);
+        assert!(poller.db.get_unscanned_packages().unwrap().is_empty());
+
+        // Backfill is over, so the next poll checks everything
+        checked.lock().unwrap().clear();
+        poller.poll_at_hour(12);
+        assert_eq!(checked.lock().unwrap().len(), 4);
+    }
+
+    /// Courier that fails for the listed tracking numbers and reports the
+    /// rest in transit.
+    struct PartlyFailingCourier {
+        failing: Vec<&'static str>,
+    }
+
+    impl CourierClient for PartlyFailingCourier {
+        fn check_status(&self, package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            if self.failing.contains(&package.tracking_number.as_str()) {
+                return Err(CourierError::Unavailable.into());
+            }
+            Ok(vec![scan("in_transit", "I", "Departed facility")])
+        }
+    }
+
+    #[test]
+    fn poll_report_tallies_courier_outcomes() {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        for (number, courier) in [("1ZAAA", "ups"), ("1ZBBB", "ups"), ("986578788855", "fedex"), ("986578788856", "fedex")] {
+            db.insert_package(&NewPackage { courier: courier.into(), ..new_package(number) }).unwrap();
+        }
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig::default(),
+            db,
+            PartlyFailingCourier { failing: vec!["1ZBBB", "986578788855", "986578788856"] },
+            Arc::default(),
+        );
+        let handle = poller.report_handle();
+
+        let report = poller.poll_once();
+
+        assert_eq!(report.checked, 4);
+        assert_eq!(report.updated, 1);
+        assert_eq!(report.failed_by_courier, BTreeMap::from([("fedex".into(), 2), ("ups".into(), 1)]));
+        assert_eq!(handle.lock().unwrap().as_ref(), Some(&report));
+    }
+
+    #[test]
+    fn services_queued_rescans_between_polls() {
+        let (mut poller, checked) = poller(StatusPollerConfig::default(), &["1ZQUEUED", "1ZOTHER"]);
+        let id = poller.db.get_by_tracking_number("1ZQUEUED").unwrap().unwrap().id;
+
+        poller.rescan_queue().try_send(RescanRequest { package_id: id, clear: false }).unwrap();
+        poller.drain_rescans();
+
+        assert_eq!(*checked.lock().unwrap(), vec!["1ZQUEUED"]);
+        assert_eq!(poller.report, PollReport::default());
+    }
+
+    #[test]
+    fn queued_rescan_clears_history_and_restarts_adaptive_backoff() {
+        let adaptive = AdaptivePollingConfig { base_interval_seconds: 3600, max_interval_seconds: 6 * 3600 };
+        let config = StatusPollerConfig { adaptive: Some(adaptive), ..StatusPollerConfig::default() };
+        let (mut poller, _) = poller(config, &["1ZAAA"]);
+        let package = poller.db.get_active_packages().unwrap().remove(0);
+        let stale = StatusDetails { description: Some("Stale scan"), ..Default::default() };
+        poller.db.insert_package_status(package.id, &PackageStatus::InTransit, &stale).unwrap();
+        for _ in 0..3 {
+            poller.check_package(&package);
+        }
+
+        poller.rescan_queue().try_send(RescanRequest { package_id: package.id, clear: true }).unwrap();
+        poller.drain_rescans();
+
+        let history = poller.db.get_package_status_history(package.id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].description, None);
+        assert_eq!(poller.recorder.backoff.lock().unwrap()[&package.id].unchanged, 0);
+    }
+
+    #[test]
+    fn checks_packages_concurrently_with_async_requests() {
+        let config = StatusPollerConfig {
+            async_requests: Some(AsyncRequestsConfig { max_concurrent: 2 }),
+            ..StatusPollerConfig::default()
+        };
+        let (mut poller, checked) = poller(config, &["1ZONE", "1ZTWO", "1ZTHREE"]);
+
+        let report = poller.poll_once();
+
+        let mut checked = checked.lock().unwrap().clone();
+        checked.sort();
+        assert_eq!(checked, vec!["1ZONE", "1ZTHREE", "1ZTWO"]);
+        assert_eq!(report.checked, 3);
+        assert!(report.failed_by_courier.is_empty());
+    }
+
+    /// Courier built on a blocking HTTP client, like the UPS web fallback.
+    struct BlockingCourier;
+
+    impl CourierClient for BlockingCourier {
+        fn check_status(&self, _package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            // Dropping a blocking client panics when done on an async task
+            drop(reqwest::blocking::Client::new());
+            thread::sleep(Duration::from_millis(300));
+            Ok(vec![scan("in_transit", "I", "Departed facility")])
+        }
+    }
+
+    #[test]
+    fn runs_blocking_couriers_concurrently_off_the_runtime() {
+        let config = StatusPollerConfig {
+            async_requests: Some(AsyncRequestsConfig { max_concurrent: 3 }),
+            ..StatusPollerConfig::default()
+        };
+        let poller = status_poller(
+            config,
+            NotifyConfig::default(),
+            SqliteDatabase::open(":memory:").unwrap(),
+            BlockingCourier,
+            Arc::default(),
+        );
+        let packages: Vec<Package> = (1..=3)
+            .map(|id| Package {
+                id,
+                tracking_number: format!("1ZBLOCKING{id}"),
+                courier: "ups".into(),
+                service: "UPS Ground".into(),
+                status: PackageStatus::InTransit,
+                destination_zip: None,
+            })
+            .collect();
+
+        let started = std::time::Instant::now();
+        let results = poller.check_concurrently(&packages, 3);
+
+        assert_eq!(results.len(), 3);
+        for result in results {
+            assert_eq!(result.unwrap()[0].status, "in_transit");
+        }
+        assert!(started.elapsed() < Duration::from_millis(800), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn notifies_delivery_only_after_two_consecutive_delivered_polls() {
+        let db = db_with(&["1ZSPURIOUS"]);
+        let (notifiers, received) = recording_notifiers();
+        let mut poller = status_poller(
+            StatusPollerConfig { confirm_delivered: true, ..StatusPollerConfig::default() },
+            NotifyConfig::default(),
+            db,
+            ScriptedCourier {
+                responses: Mutex::new(vec![
+                    scan("in_transit", "I", "On the way"),
+                    scan("delivered", "D", "Delivered"),
+                    scan("in_transit", "I", "On the way"),
+                    scan("delivered", "D", "Delivered"),
+                    scan("delivered", "D", "Delivered"),
+                ]),
+            },
+            notifiers,
+        );
+
+        poller.poll_once();
+        received.lock().unwrap().clear();
+
+        // A lone delivered scan that reverts is never announced
+        poller.poll_once();
+        poller.poll_once();
+        assert!(received.lock().unwrap().is_empty());
+
+        poller.poll_once();
+        assert!(received.lock().unwrap().is_empty());
+        assert_eq!(poller.db.get_active_packages().unwrap().len(), 1);
+
+        poller.poll_once();
+        assert_eq!(*received.lock().unwrap(), vec!["ups 1ZSPURIOUS is now delivered"]);
+        assert!(poller.db.get_active_packages().unwrap().is_empty());
+    }
+
+    /// Serve `POST /hook` on a background thread, recording each JSON body.
+    #[test]
+    fn rescan_and_poll_share_delivery_confirmation() {
+        let db = db_with(&["1ZRESCAN"]);
+        let mut poller = status_poller(
+            StatusPollerConfig { confirm_delivered: true, ..StatusPollerConfig::default() },
+            NotifyConfig::default(),
+            db,
+            ScriptedCourier { responses: Mutex::new(vec![scan("delivered", "D", "Delivered")]) },
+            Arc::default(),
+        );
+        let package = poller.db.get_active_packages().unwrap().remove(0);
+
+        // A rescan's delivered scan waits for confirmation like a poll's
+        let recorder = poller.handle().recorder;
+        let outcome = recorder.record_rescan(poller.db.as_mut(), &package, Ok(vec![scan("delivered", "D", "Delivered")]), false);
+        assert!(matches!(outcome, CheckOutcome::Recorded(None)));
+        assert_eq!(poller.db.get_active_packages().unwrap().len(), 1);
+
+        poller.poll_once();
+        assert!(poller.db.get_active_packages().unwrap().is_empty());
+    }
+
+    #[test]
+    fn posts_webhook_only_when_status_changes() {
+        let (url, received) = webhook_server();
+        let db = db_with(&["1ZHOOK"]);
+        let webhook = crate::notify::webhook::WebhookNotifier::new(&crate::config::WebhookConfig {
+            url,
+            timeout_seconds: 5,
+        });
+        let mut in_transit = scan("in_transit", "I", "Departed facility");
+        in_transit.last_known_location = Some("Memphis, TN".into());
+        in_transit.checked_at = Some("2026-03-01T10:00:00Z".into());
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig::default(),
+            db,
+            ScriptedCourier {
+                responses: Mutex::new(vec![in_transit, scan("in_transit", "I", "Arrived at facility")]),
+            },
+            Arc::new(NotifierSet::from_pointee(vec![Box::new(webhook)])),
+        );
+
+        poller.poll_once();
+        poller.poll_once();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![serde_json::json!({
+                "tracking_number": "1ZHOOK",
+                "courier": "ups",
+                "old_status": "waiting",
+                "new_status": "in_transit",
+                "location": "Memphis, TN",
+                "checked_at": "2026-03-01T10:00:00Z",
+            })]
+        );
+    }
+
+    struct UnreachableMailServer;
+
+    impl crate::notify::smtp::MailSender for UnreachableMailServer {
+        fn send(&self, _message: &lettre::Message) -> anyhow::Result<()> {
+            anyhow::bail!("connection refused")
+        }
+    }
+
+    #[test]
+    fn records_delivery_even_when_delivery_email_fails() {
+        let db = db_with(&["1ZMAIL"]);
+        let smtp = crate::notify::smtp::SmtpNotifier::with_sender(
+            Box::new(UnreachableMailServer),
+            "trackage@example.com".parse().unwrap(),
+            "me@example.com".parse().unwrap(),
+        );
+        let mut poller = status_poller(
+            StatusPollerConfig::default(),
+            NotifyConfig::default(),
+            db,
+            ScriptedCourier { responses: Mutex::new(vec![scan("delivered", "D", "Delivered")]) },
+            Arc::new(NotifierSet::from_pointee(vec![Box::new(smtp)])),
+        );
+
+        poller.poll_once();
+
+        assert_eq!(poller.db.get_by_tracking_number("1ZMAIL").unwrap().unwrap().status, "delivered");
+        assert!(poller.db.get_active_packages().unwrap().is_empty());
     }
 }
@@ -0,0 +1,103 @@
+//! Fixtures shared by the unit tests of several modules.
+
+use crate::config::{NotifyConfig, StatusPollerConfig};
+use crate::courier::CourierClient;
+use crate::db::{Database, NewPackage, SourceConfidence, SqliteDatabase};
+use crate::notify::{Notifier, NotifierSet, NotifyEvent};
+use crate::status_poller::StatusPoller;
+use chrono::Utc;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+/// A UPS Ground package with a guessed courier and no source email details.
+pub fn new_package(tracking_number: &str) -> NewPackage {
+    NewPackage {
+        tracking_number: tracking_number.into(),
+        courier: "ups".into(),
+        service: "UPS Ground".into(),
+        tracking_url: String::new(),
+        source_email_uid: 0,
+        source_email_subject: None,
+        source_email_from: None,
+        source_email_date: Utc::now(),
+        source_confidence: SourceConfidence::Guessed,
+        destination_zip: None,
+        description: None,
+    }
+}
+
+/// An in-memory database holding a [`new_package`] for each tracking number.
+pub fn db_with(tracking_numbers: &[&str]) -> SqliteDatabase {
+    let mut db = SqliteDatabase::open(":memory:").unwrap();
+    for number in tracking_numbers {
+        db.insert_package(&new_package(number)).unwrap();
+    }
+    db
+}
+
+/// Notifier that records the summary of every event it is sent.
+pub struct RecordingNotifier {
+    pub received: Arc<Mutex<Vec<String>>>,
+}
+
+impl Notifier for RecordingNotifier {
+    fn name(&self) -> &str {
+        "recording"
+    }
+
+    fn notify(&self, event: &NotifyEvent) -> anyhow::Result<()> {
+        self.received.lock().unwrap().push(event.summary());
+        Ok(())
+    }
+}
+
+/// A notifier set with one [`RecordingNotifier`], and what it receives.
+pub fn recording_notifiers() -> (Arc<NotifierSet>, Arc<Mutex<Vec<String>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let notifier = RecordingNotifier { received: Arc::clone(&received) };
+    (Arc::new(NotifierSet::from_pointee(vec![Box::new(notifier)])), received)
+}
+
+/// A running status poller over `db`.
+pub fn status_poller(
+    config: StatusPollerConfig,
+    notify_config: NotifyConfig,
+    db: SqliteDatabase,
+    courier: impl CourierClient,
+    notifiers: Arc<NotifierSet>,
+) -> StatusPoller {
+    StatusPoller::new(config, notify_config, Box::new(db), Arc::new(courier), notifiers, Arc::new(AtomicBool::new(true)))
+}
+
+/// Serve `app` on a random local port from a background thread, returning
+/// its base URL (`http://127.0.0.1:<port>`, without a trailing slash).
+pub fn serve(app: axum::Router) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, app).await.unwrap();
+        });
+    });
+
+    url
+}
+
+/// A webhook endpoint that records each JSON body POSTed to it. Returns the
+/// endpoint URL and the bodies received.
+pub fn webhook_server() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&received);
+    let app = axum::Router::new().route(
+        "/hook",
+        axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+            recorded.lock().unwrap().push(body);
+        }),
+    );
+
+    (format!("{}/hook", serve(app)), received)
+}
@@ -1,17 +1,17 @@
-/// Date/time utilities for normalizing courier-provided timestamps.
-///
-/// All dates stored in the database MUST be in one of two formats:
-///   - **Timestamps**: RFC 3339 UTC — `2026-02-25T11:26:00Z`
-///   - **Date-only**:  ISO 8601 date — `2026-03-02`
-///
-/// Courier APIs return dates in varied formats. Use the helpers here to
-/// normalize them before returning a `CourierStatus`. If a courier provides
-/// date components (year, month, day, hour, minute, second), use
-/// [`format_rfc3339_utc`]. If the API returns a compact `YYYYMMDD` string,
-/// use [`parse_date_yyyymmdd`].
-///
-/// The frontend parses these via `new Date()` and formats them with
-/// `Intl.DateTimeFormat` in the browser's local timezone.
+//! Date/time utilities for normalizing courier-provided timestamps.
+//!
+//! All dates stored in the database MUST be in one of two formats:
+//!   - **Timestamps**: RFC 3339 UTC — `2026-02-25T11:26:00Z`
+//!   - **Date-only**:  ISO 8601 date — `2026-03-02`
+//!
+//! Courier APIs return dates in varied formats. Use the helpers here to
+//! normalize them before returning a `CourierStatus`. If a courier provides
+//! date components (year, month, day, hour, minute, second), use
+//! [`format_rfc3339_utc`]. If the API returns a compact `YYYYMMDD` string,
+//! use [`parse_date_yyyymmdd`].
+//!
+//! The frontend parses these via `new Date()` and formats them with
+//! `Intl.DateTimeFormat` in the browser's local timezone.
 
 /// Parse a compact `YYYYMMDD` date string into ISO 8601 date format (`YYYY-MM-DD`).
 ///
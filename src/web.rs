@@ -1,32 +1,131 @@
-use crate::db::{Database, NewPackage, SqliteDatabase};
+use crate::config::{DatabaseConfig, WebConfig};
+use crate::courier::CourierClient;
+use crate::db::{Database, ExportRow, NewPackage, PackageWithStatus, SourceConfidence, SqliteDatabase};
+use crate::extractors::track_number;
+use crate::metrics::Metrics;
+use crate::status_poller::{CheckOutcome, PollReport, PollReportHandle, PollerHandle, RescanQueue, RescanRequest, StatusRecorder};
 use axum::{
-    Router,
-    extract::{Path, State},
+    BoxError, Router,
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::{FromRef, Path, Query, Request, State},
     http::{StatusCode, header},
+    middleware::{self, Next},
     response::{IntoResponse, Json, Response},
     routing::{delete, get, post},
 };
 use chrono::Utc;
+use futures_util::stream;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
 use std::sync::{
     Arc, Mutex,
     atomic::{AtomicBool, Ordering},
 };
-use tracking_numbers::track;
-use tracing::{error, info};
+use std::time::Duration;
+use tower::ServiceBuilder;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tracing::{error, info, warn};
 
 const INDEX_HTML: &str = include_str!("../static/index.html");
 
+/// How long a synchronous courier lookup may take before the request gives up.
+const COURIER_TIMEOUT: Duration = Duration::from_secs(30);
+
 type Db = Arc<Mutex<SqliteDatabase>>;
 
+#[derive(Clone)]
+struct AppState {
+    db: Db,
+    /// Read-only reporting database: the replica when configured, otherwise the primary.
+    reports: Db,
+    courier: Arc<dyn CourierClient>,
+    courier_timeout: Duration,
+    poll_report: PollReportHandle,
+    rescans: RescanQueue,
+    /// Records synchronous rescans by the same rules as the status poller.
+    recorder: StatusRecorder,
+    metrics: Arc<Metrics>,
+    /// Token every request except `/healthz` and `/api/version` must carry,
+    /// when configured.
+    auth_token: Option<Arc<str>>,
+}
+
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.db)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenParams {
+    token: Option<String>,
+}
+
+/// Reject requests that don't carry the configured token as an
+/// `Authorization: Bearer` header. The web UI page itself also accepts
+/// `?token=`, so it can be opened from a bookmark; keeping it off the API
+/// routes keeps the token out of their access logs and referrers.
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(expected) = &state.auth_token else {
+        return next.run(request).await;
+    };
+
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let query = if request.uri().path() == "/" {
+        Query::<TokenParams>::try_from_uri(request.uri()).ok().and_then(|Query(params)| params.token)
+    } else {
+        None
+    };
+
+    if bearer.or(query).is_some_and(|token| tokens_match(&token, expected)) {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
+/// Compare tokens without returning early, so response times don't reveal
+/// how much of a guess was right.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
 async fn index() -> Response {
     ([(header::CONTENT_TYPE, "text/html")], INDEX_HTML).into_response()
 }
 
-async fn api_packages(State(db): State<Db>) -> Response {
+#[derive(Deserialize)]
+struct ListParams {
+    /// Include the compact one-line `summary` for each package.
+    #[serde(default)]
+    summary: bool,
+    /// Also list soft-deleted packages, so they can be restored.
+    #[serde(default)]
+    include_deleted: bool,
+}
+
+async fn api_packages(State(db): State<Db>, Query(params): Query<ListParams>) -> Response {
     let db = db.lock().unwrap();
-    match db.get_all_packages_with_status() {
-        Ok(packages) => Json(packages).into_response(),
+    let packages = if params.include_deleted {
+        db.get_all_packages_with_status_including_deleted()
+    } else {
+        db.get_all_packages_with_status()
+    };
+    match packages {
+        Ok(mut packages) => {
+            if params.summary {
+                packages.iter_mut().for_each(PackageWithStatus::fill_summary);
+            }
+            Json(packages).into_response()
+        }
         Err(err) => {
             error!(error = %err, "Failed to query packages");
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
@@ -34,6 +133,141 @@ async fn api_packages(State(db): State<Db>) -> Response {
     }
 }
 
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+struct ExportParams {
+    #[serde(default)]
+    format: ExportFormat,
+    /// Also export soft-deleted packages.
+    #[serde(default)]
+    include_deleted: bool,
+}
+
+const EXPORT_COLUMNS: [&str; 16] = [
+    "id",
+    "tracking_number",
+    "courier",
+    "service",
+    "description",
+    "status",
+    "courier_status_text",
+    "last_known_location",
+    "estimated_arrival_date",
+    "status_checked_at",
+    "tracking_url",
+    "created_at",
+    "source_email_subject",
+    "source_email_from",
+    "source_email_date",
+    "deleted_at",
+];
+
+async fn api_export(State(state): State<AppState>, Query(params): Query<ExportParams>) -> Response {
+    let rows = match state.reports.lock().unwrap().export_packages(params.include_deleted) {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!(error = %err, "Failed to export packages");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // Rows are encoded one at a time as the response body is sent
+    match params.format {
+        ExportFormat::Json => {
+            let rows = rows.into_iter().enumerate().map(|(i, row)| {
+                let separator = if i == 0 { "" } else { "," };
+                format!("{separator}{}", serde_json::to_string(&row).unwrap_or_default())
+            });
+            let chunks = std::iter::once("[".to_string()).chain(rows).chain(std::iter::once("]".to_string()));
+            (
+                [
+                    (header::CONTENT_TYPE, "application/json"),
+                    (header::CONTENT_DISPOSITION, "attachment; filename=\"trackage-export.json\""),
+                ],
+                streamed(chunks),
+            )
+                .into_response()
+        }
+        ExportFormat::Csv => {
+            let rows = rows.into_iter().map(|row| export_csv_record(&row));
+            (
+                [
+                    (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                    (header::CONTENT_DISPOSITION, "attachment; filename=\"trackage-export.csv\""),
+                ],
+                streamed(std::iter::once(csv_record(EXPORT_COLUMNS)).chain(rows)),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// A response body sent chunk by chunk as `chunks` produces them.
+fn streamed(chunks: impl Iterator<Item = String> + Send + 'static) -> Body {
+    Body::from_stream(stream::iter(chunks.map(Ok::<_, Infallible>)))
+}
+
+/// One export row as a CSV line.
+fn export_csv_record(row: &ExportRow) -> String {
+    fn optional(value: &Option<String>) -> &str {
+        value.as_deref().unwrap_or_default()
+    }
+
+    let id = row.id.to_string();
+    csv_record([
+        &id,
+        &row.tracking_number,
+        &row.courier,
+        &row.service,
+        optional(&row.description),
+        &row.status,
+        optional(&row.courier_status_text),
+        optional(&row.last_known_location),
+        optional(&row.estimated_arrival_date),
+        optional(&row.status_checked_at),
+        optional(&row.tracking_url),
+        &row.created_at,
+        optional(&row.source_email_subject),
+        optional(&row.source_email_from),
+        optional(&row.source_email_date),
+        optional(&row.deleted_at),
+    ])
+}
+
+/// One CSV line, per RFC 4180. Fields containing a comma, quote, or line
+/// break are quoted, with quotes inside them doubled. Fields a spreadsheet
+/// would read as a formula (starting with `=`, `+`, `-`, `@`, tab or carriage
+/// return) are prefixed with `'` so they open as text.
+fn csv_record(fields: [&str; EXPORT_COLUMNS.len()]) -> String {
+    let mut csv = String::new();
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+            format!("'{field}")
+        } else {
+            field.to_string()
+        };
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(&field);
+        }
+    }
+    csv.push_str("\r\n");
+    csv
+}
+
 #[derive(Deserialize)]
 struct ValidateRequest {
     tracking_number: String,
@@ -56,7 +290,7 @@ async fn api_validate(Json(req): Json<ValidateRequest>) -> Json<Vec<TrackingMatc
         .filter(|c| !c.is_whitespace())
         .collect();
 
-    let matches = match track(&cleaned) {
+    let matches = match track_number(&cleaned) {
         Some(result) => vec![TrackingMatch {
             tracking_number: result.tracking_number,
             courier: result.courier,
@@ -75,6 +309,8 @@ struct AddPackageRequest {
     courier: String,
     service: String,
     tracking_url: String,
+    #[serde(default)]
+    destination_zip: Option<String>,
 }
 
 async fn api_add_package(State(db): State<Db>, Json(req): Json<AddPackageRequest>) -> Response {
@@ -87,6 +323,9 @@ async fn api_add_package(State(db): State<Db>, Json(req): Json<AddPackageRequest
         source_email_subject: None,
         source_email_from: None,
         source_email_date: Utc::now(),
+        source_confidence: SourceConfidence::Manual,
+        destination_zip: req.destination_zip.filter(|zip| !zip.trim().is_empty()),
+        description: None,
     };
 
     let mut db = db.lock().unwrap();
@@ -100,6 +339,19 @@ async fn api_add_package(State(db): State<Db>, Json(req): Json<AddPackageRequest
     }
 }
 
+async fn api_package_by_number(State(db): State<Db>, Path(number): Path<String>) -> Response {
+    let number: String = number.split_whitespace().collect::<String>().to_uppercase();
+    let db = db.lock().unwrap();
+    match db.get_by_tracking_number(&number) {
+        Ok(Some(package)) => Json(package).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(error = %err, tracking_number = %number, "Failed to query package by tracking number");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn api_delete_package(State(db): State<Db>, Path(id): Path<i64>) -> Response {
     let mut db = db.lock().unwrap();
     match db.delete_package(id) {
@@ -112,6 +364,18 @@ async fn api_delete_package(State(db): State<Db>, Path(id): Path<i64>) -> Respon
     }
 }
 
+async fn api_restore_package(State(db): State<Db>, Path(id): Path<i64>) -> Response {
+    let mut db = db.lock().unwrap();
+    match db.restore_package(id) {
+        Ok(true) => StatusCode::OK.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(error = %err, package_id = id, "Failed to restore package");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn api_package_history(State(db): State<Db>, Path(id): Path<i64>) -> Response {
     let db = db.lock().unwrap();
     match db.get_package_status_history(id) {
@@ -123,34 +387,345 @@ async fn api_package_history(State(db): State<Db>, Path(id): Path<i64>) -> Respo
     }
 }
 
-async fn api_package_rescan(State(db): State<Db>, Path(id): Path<i64>) -> Response {
-    let mut db = db.lock().unwrap();
-    match db.delete_all_package_status(id) {
-        Ok(_) => StatusCode::ACCEPTED.into_response(),
+#[derive(Serialize)]
+struct Stats {
+    total: usize,
+    by_status: BTreeMap<String, usize>,
+}
+
+async fn api_stats(State(state): State<AppState>) -> Response {
+    let db = state.reports.lock().unwrap();
+    match db.get_all_packages_with_status() {
+        Ok(packages) => {
+            let mut by_status = BTreeMap::new();
+            for package in &packages {
+                *by_status.entry(package.status.clone()).or_insert(0) += 1;
+            }
+            Json(Stats {
+                total: packages.len(),
+                by_status,
+            })
+            .into_response()
+        }
         Err(err) => {
-            error!(error = %err, package_id = id, "Failed to delete all package history");
+            error!(error = %err, "Failed to query package stats");
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
 
-pub fn start(db_path: String, port: u16, running: Arc<AtomicBool>) {
-    let db = match SqliteDatabase::open(&db_path) {
-        Ok(db) => Arc::new(Mutex::new(db)),
+async fn api_courier_distribution(State(state): State<AppState>) -> Response {
+    let db = state.reports.lock().unwrap();
+    match db.courier_distribution() {
+        Ok(counts) => Json(counts).into_response(),
         Err(err) => {
-            error!(error = %err, "Web server failed to open database");
-            return;
+            error!(error = %err, "Failed to query courier distribution");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    git_commit: Option<&'static str>,
+    schema_version: u32,
+}
+
+async fn api_version(State(db): State<Db>) -> Response {
+    let db = db.lock().unwrap();
+    match db.schema_version() {
+        Ok(schema_version) => Json(VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            // Set at build time, e.g. `TRACKAGE_GIT_COMMIT=$(git rev-parse --short HEAD) cargo build`
+            git_commit: option_env!("TRACKAGE_GIT_COMMIT").filter(|commit| !commit.is_empty()),
+            schema_version,
+        })
+        .into_response(),
+        Err(err) => {
+            error!(error = %err, "Failed to read schema version");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    /// The latest status poll's report; `None` until the first poll finishes.
+    last_status_poll: Option<PollReport>,
+}
+
+async fn api_health(State(state): State<AppState>) -> Json<Health> {
+    Json(Health {
+        last_status_poll: state.poll_report.lock().unwrap().clone(),
+    })
+}
+
+#[derive(Serialize)]
+struct Healthz {
+    status: &'static str,
+    db: &'static str,
+}
+
+/// Liveness probe for container orchestration: cheap, unauthenticated, and
+/// 503 when the database can't answer a trivial query.
+async fn healthz(State(db): State<Db>) -> Response {
+    let db_ok = match db.lock() {
+        Ok(db) => match db.ping() {
+            Ok(()) => true,
+            Err(err) => {
+                warn!(error = %err, "Health check database query failed");
+                false
+            }
+        },
+        Err(_) => {
+            warn!("Health check found the database lock poisoned");
+            false
+        }
+    };
+
+    if db_ok {
+        Json(Healthz { status: "ok", db: "ok" }).into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(Healthz { status: "error", db: "error" })).into_response()
+    }
+}
+
+async fn api_package_route(State(db): State<Db>, Path(id): Path<i64>) -> Response {
+    let db = db.lock().unwrap();
+    match db.location_path(id) {
+        Ok(locations) => Json(locations).into_response(),
+        Err(err) => {
+            error!(error = %err, package_id = id, "Failed to query package route");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Operational metrics in the Prometheus text format.
+async fn metrics(State(state): State<AppState>) -> Response {
+    let active = match state.db.lock().unwrap().get_active_packages() {
+        Ok(packages) => packages.len(),
+        Err(err) => {
+            error!(error = %err, "Failed to count active packages");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(active),
+    )
+        .into_response()
+}
+
+/// Orders confirmed by email whose tracking number hasn't arrived yet.
+async fn api_awaiting_orders(State(db): State<Db>) -> Response {
+    let db = db.lock().unwrap();
+    match db.get_awaiting_orders() {
+        Ok(orders) => Json(orders).into_response(),
+        Err(err) => {
+            error!(error = %err, "Failed to query awaiting orders");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// A package with its status, ETA, route, source email and history in one
+/// response, so clients don't have to stitch several endpoints together.
+async fn api_shipment(State(db): State<Db>, Path(id): Path<i64>) -> Response {
+    let db = db.lock().unwrap();
+    match db.get_shipment(id) {
+        Ok(Some(shipment)) => Json(shipment).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(error = %err, package_id = id, "Failed to query shipment");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// The email a package was found in. 404 for packages added by hand.
+async fn api_package_source(State(db): State<Db>, Path(id): Path<i64>) -> Response {
+    let db = db.lock().unwrap();
+    match db.get_package_source(id) {
+        Ok(Some(source)) => Json(source).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(error = %err, package_id = id, "Failed to query package source email");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RescanParams {
+    #[serde(default)]
+    clear: bool,
+    #[serde(default)]
+    queue: bool,
+}
+
+/// Re-check a package with its courier and return the fresh status. The
+/// result is recorded as a poll would record it: delivery confirmation,
+/// backoff and notifications all apply. With `?clear=true` the existing
+/// history is discarded before the new results are recorded. If the courier
+/// fails, the last-known status is returned with a 502; if it is slow, with a
+/// 504, and the result is recorded once it arrives. With `?queue=true` the
+/// check is handed to the status poller instead, which records it the same
+/// way, and the request returns 202 straight away.
+async fn api_package_rescan(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<RescanParams>,
+) -> Response {
+    let package = match state.db.lock().unwrap().get_package(id) {
+        Ok(Some(package)) => package,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(error = %err, package_id = id, "Failed to query package");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
-    let app = Router::new()
+    if params.queue {
+        return match state.rescans.try_send(RescanRequest { package_id: id, clear: params.clear }) {
+            Ok(()) => (StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id }))).into_response(),
+            Err(err) => {
+                warn!(error = %err, package_id = id, "Failed to queue rescan");
+                StatusCode::SERVICE_UNAVAILABLE.into_response()
+            }
+        };
+    }
+
+    let courier = Arc::clone(&state.courier);
+    let db = Arc::clone(&state.db);
+    let recorder = state.recorder.clone();
+    let check = tokio::task::spawn_blocking(move || {
+        let result = courier.check_status(&package);
+        let mut db = db.lock().unwrap();
+        recorder.record_rescan(&mut *db, &package, result, params.clear)
+    });
+
+    let status_code = match tokio::time::timeout(state.courier_timeout, check).await {
+        Ok(Ok(CheckOutcome::Recorded(_))) => StatusCode::OK,
+        Ok(Ok(CheckOutcome::Failed | CheckOutcome::Skipped)) => StatusCode::BAD_GATEWAY,
+        Ok(Err(err)) => {
+            error!(error = %err, package_id = id, "Courier status check panicked");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        Err(_) => {
+            warn!(package_id = id, "Courier status check timed out");
+            StatusCode::GATEWAY_TIMEOUT
+        }
+    };
+
+    match state.db.lock().unwrap().get_package_with_status(id) {
+        Ok(Some(package)) => (status_code, Json(package)).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            error!(error = %err, package_id = id, "Failed to query package");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn build_router(state: AppState, max_connections: Option<usize>) -> Router {
+    // Probes are merged in last so layers on the API routes never apply to them
+    let probes = Router::new().route("/healthz", get(healthz)).with_state(state.clone());
+    // Open without the token, but still under the connection limit
+    let public = Router::new().route("/api/version", get(api_version)).with_state(state.clone());
+
+    let router = Router::new()
         .route("/", get(index))
+        .route("/api/stats", get(api_stats))
+        .route("/api/health", get(api_health))
+        .route("/metrics", get(metrics))
         .route("/api/packages", get(api_packages).post(api_add_package))
         .route("/api/packages/validate", post(api_validate))
+        .route("/api/packages/export", get(api_export))
+        .route("/api/packages/by-courier", get(api_courier_distribution))
+        .route("/api/packages/by-number/{number}", get(api_package_by_number))
         .route("/api/packages/{id}", delete(api_delete_package))
         .route("/api/packages/{id}/history", get(api_package_history))
+        .route("/api/packages/{id}/route", get(api_package_route))
+        .route("/api/packages/{id}/source", get(api_package_source))
         .route("/api/packages/{id}/rescan", post(api_package_rescan))
-        .with_state(db);
+        .route("/api/packages/{id}/restore", post(api_restore_package))
+        .route("/api/shipments/{id}", get(api_shipment))
+        .route("/api/orders/awaiting", get(api_awaiting_orders))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+        .merge(public);
+
+    let router = match max_connections {
+        // Shed excess requests with a 503 instead of queueing them
+        Some(limit) => router.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_: BoxError| async { StatusCode::SERVICE_UNAVAILABLE }))
+                .layer(LoadShedLayer::new())
+                .layer(GlobalConcurrencyLimitLayer::new(limit)),
+        ),
+        None => router,
+    };
+
+    router.merge(probes)
+}
+
+pub fn start(
+    db_config: DatabaseConfig,
+    web_config: WebConfig,
+    courier: Arc<dyn CourierClient>,
+    poller: PollerHandle,
+    metrics: Arc<Metrics>,
+    running: Arc<AtomicBool>,
+) {
+    let db = match SqliteDatabase::from_config(&db_config) {
+        Ok(db) => Arc::new(Mutex::new(db)),
+        Err(err) => {
+            error!(error = %err, "Web server failed to open database");
+            return;
+        }
+    };
+
+    let reports = match &db_config.replica_path {
+        Some(path) => match SqliteDatabase::open_read_only(path) {
+            Ok(replica) => {
+                info!(path = %path, "Serving reporting endpoints from read-only replica");
+                Arc::new(Mutex::new(replica))
+            }
+            Err(err) => {
+                error!(error = %err, "Web server failed to open database replica");
+                return;
+            }
+        },
+        None => Arc::clone(&db),
+    };
+
+    let state = AppState {
+        db,
+        reports,
+        courier,
+        courier_timeout: COURIER_TIMEOUT,
+        poll_report: poller.report,
+        rescans: poller.rescans,
+        recorder: poller.recorder,
+        metrics,
+        auth_token: web_config.auth_token.as_deref().map(Arc::from),
+    };
+
+    if state.auth_token.is_none() {
+        warn!("web.auth_token is not set; the web UI and API are open to anyone who can reach the port");
+    }
+
+    let app = build_router(state, web_config.max_connections);
+    let addr = match web_config.socket_addr() {
+        Ok(addr) => addr,
+        Err(err) => {
+            error!(error = %err, bind_address = %web_config.bind_address, "Invalid web server bind address");
+            return;
+        }
+    };
 
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
@@ -158,15 +733,15 @@ pub fn start(db_path: String, port: u16, running: Arc<AtomicBool>) {
         .expect("Failed to create tokio runtime for web server");
 
     rt.block_on(async {
-        let listener = match tokio::net::TcpListener::bind(format!("0.0.0.0:{port}")).await {
+        let listener = match tokio::net::TcpListener::bind(addr).await {
             Ok(l) => l,
             Err(err) => {
-                error!(error = %err, port, "Web server failed to bind");
+                error!(error = %err, %addr, "Web server failed to bind");
                 return;
             }
         };
 
-        info!(port, "Web server listening");
+        info!(%addr, "Web server listening");
 
         let shutdown = async move {
             while running.load(Ordering::SeqCst) {
@@ -181,3 +756,634 @@ pub fn start(db_path: String, port: u16, running: Arc<AtomicBool>) {
             .expect("Web server error");
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{NotifyConfig, StatusPollerConfig};
+    use crate::courier::CourierStatus;
+    use crate::db::Package;
+    use crate::test_support::{new_package, recording_notifiers};
+
+    struct InTransitCourier;
+
+    impl CourierClient for InTransitCourier {
+        fn check_status(&self, _package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            Ok(vec![CourierStatus {
+                status: "in_transit".into(),
+                estimated_arrival_date: Some("2026-03-02".into()),
+                last_known_location: Some("Memphis, TN".into()),
+                description: Some("Departed FedEx hub".into()),
+                checked_at: None,
+                delivery_location_detail: None,
+                courier_status_text: Some("On the way".into()),
+                courier_status_code: None,
+            }])
+        }
+    }
+
+    struct SlowCourier;
+
+    impl CourierClient for SlowCourier {
+        fn check_status(&self, _package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(vec![])
+        }
+    }
+
+    fn state_with(courier: Arc<dyn CourierClient>) -> AppState {
+        let mut db = SqliteDatabase::open(":memory:").unwrap();
+        db.insert_package(&NewPackage {
+            courier: "fedex".into(),
+            service: "FedEx Express".into(),
+            ..new_package("986578788855")
+        })
+        .unwrap();
+
+        let db = Arc::new(Mutex::new(db));
+        AppState {
+            reports: Arc::clone(&db),
+            db,
+            courier,
+            courier_timeout: Duration::from_millis(50),
+            poll_report: Arc::default(),
+            rescans: std::sync::mpsc::sync_channel(1).0,
+            recorder: StatusRecorder::new(StatusPollerConfig::default(), NotifyConfig::default(), Arc::default()),
+            metrics: Arc::default(),
+            auth_token: None,
+        }
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn rescan_returns_fresh_status() {
+        let state = state_with(Arc::new(InTransitCourier));
+
+        let (status, body) = block_on(async {
+            let response = api_package_rescan(State(state), Path(1), Query(RescanParams { clear: false, queue: false })).await;
+            (response.status(), body_json(response).await)
+        });
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "in_transit");
+        assert_eq!(body["last_known_location"], "Memphis, TN");
+        assert_eq!(body["courier_status_text"], "On the way");
+    }
+
+    #[test]
+    fn rescan_timeout_returns_last_known_status() {
+        let state = state_with(Arc::new(SlowCourier));
+
+        let (status, body) = block_on(async {
+            let response = api_package_rescan(State(state), Path(1), Query(RescanParams { clear: true, queue: false })).await;
+            (response.status(), body_json(response).await)
+        });
+
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["status"], "waiting");
+    }
+
+    struct DeliveredCourier;
+
+    impl CourierClient for DeliveredCourier {
+        fn check_status(&self, _package: &Package) -> anyhow::Result<Vec<CourierStatus>> {
+            Ok(vec![CourierStatus {
+                status: "delivered".into(),
+                estimated_arrival_date: None,
+                last_known_location: Some("Austin, TX".into()),
+                description: Some("Delivered".into()),
+                checked_at: None,
+                delivery_location_detail: None,
+                courier_status_text: None,
+                courier_status_code: None,
+            }])
+        }
+    }
+
+    fn recording_state(courier: Arc<dyn CourierClient>, config: StatusPollerConfig) -> (AppState, Arc<Mutex<Vec<String>>>) {
+        let (notifiers, received) = recording_notifiers();
+        let recorder = StatusRecorder::new(config, NotifyConfig::default(), notifiers);
+        (AppState { recorder, courier_timeout: Duration::from_secs(5), ..state_with(courier) }, received)
+    }
+
+    #[test]
+    fn rescan_notifies_status_change() {
+        let (state, received) = recording_state(Arc::new(InTransitCourier), StatusPollerConfig::default());
+
+        let status = block_on(async {
+            api_package_rescan(State(state), Path(1), Query(RescanParams { clear: false, queue: false }))
+                .await
+                .status()
+        });
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(*received.lock().unwrap(), vec!["fedex 986578788855 is now in_transit (Memphis, TN)"]);
+    }
+
+    #[test]
+    fn rescan_holds_back_unconfirmed_delivery() {
+        let config = StatusPollerConfig { confirm_delivered: true, ..StatusPollerConfig::default() };
+        let (state, received) = recording_state(Arc::new(DeliveredCourier), config);
+
+        let rescan = |state: AppState| async move {
+            let response = api_package_rescan(State(state), Path(1), Query(RescanParams { clear: false, queue: false })).await;
+            body_json(response).await["status"].clone()
+        };
+
+        // Held back until a second check agrees, as a poll would
+        assert_eq!(block_on(rescan(state.clone())), "waiting");
+        assert!(received.lock().unwrap().is_empty());
+
+        assert_eq!(block_on(rescan(state)), "delivered");
+        assert_eq!(*received.lock().unwrap(), vec!["fedex 986578788855 is now delivered (Austin, TX)"]);
+    }
+
+    #[test]
+    fn queued_rescan_is_accepted_and_handed_to_the_poller() {
+        let (rescans, queued) = std::sync::mpsc::sync_channel(1);
+        let state = AppState { rescans, ..state_with(Arc::new(SlowCourier)) };
+
+        let (status, body) = block_on(async {
+            let response = api_package_rescan(State(state), Path(1), Query(RescanParams { clear: true, queue: true })).await;
+            (response.status(), body_json(response).await)
+        });
+
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["id"], 1);
+        assert_eq!(queued.try_recv(), Ok(RescanRequest { package_id: 1, clear: true }));
+    }
+
+    #[test]
+    fn rescan_unknown_package_is_not_found() {
+        let state = state_with(Arc::new(InTransitCourier));
+
+        let status = block_on(async {
+            api_package_rescan(State(state), Path(42), Query(RescanParams { clear: false, queue: false }))
+                .await
+                .status()
+        });
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn courier_distribution_groups_by_display_name() {
+        let state = state_with(Arc::new(InTransitCourier));
+
+        let (status, body) = block_on(async {
+            let response = api_courier_distribution(State(state)).await;
+            (response.status(), body_json(response).await)
+        });
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!([{ "courier": "FedEx", "active": 1, "total": 1 }]));
+    }
+
+    #[test]
+    fn finds_package_by_tracking_number() {
+        let state = state_with(Arc::new(InTransitCourier));
+
+        let (status, body) = block_on(async {
+            let response = api_package_by_number(State(state.db.clone()), Path("9865 7878 8855".into())).await;
+            (response.status(), body_json(response).await)
+        });
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["tracking_number"], "986578788855");
+        assert!(body.get("summary").is_none());
+
+        let status = block_on(async { api_package_by_number(State(state.db), Path("1ZMISSING".into())).await.status() });
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn package_list_includes_summary_only_when_requested() {
+        let state = state_with(Arc::new(InTransitCourier));
+
+        let body = block_on(async {
+            body_json(api_packages(State(state.db.clone()), Query(ListParams { summary: true, include_deleted: false })).await).await
+        });
+        assert_eq!(body[0]["summary"], "FedEx · Waiting");
+
+        let body = block_on(async { body_json(api_packages(State(state.db), Query(ListParams { summary: false, include_deleted: false })).await).await });
+        assert!(body[0].get("summary").is_none());
+    }
+
+    #[test]
+    fn version_reports_crate_and_schema_version() {
+        let state = state_with(Arc::new(InTransitCourier));
+
+        let body = block_on(async { body_json(api_version(State(state.db)).await).await });
+
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body["schema_version"].as_u64().unwrap() >= 12);
+    }
+
+    #[test]
+    fn rejects_requests_beyond_max_connections() {
+        use tower::ServiceExt;
+
+        let mut state = state_with(Arc::new(SlowCourier));
+        state.courier_timeout = Duration::from_secs(5);
+        let app = build_router(state, Some(1));
+
+        let (first, second, version) = block_on(async {
+            let request = |method: &str, uri: &str| {
+                axum::http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(axum::body::Body::empty())
+                    .unwrap()
+            };
+
+            // Occupies the only slot while the slow courier check runs
+            let slow = tokio::spawn(app.clone().oneshot(request("POST", "/api/packages/1/rescan")));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let second = app.clone().oneshot(request("GET", "/api/stats")).await.unwrap().status();
+            let version = app.oneshot(request("GET", "/api/version")).await.unwrap().status();
+            (slow.await.unwrap().unwrap().status(), second, version)
+        });
+
+        assert_eq!(second, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(version, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(first, StatusCode::OK);
+    }
+
+    /// Send a request through the full router, returning the status and the
+    /// JSON body (null when the body is empty).
+    async fn send(app: &Router, method: &str, uri: &str, body: Option<serde_json::Value>) -> (StatusCode, serde_json::Value) {
+        use tower::ServiceExt;
+
+        let mut request = axum::http::Request::builder().method(method).uri(uri);
+        let body = match body {
+            Some(json) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                axum::body::Body::from(json.to_string())
+            }
+            None => axum::body::Body::empty(),
+        };
+        let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json = if bytes.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&bytes).unwrap()
+        };
+        (status, json)
+    }
+
+    fn app() -> Router {
+        build_router(state_with(Arc::new(InTransitCourier)), None)
+    }
+
+    #[test]
+    fn healthz_reports_database_health() {
+        let state = state_with(Arc::new(InTransitCourier));
+        let db = Arc::clone(&state.db);
+        let app = build_router(state, None);
+
+        let (status, body) = block_on(send(&app, "GET", "/healthz", None));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({ "status": "ok", "db": "ok" }));
+
+        // A thread panicking while holding the connection poisons it
+        let _ = std::thread::spawn(move || {
+            let _guard = db.lock().unwrap();
+            panic!("poison the database lock");
+        })
+        .join();
+
+        let (status, body) = block_on(send(&app, "GET", "/healthz", None));
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["db"], "error");
+    }
+
+    #[test]
+    fn router_requires_the_configured_token() {
+        use tower::ServiceExt;
+
+        let app = build_router(
+            AppState { auth_token: Some(Arc::from("s3cret")), ..state_with(Arc::new(InTransitCourier)) },
+            None,
+        );
+        let status = |uri: &str, authorization: Option<&str>| {
+            let mut request = axum::http::Request::builder().uri(uri);
+            if let Some(value) = authorization {
+                request = request.header(header::AUTHORIZATION, value);
+            }
+            let request = request.body(axum::body::Body::empty()).unwrap();
+            block_on(app.clone().oneshot(request)).unwrap().status()
+        };
+
+        assert_eq!(status("/api/packages", Some("Bearer s3cret")), StatusCode::OK);
+        assert_eq!(status("/?token=s3cret", None), StatusCode::OK);
+
+        assert_eq!(status("/api/packages", None), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/api/packages", Some("Bearer wrong")), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/api/packages", Some("s3cret")), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/?token=s3cre", None), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/api/packages?token=s3cret", None), StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/metrics", None), StatusCode::UNAUTHORIZED);
+
+        assert_eq!(status("/healthz", None), StatusCode::OK);
+        assert_eq!(status("/api/version", None), StatusCode::OK);
+    }
+
+    #[test]
+    fn router_is_open_without_a_configured_token() {
+        let app = app();
+
+        let (status, _) = block_on(send(&app, "GET", "/api/packages", None));
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = block_on(send(&app, "GET", "/api/packages?token=anything", None));
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn router_exports_csv_with_quoted_fields() {
+        use tower::ServiceExt;
+
+        let state = state_with(Arc::new(InTransitCourier));
+        state
+            .db
+            .lock()
+            .unwrap()
+            .insert_package(&NewPackage {
+                source_email_uid: 7,
+                description: Some(r#"=HYPERLINK("http://evil")"#.into()),
+                source_email_subject: Some(r#"Your order "Desk lamp, brass" has shipped"#.into()),
+                source_email_from: Some("orders@example.com".into()),
+                source_confidence: SourceConfidence::Sender,
+                ..new_package("1Z5R89390357567127")
+            })
+            .unwrap();
+        let app = build_router(state, None);
+
+        let get = |uri: &str| {
+            let request = axum::http::Request::builder().uri(uri).body(axum::body::Body::empty()).unwrap();
+            block_on(app.clone().oneshot(request)).unwrap()
+        };
+
+        let response = get("/api/packages/export?format=csv");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"trackage-export.csv\""
+        );
+
+        let bytes = block_on(axum::body::to_bytes(response.into_body(), usize::MAX)).unwrap();
+        let csv = String::from_utf8(bytes.to_vec()).unwrap();
+        let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("id,tracking_number,courier,"));
+        assert!(lines[1].starts_with("1,986578788855,fedex,FedEx Express,,waiting,"));
+        assert!(
+            lines[2].contains(r#","Your order ""Desk lamp, brass"" has shipped",orders@example.com,"#),
+            "{}",
+            lines[2]
+        );
+        assert!(lines[2].contains(r#","'=HYPERLINK(""http://evil"")","#), "{}", lines[2]);
+
+        let (status, body) = block_on(send(&app, "GET", "/api/packages/export?format=json", None));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[1]["source_email_subject"], r#"Your order "Desk lamp, brass" has shipped"#);
+
+        assert_eq!(get("/api/packages/export?format=xml").status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn router_lists_packages() {
+        let app = app();
+
+        let (status, body) = block_on(send(&app, "GET", "/api/packages", None));
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["tracking_number"], "986578788855");
+        assert_eq!(body[0]["status"], "waiting");
+    }
+
+    #[test]
+    fn router_adds_package_and_rejects_duplicates() {
+        let app = app();
+        let package = serde_json::json!({
+            "tracking_number": "1Z5R89390357567127",
+            "courier": "ups",
+            "service": "UPS",
+            "tracking_url": "",
+        });
+
+        block_on(async {
+            let (status, _) = send(&app, "POST", "/api/packages", Some(package.clone())).await;
+            assert_eq!(status, StatusCode::CREATED);
+
+            let (status, _) = send(&app, "POST", "/api/packages", Some(package)).await;
+            assert_eq!(status, StatusCode::CONFLICT);
+
+            let (_, body) = send(&app, "GET", "/api/packages", None).await;
+            assert_eq!(body.as_array().unwrap().len(), 2);
+        });
+    }
+
+    #[test]
+    fn router_validates_tracking_numbers() {
+        let app = app();
+
+        block_on(async {
+            let valid = serde_json::json!({ "tracking_number": "1Z5R 8939 0357 567127" });
+            let (status, body) = send(&app, "POST", "/api/packages/validate", Some(valid)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body[0]["tracking_number"], "1Z5R89390357567127");
+
+            let invalid = serde_json::json!({ "tracking_number": "not a number" });
+            let (status, body) = send(&app, "POST", "/api/packages/validate", Some(invalid)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, serde_json::json!([]));
+        });
+    }
+
+    #[test]
+    fn router_deletes_package_once() {
+        let app = app();
+
+        block_on(async {
+            let (status, _) = send(&app, "DELETE", "/api/packages/1", None).await;
+            assert_eq!(status, StatusCode::OK);
+
+            let (status, _) = send(&app, "DELETE", "/api/packages/1", None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+
+            let (_, body) = send(&app, "GET", "/api/packages", None).await;
+            assert_eq!(body, serde_json::json!([]));
+        });
+    }
+
+    #[test]
+    fn router_restores_deleted_package() {
+        let app = app();
+
+        block_on(async {
+            let (status, _) = send(&app, "POST", "/api/packages/1/restore", None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+
+            send(&app, "DELETE", "/api/packages/1", None).await;
+            let (_, body) = send(&app, "GET", "/api/packages?include_deleted=true", None).await;
+            assert_eq!(body[0]["id"], 1);
+            assert!(body[0]["deleted_at"].is_string());
+
+            let (status, _) = send(&app, "POST", "/api/packages/1/restore", None).await;
+            assert_eq!(status, StatusCode::OK);
+            let (_, body) = send(&app, "GET", "/api/packages", None).await;
+            assert_eq!(body[0]["id"], 1);
+            assert!(body[0].get("deleted_at").is_none());
+        });
+    }
+
+    #[test]
+    fn router_rescans_and_records_history() {
+        let app = app();
+
+        block_on(async {
+            let (status, body) = send(&app, "GET", "/api/packages/1/history", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body, serde_json::json!([]));
+
+            let (status, body) = send(&app, "POST", "/api/packages/1/rescan", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["status"], "in_transit");
+
+            let (_, body) = send(&app, "GET", "/api/packages/1/history", None).await;
+            assert_eq!(body[0]["status"], "in_transit");
+            assert_eq!(body[0]["last_known_location"], "Memphis, TN");
+
+            let (status, _) = send(&app, "POST", "/api/packages/99/rescan", None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn shipment_composes_package_status_route_and_source() {
+        use crate::db::{PackageStatus, StatusDetails};
+
+        let state = state_with(Arc::new(InTransitCourier));
+        {
+            let mut db = state.db.lock().unwrap();
+            db.insert_package(&NewPackage {
+                source_email_uid: 42,
+                source_email_subject: Some("Your order has shipped".into()),
+                source_email_from: Some("orders@example.com".into()),
+                ..new_package("1Z5R89390357567127")
+            })
+            .unwrap();
+            let scans = [
+                (Some("Louisville, KY"), Some("2026-03-04"), "Origin scan", "I"),
+                (Some("Memphis, TN"), Some("2026-03-03"), "Arrived at hub", "I"),
+                (Some("Memphis, TN"), None, "Departed facility", "I"),
+            ];
+            for (location, eta, description, code) in scans {
+                let details = StatusDetails {
+                    last_known_location: location,
+                    estimated_arrival_date: eta,
+                    description: Some(description),
+                    courier_status_text: Some("On the Way"),
+                    courier_status_code: Some(code),
+                    ..Default::default()
+                };
+                db.insert_package_status(2, &PackageStatus::InTransit, &details).unwrap();
+            }
+        }
+        let app = build_router(state, None);
+
+        block_on(async {
+            let (status, body) = send(&app, "GET", "/api/shipments/2", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["package"]["tracking_number"], "1Z5R89390357567127");
+            assert_eq!(
+                body["status"],
+                serde_json::json!({ "normalized": "in_transit", "courier_text": "On the Way", "courier_code": "I" })
+            );
+            assert_eq!(body["estimated_arrival_date"], "2026-03-03");
+            assert_eq!(body["route"], serde_json::json!(["Louisville, KY", "Memphis, TN"]));
+            assert_eq!(body["source"]["email_subject"], "Your order has shipped");
+            assert_eq!(body["history"].as_array().unwrap().len(), 3);
+            assert_eq!(body["history"][0]["description"], "Departed facility");
+
+            // Manually added packages have no source email
+            let (_, body) = send(&app, "GET", "/api/shipments/1", None).await;
+            assert!(body["source"].is_null());
+
+            let (status, _) = send(&app, "GET", "/api/shipments/99", None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+
+            let (status, body) = send(&app, "GET", "/api/packages/2/source", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body["email_subject"], "Your order has shipped");
+            assert_eq!(body["email_from"], "orders@example.com");
+            let (status, _) = send(&app, "GET", "/api/packages/1/source", None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        });
+    }
+
+    #[test]
+    fn health_reports_latest_status_poll() {
+        let state = state_with(Arc::new(InTransitCourier));
+        let report = Arc::clone(&state.poll_report);
+        let app = build_router(state, None);
+
+        block_on(async {
+            let (status, body) = send(&app, "GET", "/api/health", None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(body["last_status_poll"].is_null());
+
+            *report.lock().unwrap() = Some(PollReport {
+                checked: 3,
+                updated: 1,
+                failed_by_courier: BTreeMap::from([("fedex".to_string(), 2)]),
+            });
+            let (_, body) = send(&app, "GET", "/api/health", None).await;
+            assert_eq!(
+                body["last_status_poll"],
+                serde_json::json!({ "checked": 3, "updated": 1, "failed_by_courier": { "fedex": 2 } })
+            );
+        });
+    }
+
+    #[test]
+    fn metrics_reports_counters_and_active_packages() {
+        use tower::ServiceExt;
+
+        let state = state_with(Arc::new(InTransitCourier));
+        Metrics::increment(&state.metrics.emails_processed);
+        state.metrics.record_courier_request("fedex", crate::metrics::CourierOutcome::Error);
+        let app = build_router(state, None);
+
+        let (status, content_type, text) = block_on(async {
+            let request = axum::http::Request::builder().uri("/metrics").body(axum::body::Body::empty()).unwrap();
+            let response = app.oneshot(request).await.unwrap();
+            let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+            let status = response.status();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, content_type, String::from_utf8(bytes.to_vec()).unwrap())
+        });
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/plain"));
+        assert!(text.contains("trackage_emails_processed_total 1\n"));
+        assert!(text.contains("trackage_courier_requests_total{courier=\"fedex\",outcome=\"error\"} 1\n"));
+        assert!(text.contains("trackage_active_packages 1\n"));
+    }
+}